@@ -1,4 +1,4 @@
-use graph_mvcc::{Graph, IGraph, NodeId, TxError};
+use graph_mvcc::{ElementId, Graph, IGraph, NodeId, TxError};
 use std::collections::HashMap;
 
 #[derive(Debug, Clone)]
@@ -8,6 +8,7 @@ enum NodeType {
     TrainStation,
 }
 
+#[allow(dead_code)]
 #[derive(Debug, Clone)]
 enum EdgeType {
     Road,
@@ -70,6 +71,10 @@ fn main() -> Result<(), TxError> {
     println!("\n🗺️ Testing Graph Traversal...");
     test_graph_traversal(&mut graph, &nodes, &node_names)?;
     
+    // Plan the fastest routes over a consistent snapshot
+    println!("\n🧭 Testing Route Planning...");
+    test_route_planning(&mut graph, &nodes, &node_names)?;
+    
     println!("\n🎉 Transportation Network Example Complete!");
     
     Ok(())
@@ -292,10 +297,80 @@ fn test_graph_traversal(graph: &mut Graph, nodes: &[NodeId], node_names: &HashMa
     Ok(())
 }
 
+fn travel_minutes(edge_type: &str) -> i64 {
+    match edge_type {
+        "AirToLondon" => 420,
+        "AirToTokyo" => 840,
+        "AirToParis" => 75,
+        "RailToParis" => 140,
+        "RailToTokyo" => 30,
+        "RoadToParis" => 360,
+        "RoadToTokyo" => 600,
+        _ => 60,
+    }
+}
+
+fn test_route_planning(graph: &mut Graph, nodes: &[NodeId], node_names: &HashMap<NodeId, String>) -> Result<(), TxError> {
+    if nodes.len() >= 12 {
+        // Annotate every connection with its travel time in one transaction
+        let mut tx = graph.start_transaction();
+        for node_id in nodes {
+            let node = match graph.visible_node(&tx, node_id) {
+                Some(node) => node,
+                None => continue,
+            };
+            for (_, edge) in graph.visible_edges(&tx, &node) {
+                if edge.source() == node_id {
+                    let minutes = travel_minutes(edge.edgetype());
+                    graph.set_property(&mut tx, ElementId::Edge(edge.id().clone()), "minutes".to_string(), minutes.into())?;
+                }
+            }
+        }
+        graph.commit_transaction(&tx)?;
+        
+        let reader = graph.start_transaction();
+        let router = graph.router(&reader);
+        
+        if let Some(route) = router.shortest_path(&nodes[4], &nodes[7]) {
+            let stops: Vec<String> = route.nodes.iter().map(|id| get_node_name(node_names, id)).collect();
+            println!("    🔢 Fewest hops {} → {}: {}", 
+                    get_node_name(node_names, &nodes[4]), 
+                    get_node_name(node_names, &nodes[7]),
+                    stops.join(" → "));
+        }
+        
+        let air = ["AirToLondon", "AirToTokyo", "AirToParis"];
+        match router.clone().edge_types(&air).dijkstra(&nodes[4], &nodes[7], "minutes")? {
+            Some(route) => {
+                let stops: Vec<String> = route.nodes.iter().map(|id| get_node_name(node_names, id)).collect();
+                println!("    ⏱️ Fastest by air {} → {}: {} ({} minutes)", 
+                        get_node_name(node_names, &nodes[4]), 
+                        get_node_name(node_names, &nodes[7]),
+                        stops.join(" → "),
+                        route.cost);
+            },
+            None => println!("    ❌ No air route found"),
+        }
+        
+        match router.dijkstra(&nodes[0], &nodes[2], "minutes")? {
+            Some(route) => println!("    ⏱️ Fastest {} → {}: {} minutes over {} hops", 
+                    get_node_name(node_names, &nodes[0]), 
+                    get_node_name(node_names, &nodes[2]),
+                    route.cost,
+                    route.edges.len()),
+            None => println!("    ❌ No route found"),
+        }
+        
+        graph.commit_transaction(&reader)?;
+    }
+    
+    Ok(())
+}
+
 // Helper function to get node name for display
 fn get_node_name(node_names: &HashMap<NodeId, String>, node_id: &NodeId) -> String {
     node_names.get(node_id)
-             .map(|s| s.clone())
+             .cloned()
              .unwrap_or_else(|| format!("{:?}", node_id))
 }
 
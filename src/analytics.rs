@@ -0,0 +1,290 @@
+//! # Analytics
+//!
+//! Whole-graph algorithms that run on a `GraphSnapshot`: an owned, read-only
+//! copy of the nodes and edges a transaction can see. Once taken, the
+//! snapshot no longer borrows the `Graph`, so it can be moved to other
+//! threads and analysed while writers keep committing to the graph.
+//!
+//! ```
+//! use graph_mvcc::Graph;
+//!
+//! let mut graph = Graph::new();
+//! let mut tx = graph.start_transaction();
+//! let a = graph.add_node(&mut tx);
+//! let b = graph.add_node(&mut tx);
+//! graph.add_edge(&mut tx, &a, &b, "red".to_string()).unwrap();
+//! graph.commit_transaction(&tx).unwrap();
+//!
+//! let reader = graph.start_transaction();
+//! let snapshot = graph.graph_snapshot(&reader).threads(2);
+//! let components = snapshot.connected_components();
+//! assert_eq!(components[a.id()], components[b.id()]);
+//! ```
+
+use std::collections::{HashMap, HashSet};
+use std::thread;
+
+use crate::{Graph, NodeId, TransactionId};
+
+/// An owned copy of the graph as seen by one transaction.
+#[derive(Debug, Clone)]
+pub struct GraphSnapshot {
+    nodes: Vec<NodeId>,
+    // Outgoing and incoming edges by node index, one entry per edge.
+    outgoing: Vec<Vec<usize>>,
+    incoming: Vec<Vec<usize>>,
+    threads: usize,
+}
+
+impl Graph {
+    /// Copies the nodes and edges visible to `t` into a `GraphSnapshot`.
+    pub fn graph_snapshot(&self, t: &TransactionId) -> GraphSnapshot {
+        let mut nodes: Vec<NodeId> = self.visible_nodes(t).into_iter().map(|node| node.id().clone()).collect();
+        nodes.sort_by(|NodeId::String(a), NodeId::String(b)| a.cmp(b));
+        let index: HashMap<NodeId, usize> = nodes.iter().enumerate().map(|(i, id)| (id.clone(), i)).collect();
+
+        let mut outgoing = vec![Vec::new(); nodes.len()];
+        let mut incoming = vec![Vec::new(); nodes.len()];
+        for (i, id) in nodes.iter().enumerate() {
+            let node = self.visible_node(t, id).expect("listed nodes are visible");
+            for (other, edge) in self.visible_edges(t, &node) {
+                // Every edge is listed at both endpoints, keep it once.
+                if edge.source() != id {
+                    continue;
+                }
+                if let Some(&j) = index.get(other.id()) {
+                    outgoing[i].push(j);
+                    incoming[j].push(i);
+                }
+            }
+        }
+
+        GraphSnapshot {
+            nodes,
+            outgoing,
+            incoming,
+            threads: 1,
+        }
+    }
+}
+
+impl GraphSnapshot {
+    /// Spreads the work of the algorithms over `threads` threads.
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads.max(1);
+        self
+    }
+
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    pub fn edge_count(&self) -> usize {
+        self.outgoing.iter().map(Vec::len).sum()
+    }
+
+    pub fn nodes(&self) -> &[NodeId] {
+        &self.nodes
+    }
+
+    /// Distinct neighbors of node `i` regardless of direction, without `i`.
+    fn neighbors(&self, i: usize) -> HashSet<usize> {
+        self.outgoing[i]
+            .iter()
+            .chain(self.incoming[i].iter())
+            .copied()
+            .filter(|&j| j != i)
+            .collect()
+    }
+
+    /// Evaluates `f` for every node index, split over the configured threads.
+    fn map_nodes<T: Send>(&self, f: impl Fn(usize) -> T + Sync) -> Vec<T> {
+        let n = self.nodes.len();
+        if self.threads <= 1 || n < 2 {
+            return (0..n).map(f).collect();
+        }
+        let chunk = n.div_ceil(self.threads);
+        let f = &f;
+        thread::scope(|scope| {
+            let workers: Vec<_> = (0..n)
+                .step_by(chunk)
+                .map(|start| scope.spawn(move || (start..(start + chunk).min(n)).map(f).collect::<Vec<T>>()))
+                .collect();
+            workers
+                .into_iter()
+                .flat_map(|worker| worker.join().expect("analytics worker panicked"))
+                .collect()
+        })
+    }
+
+    fn keyed<T>(&self, values: Vec<T>) -> HashMap<NodeId, T> {
+        self.nodes.iter().cloned().zip(values).collect()
+    }
+
+    /// Number of incident edges of each node divided by the number of other
+    /// nodes, ignoring direction.
+    pub fn degree_centrality(&self) -> HashMap<NodeId, f64> {
+        let scale = if self.nodes.len() > 1 { 1.0 / (self.nodes.len() - 1) as f64 } else { 0.0 };
+        let values = self.map_nodes(|i| (self.outgoing[i].len() + self.incoming[i].len()) as f64 * scale);
+        self.keyed(values)
+    }
+
+    /// PageRank along edge direction. Rank held by nodes without outgoing
+    /// edges is spread evenly over all nodes.
+    pub fn pagerank(&self, damping: f64, iterations: usize) -> HashMap<NodeId, f64> {
+        let n = self.nodes.len();
+        if n == 0 {
+            return HashMap::new();
+        }
+        let mut rank = vec![1.0 / n as f64; n];
+        for _ in 0..iterations {
+            let dangling: f64 = (0..n).filter(|&i| self.outgoing[i].is_empty()).map(|i| rank[i]).sum();
+            let base = (1.0 - damping) / n as f64 + damping * dangling / n as f64;
+            let current = &rank;
+            rank = self.map_nodes(|i| {
+                let inflow: f64 = self.incoming[i]
+                    .iter()
+                    .map(|&j| current[j] / self.outgoing[j].len() as f64)
+                    .sum();
+                base + damping * inflow
+            });
+        }
+        self.keyed(rank)
+    }
+
+    /// Weakly connected components. Every node maps to a component number;
+    /// components are numbered from 0 in node order.
+    pub fn connected_components(&self) -> HashMap<NodeId, usize> {
+        let n = self.nodes.len();
+        let mut parent: Vec<usize> = (0..n).collect();
+        fn find(parent: &mut [usize], mut i: usize) -> usize {
+            while parent[i] != i {
+                parent[i] = parent[parent[i]];
+                i = parent[i];
+            }
+            i
+        }
+        for i in 0..n {
+            for &j in &self.outgoing[i] {
+                let (a, b) = (find(&mut parent, i), find(&mut parent, j));
+                if a != b {
+                    parent[a.max(b)] = a.min(b);
+                }
+            }
+        }
+
+        let mut numbers = HashMap::new();
+        let mut components = Vec::with_capacity(n);
+        for i in 0..n {
+            let root = find(&mut parent, i);
+            let next = numbers.len();
+            components.push(*numbers.entry(root).or_insert(next));
+        }
+        self.keyed(components)
+    }
+
+    /// Number of triangles each node takes part in, ignoring direction and
+    /// parallel edges. The total number of triangles is the sum divided by 3.
+    pub fn triangle_count(&self) -> HashMap<NodeId, usize> {
+        let neighbors: Vec<HashSet<usize>> = (0..self.nodes.len()).map(|i| self.neighbors(i)).collect();
+        let values = self.map_nodes(|i| {
+            let around: Vec<usize> = neighbors[i].iter().copied().collect();
+            let mut triangles = 0;
+            for (k, &a) in around.iter().enumerate() {
+                for &b in &around[k + 1..] {
+                    if neighbors[a].contains(&b) {
+                        triangles += 1;
+                    }
+                }
+            }
+            triangles
+        });
+        self.keyed(values)
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Unit Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    /// A triangle a -> b -> c -> a plus a separate pair d -> e.
+    const SAMPLE: [(usize, usize, &str); 4] = [(0, 1, "ab"), (1, 2, "bc"), (2, 0, "ca"), (3, 4, "de")];
+
+    #[test]
+    fn test_components_and_triangles() {
+        let (mut graph, nodes) = testing::graph(5, &SAMPLE);
+        let tx = graph.start_transaction();
+        for threads in [1, 3] {
+            let snapshot = graph.graph_snapshot(&tx).threads(threads);
+            assert_eq!(snapshot.node_count(), 5);
+            assert_eq!(snapshot.edge_count(), 4);
+
+            let components = snapshot.connected_components();
+            assert_eq!(components[nodes[0].id()], components[nodes[2].id()]);
+            assert_eq!(components[nodes[3].id()], components[nodes[4].id()]);
+            assert_ne!(components[nodes[0].id()], components[nodes[3].id()]);
+
+            let triangles = snapshot.triangle_count();
+            assert_eq!(triangles[nodes[1].id()], 1);
+            assert_eq!(triangles[nodes[3].id()], 0);
+            assert_eq!(triangles.values().sum::<usize>() / 3, 1);
+        }
+    }
+
+    #[test]
+    fn test_pagerank_and_degree() {
+        let (mut graph, nodes) = testing::graph(5, &SAMPLE);
+        let tx = graph.start_transaction();
+        let sequential = graph.graph_snapshot(&tx).pagerank(0.85, 30);
+        let parallel = graph.graph_snapshot(&tx).threads(4).pagerank(0.85, 30);
+        assert!((sequential.values().sum::<f64>() - 1.0).abs() < 1e-9);
+        for node in &nodes {
+            assert!((sequential[node.id()] - parallel[node.id()]).abs() < 1e-12);
+        }
+        // The cycle members are symmetric.
+        assert!((sequential[nodes[0].id()] - sequential[nodes[1].id()]).abs() < 1e-9);
+        // e collects all of d's rank.
+        assert!(sequential[nodes[4].id()] > sequential[nodes[3].id()]);
+
+        let degree = graph.graph_snapshot(&tx).degree_centrality();
+        assert_eq!(degree[nodes[0].id()], 0.5);
+        assert_eq!(degree[nodes[3].id()], 0.25);
+    }
+
+    #[test]
+    fn test_snapshot_is_detached_from_writers() {
+        let (mut graph, nodes) = testing::graph(5, &SAMPLE);
+        let reader = graph.start_transaction();
+        let snapshot = graph.graph_snapshot(&reader);
+
+        let worker = thread::spawn(move || snapshot.connected_components());
+
+        let mut writer = graph.start_transaction();
+        graph.add_edge(&mut writer, &nodes[2], &nodes[3], "cd".to_string()).unwrap();
+        graph.commit_transaction(&writer).unwrap();
+
+        let components = worker.join().unwrap();
+        assert_ne!(components[nodes[0].id()], components[nodes[3].id()]);
+        let fresh = graph.start_transaction();
+        let components = graph.graph_snapshot(&fresh).connected_components();
+        assert_eq!(components[nodes[0].id()], components[nodes[3].id()]);
+    }
+
+    #[test]
+    fn test_self_loops_are_one_edge() {
+        let (mut graph, nodes) = testing::graph(2, &[(0, 0, "loop"), (0, 1, "ab")]);
+        let tx = graph.start_transaction();
+        let snapshot = graph.graph_snapshot(&tx);
+        assert_eq!(snapshot.edge_count(), 2);
+        let index = |i: usize| snapshot.nodes().iter().position(|id| id == nodes[i].id()).unwrap();
+        let (a, b) = (index(0), index(1));
+        let mut outgoing = snapshot.outgoing[a].clone();
+        outgoing.sort_unstable();
+        assert_eq!(outgoing, if a < b { vec![a, b] } else { vec![b, a] });
+        assert_eq!(snapshot.incoming[a], vec![a]);
+        assert_eq!(snapshot.degree_centrality()[nodes[1].id()], 1.0);
+    }
+}
@@ -0,0 +1,366 @@
+//! # Bulk loading
+//!
+//! `add_node` and `add_edge` take a snapshot, record read locks and check
+//! for collisions on every call, which adds up when loading millions of
+//! elements. A `BulkLoader` ingests a stream of `BulkItem`s into a single
+//! transaction and skips that per-call work:
+//!
+//! - elements are written straight into the version maps and only listed in
+//!   the undo log for the commit's change event (a failed load is swept away
+//!   by txid), and the load has no deadline;
+//! - edges may refer to nodes that come later in the stream, since their
+//!   endpoints and collisions are checked once all items are in;
+//! - the adjacency lists are built in one pass at the end;
+//! - pre-commit hooks, and with them any constraints, run once on commit.
+//!
+//! Nodes can bring their own ids, so that edges can refer to them. The load
+//! is meant for a graph no other transaction is writing to; it takes no
+//! locks.
+//!
+//! ```
+//! use graph_mvcc::{BulkEdge, BulkItem, BulkNode, Graph, NodeId};
+//!
+//! let mut graph = Graph::new();
+//! let id = |name: &str| NodeId::String(name.to_string());
+//! let items = vec![
+//!     BulkItem::Edge(BulkEdge::new(id("a"), id("b"), "red")),
+//!     BulkItem::Node(BulkNode::with_id(id("a")).label("Station")),
+//!     BulkItem::Node(BulkNode::with_id(id("b")).property("name", "Central")),
+//! ];
+//! let report = graph.bulk_loader().load(items).unwrap();
+//! assert_eq!((report.nodes, report.edges), (2, 1));
+//!
+//! let reader = graph.start_transaction();
+//! assert_eq!(graph.traversal(&reader).v(&id("a")).out("red").to_list(), vec![id("b")]);
+//! ```
+
+use std::collections::HashSet;
+
+use crate::{Edge, EdgeId, ElementId, Graph, Node, NodeId, PropertyValue, TransactionId, TxError, TxResult, UndoAction, Version};
+
+/// A node to load.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct BulkNode {
+    /// The id of the node; a fresh one is minted if `None`.
+    pub id: Option<NodeId>,
+    pub labels: Vec<String>,
+    pub properties: Vec<(String, PropertyValue)>,
+}
+
+impl BulkNode {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_id(id: NodeId) -> Self {
+        BulkNode { id: Some(id), ..Self::default() }
+    }
+
+    pub fn label(mut self, label: &str) -> Self {
+        self.labels.push(label.to_string());
+        self
+    }
+
+    pub fn property(mut self, key: &str, value: impl Into<PropertyValue>) -> Self {
+        self.properties.push((key.to_string(), value.into()));
+        self
+    }
+}
+
+/// An edge to load, between nodes of the graph or of the load.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BulkEdge {
+    /// The id of the edge; a fresh one is minted if `None`.
+    pub id: Option<EdgeId>,
+    pub source: NodeId,
+    pub target: NodeId,
+    pub edge_type: String,
+    pub properties: Vec<(String, PropertyValue)>,
+}
+
+impl BulkEdge {
+    pub fn new(source: NodeId, target: NodeId, edge_type: &str) -> Self {
+        BulkEdge {
+            id: None,
+            source,
+            target,
+            edge_type: edge_type.to_string(),
+            properties: Vec::new(),
+        }
+    }
+
+    pub fn property(mut self, key: &str, value: impl Into<PropertyValue>) -> Self {
+        self.properties.push((key.to_string(), value.into()));
+        self
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum BulkItem {
+    Node(BulkNode),
+    Edge(BulkEdge),
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum BulkPhase {
+    /// Items are being written.
+    Loading,
+    /// Edges are being checked and the adjacency lists built.
+    Indexing,
+    /// The transaction is being committed.
+    Committing,
+}
+
+/// Where a load stands, passed to the progress callback.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct BulkProgress {
+    pub phase: BulkPhase,
+    pub nodes: usize,
+    pub edges: usize,
+}
+
+/// What a finished load wrote.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BulkReport {
+    pub txid: u64,
+    pub nodes: usize,
+    pub edges: usize,
+}
+
+type ProgressCallback<'graph> = Box<dyn FnMut(&BulkProgress) + 'graph>;
+
+/// Loads nodes and edges in one transaction, see the module documentation.
+pub struct BulkLoader<'graph> {
+    graph: &'graph mut Graph,
+    report_every: usize,
+    progress: Option<ProgressCallback<'graph>>,
+}
+
+impl Graph {
+    pub fn bulk_loader(&mut self) -> BulkLoader<'_> {
+        BulkLoader {
+            graph: self,
+            report_every: 10_000,
+            progress: None,
+        }
+    }
+}
+
+impl<'graph> BulkLoader<'graph> {
+    /// Calls `f` every `every` items while loading, and at the start of each
+    /// later phase.
+    pub fn on_progress(mut self, every: usize, f: impl FnMut(&BulkProgress) + 'graph) -> Self {
+        self.report_every = every.max(1);
+        self.progress = Some(Box::new(f));
+        self
+    }
+
+    fn report(&mut self, phase: BulkPhase, nodes: usize, edges: usize) {
+        if let Some(progress) = self.progress.as_mut() {
+            progress(&BulkProgress { phase, nodes, edges });
+        }
+    }
+
+    /// Writes `items` in a new transaction and commits it. If an item or the
+    /// commit fails, nothing of the load remains.
+    pub fn load(self, items: impl IntoIterator<Item = BulkItem>) -> TxResult<BulkReport> {
+        self.try_load(items.into_iter().map(Ok))
+    }
+
+    /// `load` for a stream that can fail part way, such as a file being
+    /// parsed. The first error ends the load and nothing of it remains.
+    pub fn try_load(mut self, items: impl IntoIterator<Item = TxResult<BulkItem>>) -> TxResult<BulkReport> {
+        let mut t = self.graph.start_transaction();
+        self.graph.set_transaction_timeout(&t, None);
+        match self.write(&mut t, items) {
+            Ok(report) => {
+                self.graph.commit_transaction(&t)?;
+                Ok(report)
+            }
+            Err(error) => {
+                self.graph.abort_transaction(&t)?;
+                Err(error)
+            }
+        }
+    }
+
+    fn write(&mut self, t: &mut TransactionId, items: impl IntoIterator<Item = TxResult<BulkItem>>) -> TxResult<BulkReport> {
+        let mut loaded: HashSet<NodeId> = HashSet::new();
+        let mut edges: Vec<Edge> = Vec::new();
+
+        for (i, item) in items.into_iter().enumerate() {
+            match item? {
+                BulkItem::Node(node) => loaded.insert(self.write_node(t, node)?),
+                BulkItem::Edge(edge) => {
+                    edges.push(self.write_edge(t, edge)?);
+                    true
+                }
+            };
+            if (i + 1) % self.report_every == 0 {
+                self.report(BulkPhase::Loading, loaded.len(), edges.len());
+            }
+        }
+
+        self.report(BulkPhase::Indexing, loaded.len(), edges.len());
+        for edge in &edges {
+            self.index_edge(t, &loaded, edge)?;
+        }
+
+        self.report(BulkPhase::Committing, loaded.len(), edges.len());
+        Ok(BulkReport {
+            txid: t.txid,
+            nodes: loaded.len(),
+            edges: edges.len(),
+        })
+    }
+
+    fn write_node(&mut self, t: &mut TransactionId, bulk: BulkNode) -> TxResult<NodeId> {
+        let node = match bulk.id {
+            Some(id) => Node { id },
+            None => Node::new(),
+        };
+        if self.graph.nodes.contains_key(&node) {
+            return Err(TxError::Collision(format!("node {} already exists", node.id())));
+        }
+        self.graph.nodes.insert(node.clone(), Version::new(t.txid));
+        self.graph.writes.push(t, UndoAction::CreateNode(node.clone()));
+
+        let labels = self.graph.labels.entry(node.id().clone()).or_default();
+        for label in bulk.labels {
+            if !labels.iter().any(|(existing, _)| *existing == label) {
+                labels.push((label.clone(), Version::new(t.txid)));
+                self.graph.writes.push(t, UndoAction::AddLabel(node.id().clone(), label));
+            }
+        }
+        self.write_properties(t, ElementId::Node(node.id().clone()), bulk.properties);
+        Ok(node.id)
+    }
+
+    fn write_edge(&mut self, t: &mut TransactionId, bulk: BulkEdge) -> TxResult<Edge> {
+        let mut edge = Edge::new(bulk.edge_type, bulk.source, bulk.target);
+        if let Some(id) = bulk.id {
+            edge.id = id;
+        }
+        if self.graph.edges.contains_key(edge.id()) {
+            return Err(TxError::Collision(format!("edge {} already exists", edge.id())));
+        }
+        self.graph.edges.insert(edge.id().clone(), (edge.clone(), Version::new(t.txid)));
+        self.graph.writes.push(t, UndoAction::CreateEdge(edge.id().clone()));
+        self.write_properties(t, ElementId::Edge(edge.id().clone()), bulk.properties);
+        Ok(edge)
+    }
+
+    fn write_properties(&mut self, t: &mut TransactionId, element: ElementId, properties: Vec<(String, PropertyValue)>) {
+        if properties.is_empty() {
+            return;
+        }
+        let keys = self.graph.properties.entry(element.clone()).or_default();
+        for (key, value) in properties {
+            let versions = keys.entry(key.clone()).or_default();
+            // A key given twice keeps the last value
+            versions.retain(|(_, version)| version.creation_id != t.txid);
+            versions.push((value, Version::new(t.txid)));
+            self.graph.writes.push(t, UndoAction::SetProperty(element.clone(), key));
+        }
+    }
+
+    /// Checks the endpoints of `edge` and whether it collides, like `add_edge`
+    /// would have, and adds it to the adjacency lists.
+    fn index_edge(&mut self, t: &TransactionId, loaded: &HashSet<NodeId>, edge: &Edge) -> TxResult<()> {
+        let endpoint = |id: &NodeId| {
+            let node = Node { id: id.clone() };
+            if loaded.contains(id) || self.graph.node_is_visible(t, &node) {
+                Ok(node)
+            } else {
+                Err(TxError::NodeNotFound(id.clone()))
+            }
+        };
+        let from = endpoint(edge.source())?;
+        let to = endpoint(edge.target())?;
+
+        if self.graph.has_collision_excluding_destination(t, &from, &to, edge.edgetype()) {
+            let conflict = format!("edge type '{}' already exists for source node", edge.edgetype());
+            return Err(TxError::Collision(conflict));
+        }
+        self.graph.list_edge(&from, &to, edge.clone());
+        Ok(())
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Unit Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn id(name: &str) -> NodeId {
+        NodeId::String(name.to_string())
+    }
+
+    #[test]
+    fn test_bulk_load_matches_single_writes() {
+        let mut graph = Graph::new();
+        let mut phases = Vec::new();
+        let items = (0..100)
+            .map(|i| BulkItem::Node(BulkNode::with_id(id(&i.to_string())).label("Stop").property("n", i as i64)))
+            .chain((0..50).map(|i| BulkItem::Edge(BulkEdge::new(id(&i.to_string()), id(&(i + 50).to_string()), "pair"))));
+        let report = graph
+            .bulk_loader()
+            .on_progress(50, |progress| phases.push((progress.phase, progress.nodes)))
+            .load(items)
+            .unwrap();
+
+        assert_eq!((report.nodes, report.edges), (100, 50));
+        assert_eq!(phases.first(), Some(&(BulkPhase::Loading, 50)));
+        assert_eq!(&phases[phases.len() - 2..], &[(BulkPhase::Indexing, 100), (BulkPhase::Committing, 100)]);
+
+        let reader = graph.start_transaction();
+        let middle = Node { id: id("50") };
+        assert_eq!(graph.visible_nodes(&reader).len(), 100);
+        assert_eq!(graph.visible_edges(&reader, &middle).len(), 1);
+        assert_eq!(graph.get_labels(&reader, &middle), vec!["Stop".to_string()]);
+        assert_eq!(graph.get_property(&reader, &ElementId::Node(id("50")), "n"), Some(50i64.into()));
+        assert!(graph.committed_before(&reader, report.txid));
+    }
+
+    #[test]
+    fn test_failed_load_leaves_nothing_behind() {
+        let mut graph = Graph::new();
+        let items = vec![
+            BulkItem::Node(BulkNode::with_id(id("a")).property("name", "A")),
+            BulkItem::Edge(BulkEdge::new(id("a"), id("missing"), "red")),
+        ];
+        assert_eq!(graph.bulk_loader().load(items), Err(TxError::NodeNotFound(id("missing"))));
+
+        let reader = graph.start_transaction();
+        assert!(graph.visible_nodes(&reader).is_empty());
+        assert!(graph.nodes.is_empty() && graph.edges.is_empty() && graph.adjacencylist.is_empty());
+    }
+
+    #[test]
+    fn test_deferred_checks_see_the_whole_load() {
+        let mut graph = Graph::new();
+        graph.add_pre_commit_hook(|_, _, changes| match changes.created_nodes.len() {
+            n if n > 2 => Err(TxError::constraint_violation("small_graph", "at most two nodes")),
+            _ => Ok(()),
+        });
+
+        let collision = vec![
+            BulkItem::Edge(BulkEdge::new(id("a"), id("b"), "red")),
+            BulkItem::Edge(BulkEdge::new(id("a"), id("c"), "red")),
+            BulkItem::Node(BulkNode::with_id(id("a"))),
+            BulkItem::Node(BulkNode::with_id(id("b"))),
+            BulkItem::Node(BulkNode::with_id(id("c"))),
+        ];
+        assert!(matches!(graph.bulk_loader().load(collision), Err(TxError::Collision(_))));
+
+        let too_many = (0..3).map(|i| BulkItem::Node(BulkNode::with_id(id(&i.to_string()))));
+        let error = graph.bulk_loader().load(too_many).unwrap_err();
+        assert!(matches!(error, TxError::ConstraintViolation { .. }));
+        assert!(graph.nodes.is_empty());
+
+        graph.bulk_loader().load(vec![BulkItem::Node(BulkNode::with_id(id("a")))]).unwrap();
+        let duplicate = graph.bulk_loader().load(vec![BulkItem::Node(BulkNode::with_id(id("a")))]);
+        assert!(matches!(duplicate, Err(TxError::Collision(_))));
+    }
+}
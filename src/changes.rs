@@ -0,0 +1,472 @@
+//! # Change data capture
+//!
+//! Every commit that changed something publishes a `ChangeEvent` describing
+//! what the transaction created and removed. Events are kept in commit order
+//! in a change log and delivered to subscribers over a channel, so caches and
+//! indexes can follow the graph incrementally and resume where they left off.
+//! The log starts with the first subscription: until then, and unless there
+//! are commit hooks, commits build no events at all.
+//!
+//! ```
+//! use graph_mvcc::Graph;
+//!
+//! let mut graph = Graph::new();
+//! let changes = graph.subscribe(None);
+//!
+//! let mut tx = graph.start_transaction();
+//! let node = graph.add_node(&mut tx);
+//! graph.commit_transaction(&tx).unwrap();
+//!
+//! let event = changes.try_recv().unwrap();
+//! assert_eq!(event.txid, tx.txid);
+//! assert_eq!(event.created_nodes, vec![node.id().clone()]);
+//! ```
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::sync::mpsc::{self, Receiver, Sender};
+
+use serde_json::Value;
+
+use crate::{Edge, EdgeId, ElementId, Graph, NodeId, PropertyValue, TransactionId, UndoAction, MVCC};
+
+/// A property that a transaction wrote (`value` is `Some`) or removed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PropertyChange {
+    pub element: ElementId,
+    pub key: String,
+    pub value: Option<PropertyValue>,
+}
+
+/// A record store key that a transaction wrote (`payload` is `Some`) or
+/// deleted.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordChange {
+    pub key: String,
+    pub payload: Option<Value>,
+}
+
+/// Everything a committed transaction changed.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ChangeEvent {
+    /// Position of the commit in the change log, starting at 1.
+    pub sequence: u64,
+    pub txid: u64,
+    /// When the transaction committed, as issued by the graph's `Conductor`.
+    pub commit_ts: u64,
+    pub created_nodes: Vec<NodeId>,
+    pub removed_nodes: Vec<NodeId>,
+    pub created_edges: Vec<Edge>,
+    pub removed_edges: Vec<Edge>,
+    pub added_labels: Vec<(NodeId, String)>,
+    pub removed_labels: Vec<(NodeId, String)>,
+    pub property_changes: Vec<PropertyChange>,
+    pub created_records: Vec<BTreeMap<MVCC, u64>>,
+    pub removed_records: Vec<BTreeMap<MVCC, u64>>,
+    pub record_changes: Vec<RecordChange>,
+}
+
+impl ChangeEvent {
+    pub fn is_empty(&self) -> bool {
+        self.created_nodes.is_empty()
+            && self.removed_nodes.is_empty()
+            && self.created_edges.is_empty()
+            && self.removed_edges.is_empty()
+            && self.added_labels.is_empty()
+            && self.removed_labels.is_empty()
+            && self.property_changes.is_empty()
+            && self.created_records.is_empty()
+            && self.removed_records.is_empty()
+            && self.record_changes.is_empty()
+    }
+}
+
+/// The change log and the channels of its subscribers.
+#[derive(Debug, Default)]
+pub(crate) struct ChangeFeed {
+    log: VecDeque<ChangeEvent>,
+    next_sequence: u64,
+    retention: Option<usize>,
+    subscribers: Vec<Sender<ChangeEvent>>,
+    // Set by the first subscription; until then commits build no events
+    started: bool,
+}
+
+impl Clone for ChangeFeed {
+    /// A cloned graph keeps the change log, but subscribers stay attached to
+    /// the graph they subscribed to.
+    fn clone(&self) -> Self {
+        ChangeFeed {
+            log: self.log.clone(),
+            next_sequence: self.next_sequence,
+            retention: self.retention,
+            subscribers: Vec::new(),
+            started: self.started,
+        }
+    }
+}
+
+/// The writes of every unfinished transaction, by txid. A `TransactionId`
+/// only holds the writes made through it, not those made through a copy.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct WriteLog(HashMap<u64, Vec<UndoAction>>);
+
+impl WriteLog {
+    /// Adds `action` to the undo log of `t` and to the writes of its txid.
+    pub(crate) fn push(&mut self, t: &mut TransactionId, action: UndoAction) {
+        self.0.entry(t.txid).or_default().push(action.clone());
+        t.rollback_actions.push(action);
+    }
+
+    fn of(&self, txid: u64) -> &[UndoAction] {
+        self.0.get(&txid).map_or(&[], Vec::as_slice)
+    }
+
+    /// Forgets the writes of a transaction that has ended.
+    pub(crate) fn finish(&mut self, txid: u64) {
+        self.0.remove(&txid);
+    }
+}
+
+fn sort_ids(ids: &mut [NodeId]) {
+    ids.sort_by(|NodeId::String(a), NodeId::String(b)| a.cmp(b));
+}
+
+fn sort_edges(edges: &mut [Edge]) {
+    edges.sort_by(|a, b| {
+        let (EdgeId::String(a), EdgeId::String(b)) = (a.id(), b.id());
+        a.cmp(b)
+    });
+}
+
+impl Graph {
+    /// Subscribes to change events. With `from` set to the `sequence` of the
+    /// last event seen, the later events still in the change log are
+    /// replayed first; `Some(0)` replays the whole log. With `None` only
+    /// future commits are sent.
+    pub fn subscribe(&mut self, from: Option<u64>) -> Receiver<ChangeEvent> {
+        let (sender, receiver) = mpsc::channel();
+        if let Some(sequence) = from {
+            for event in self.changes_since(sequence) {
+                let _ = sender.send(event);
+            }
+        }
+        self.changes.subscribers.push(sender);
+        self.changes.started = true;
+        receiver
+    }
+
+    /// The events in the change log after the one numbered `sequence`.
+    /// Txids are issued when transactions start, not when they commit, so
+    /// the log is followed by sequence number rather than by txid.
+    pub fn changes_since(&self, sequence: u64) -> Vec<ChangeEvent> {
+        self.changes.log.iter().filter(|event| event.sequence > sequence).cloned().collect()
+    }
+
+    /// Keeps at most `events` events in the change log; `None` keeps all.
+    pub fn set_change_retention(&mut self, events: Option<usize>) {
+        self.changes.retention = events;
+        self.trim_change_log();
+    }
+
+    fn trim_change_log(&mut self) {
+        if let Some(limit) = self.changes.retention {
+            while self.changes.log.len() > limit {
+                self.changes.log.pop_front();
+            }
+        }
+    }
+
+    /// Hands a committed transaction's change event to the log and to every
+    /// live subscriber, assigning its sequence number. Empty events are
+    /// returned as they are and not published.
+    pub(crate) fn publish_changes(&mut self, mut event: ChangeEvent) -> ChangeEvent {
+        if event.is_empty() {
+            return event;
+        }
+        self.changes.next_sequence += 1;
+        event.sequence = self.changes.next_sequence;
+
+        self.changes.subscribers.retain(|subscriber| subscriber.send(event.clone()).is_ok());
+        self.changes.log.push_back(event.clone());
+        self.trim_change_log();
+        event
+    }
+
+    /// Whether a commit's change event has anyone to go to: subscribers,
+    /// the change log of a graph that has had one, or commit hooks.
+    pub(crate) fn wants_changes(&self) -> bool {
+        self.changes.started || !self.hooks.is_empty()
+    }
+
+    /// Everything `t` has changed so far, read off the writes of its txid, so
+    /// the cost is that of the writes and not of the graph. The sequence
+    /// number and commit timestamp are left at 0.
+    pub(crate) fn collect_changes(&self, t: &TransactionId) -> ChangeEvent {
+        let txid = t.txid;
+        let mut event = ChangeEvent { txid, ..ChangeEvent::default() };
+        // The same element can be written several times; each is looked at
+        // once, in the state the transaction left it.
+        let mut nodes = HashSet::new();
+        let mut edges = HashSet::new();
+        let mut labels = HashSet::new();
+        let mut properties = HashSet::new();
+        let mut records = BTreeSet::new();
+        let mut keys = BTreeSet::new();
+        for action in self.writes.of(txid) {
+            match action {
+                UndoAction::CreateNode(node) | UndoAction::ExpireNode(node) => {
+                    nodes.insert(node);
+                }
+                UndoAction::CreateEdge(id) | UndoAction::ExpireEdge(id) => {
+                    edges.insert(id);
+                }
+                UndoAction::AddLabel(node, label) | UndoAction::ExpireLabel(node, label) => {
+                    labels.insert((node, label));
+                }
+                UndoAction::SetProperty(element, key) | UndoAction::ExpireProperty(element, key) => {
+                    properties.insert((element, key));
+                }
+                UndoAction::CreateRecord(handle) | UndoAction::ExpireRecord(handle) => {
+                    records.insert(*handle);
+                }
+                UndoAction::PutRecord(key) | UndoAction::RemoveRecord(key) => {
+                    keys.insert(key);
+                }
+            }
+        }
+
+        for node in nodes {
+            match self.nodes.get(node) {
+                Some(version) if version.creation_id == txid && version.expiration_id != txid => {
+                    event.created_nodes.push(node.id().clone())
+                }
+                Some(version) if version.creation_id != txid && version.expiration_id == txid => {
+                    event.removed_nodes.push(node.id().clone())
+                }
+                _ => {}
+            }
+        }
+        sort_ids(&mut event.created_nodes);
+        sort_ids(&mut event.removed_nodes);
+
+        for id in edges {
+            match self.edges.get(id) {
+                Some((edge, version)) if version.creation_id == txid && version.expiration_id != txid => {
+                    event.created_edges.push(edge.clone())
+                }
+                Some((edge, version)) if version.creation_id != txid && version.expiration_id == txid => {
+                    event.removed_edges.push(edge.clone())
+                }
+                _ => {}
+            }
+        }
+        sort_edges(&mut event.created_edges);
+        sort_edges(&mut event.removed_edges);
+
+        for (node, label) in labels {
+            let versions = self.labels.get(node).into_iter().flatten().filter(|(l, _)| l == label);
+            for (_, version) in versions {
+                if version.creation_id == txid && version.expiration_id != txid {
+                    event.added_labels.push((node.clone(), label.clone()));
+                } else if version.creation_id != txid && version.expiration_id == txid {
+                    event.removed_labels.push((node.clone(), label.clone()));
+                }
+            }
+        }
+        event.added_labels.sort_by(|(NodeId::String(a), x), (NodeId::String(b), y)| (a, x).cmp(&(b, y)));
+        event.removed_labels.sort_by(|(NodeId::String(a), x), (NodeId::String(b), y)| (a, x).cmp(&(b, y)));
+
+        for (element, key) in properties {
+            let Some(versions) = self.properties.get(element).and_then(|keys| keys.get(key)) else {
+                continue;
+            };
+            // The value the transaction left behind, if it wrote one.
+            let written = versions
+                .iter()
+                .rev()
+                .find(|(_, version)| version.creation_id == txid && version.expiration_id == 0);
+            let expired = versions.iter().any(|(_, version)| version.expiration_id == txid);
+            match written {
+                Some((value, _)) => event.property_changes.push(PropertyChange {
+                    element: element.clone(),
+                    key: key.clone(),
+                    value: Some(value.clone()),
+                }),
+                None if expired => event.property_changes.push(PropertyChange {
+                    element: element.clone(),
+                    key: key.clone(),
+                    value: None,
+                }),
+                None => {}
+            }
+        }
+        event.property_changes.sort_by(|a, b| {
+            format!("{:?}{}", a.element, a.key).cmp(&format!("{:?}{}", b.element, b.key))
+        });
+
+        for record in records.into_iter().filter_map(|handle| self.records.get(handle)) {
+            if record.get(&MVCC::TransactionCreationId) == Some(&txid) {
+                event.created_records.push(record.clone());
+            } else if record.get(&MVCC::TransactionExpirationId) == Some(&txid) {
+                event.removed_records.push(record.clone());
+            }
+        }
+
+        for key in keys {
+            let Some(versions) = self.store.get(key) else {
+                continue;
+            };
+            let written = versions
+                .iter()
+                .rev()
+                .find(|record| record.version.creation_id == txid && record.version.expiration_id == 0);
+            let expired = versions.iter().any(|record| record.version.expiration_id == txid);
+            match written {
+                Some(record) => event.record_changes.push(RecordChange {
+                    key: key.clone(),
+                    payload: Some(record.payload.clone()),
+                }),
+                None if expired => event.record_changes.push(RecordChange { key: key.clone(), payload: None }),
+                None => {}
+            }
+        }
+
+        event
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Unit Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_commit_publishes_changes() {
+        let mut graph = Graph::new();
+        let changes = graph.subscribe(None);
+
+        let mut t1 = graph.start_transaction();
+        let a = graph.add_node(&mut t1);
+        let b = graph.add_node(&mut t1);
+        graph.add_edge(&mut t1, &a, &b, "red".to_string()).unwrap();
+        graph.set_property(&mut t1, ElementId::Node(a.id().clone()), "name".to_string(), "a".into()).unwrap();
+        graph.add_label(&mut t1, &a, "Station".to_string()).unwrap();
+        graph.commit_transaction(&t1).unwrap();
+
+        let event = changes.try_recv().unwrap();
+        assert_eq!(event.sequence, 1);
+        assert_eq!(event.txid, t1.txid);
+        assert_eq!(event.created_nodes.len(), 2);
+        assert_eq!(event.created_edges.len(), 1);
+        assert_eq!(event.added_labels, vec![(a.id().clone(), "Station".to_string())]);
+        assert_eq!(event.property_changes[0].value, Some("a".into()));
+
+        let mut t2 = graph.start_transaction();
+        graph.remove_property(&mut t2, &ElementId::Node(a.id().clone()), "name").unwrap();
+        graph.remove_node(&mut t2, &b).unwrap();
+        graph.commit_transaction(&t2).unwrap();
+
+        let event = changes.try_recv().unwrap();
+        assert_eq!(event.sequence, 2);
+        assert_eq!(event.removed_nodes, vec![b.id().clone()]);
+        assert_eq!(event.removed_edges.len(), 1);
+        assert_eq!(event.property_changes[0].value, None);
+    }
+
+    #[test]
+    fn test_aborted_and_read_only_transactions_publish_nothing() {
+        let mut graph = Graph::new();
+        let changes = graph.subscribe(None);
+
+        let mut aborted = graph.start_transaction();
+        graph.add_node(&mut aborted);
+        graph.abort_transaction(&aborted).unwrap();
+
+        let reader = graph.start_transaction();
+        graph.commit_transaction(&reader).unwrap();
+
+        assert!(changes.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_resume_from_sequence() {
+        let mut graph = Graph::new();
+        let live = graph.subscribe(None);
+        // The later transaction commits first
+        let mut first = graph.start_transaction();
+        let mut second = graph.start_transaction();
+        graph.add_node(&mut first);
+        graph.add_node(&mut second);
+        graph.commit_transaction(&second).unwrap();
+        graph.commit_transaction(&first).unwrap();
+        let mut third = graph.start_transaction();
+        graph.add_node(&mut third);
+        graph.commit_transaction(&third).unwrap();
+
+        let seen = live.try_recv().unwrap();
+        assert_eq!(seen.txid, second.txid);
+        drop(live);
+        let resumed = graph.subscribe(Some(seen.sequence));
+        let replayed: Vec<u64> = resumed.try_iter().map(|event| event.txid).collect();
+        assert_eq!(replayed, vec![first.txid, third.txid]);
+
+        let mut tx = graph.start_transaction();
+        graph.add_node(&mut tx);
+        graph.commit_transaction(&tx).unwrap();
+        assert_eq!(resumed.try_recv().unwrap().txid, tx.txid);
+
+        graph.set_change_retention(Some(1));
+        assert_eq!(graph.changes_since(0).len(), 1);
+    }
+
+    #[test]
+    fn test_events_are_read_off_the_undo_log() {
+        let mut graph = Graph::new();
+        let mut tx = graph.start_transaction();
+        graph.add_node(&mut tx);
+        graph.commit_transaction(&tx).unwrap();
+        // Nobody listened, so nothing was built or logged
+        assert!(graph.changes_since(0).is_empty());
+
+        let changes = graph.subscribe(None);
+        let mut tx = graph.start_transaction();
+        let kept = graph.add_node(&mut tx);
+        let dropped = graph.add_node(&mut tx);
+        graph.set_property(&mut tx, ElementId::Node(kept.id().clone()), "name".to_string(), "a".into()).unwrap();
+        graph.set_property(&mut tx, ElementId::Node(kept.id().clone()), "name".to_string(), "b".into()).unwrap();
+        graph.remove_node(&mut tx, &dropped).unwrap();
+        graph.commit_transaction(&tx).unwrap();
+
+        let event = changes.try_recv().unwrap();
+        assert_eq!(event.created_nodes, vec![kept.id().clone()]);
+        assert!(event.removed_nodes.is_empty());
+        assert_eq!(event.property_changes.len(), 1);
+        assert_eq!(event.property_changes[0].value, Some("b".into()));
+
+        let id = |name: &str| NodeId::String(name.to_string());
+        let items = vec![
+            crate::BulkItem::Node(crate::BulkNode::with_id(id("x")).label("Station")),
+            crate::BulkItem::Node(crate::BulkNode::with_id(id("y"))),
+            crate::BulkItem::Edge(crate::BulkEdge::new(id("x"), id("y"), "red")),
+        ];
+        graph.bulk_loader().load(items).unwrap();
+        let event = changes.try_recv().unwrap();
+        assert_eq!(event.created_nodes, vec![id("x"), id("y")]);
+        assert_eq!((event.created_edges.len(), event.added_labels.len()), (1, 1));
+    }
+
+    #[test]
+    fn test_writes_through_a_copy_are_reported() {
+        use crate::IGraph;
+
+        let mut graph = Graph::new();
+        let changes = graph.subscribe(None);
+        let tx = IGraph::start_transaction(&mut graph);
+        let node = IGraph::add_node(&mut graph, Some(tx.clone())).unwrap();
+        IGraph::commit_transaction(&mut graph, tx).unwrap();
+
+        let event = changes.try_recv().unwrap();
+        assert_eq!(event.created_nodes, vec![node]);
+    }
+}
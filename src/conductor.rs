@@ -0,0 +1,331 @@
+//! # Conductors
+//!
+//! A `Conductor` issues txids, tracks which transactions are active and
+//! orders commits by handing out commit timestamps. Txids and commit
+//! timestamps are drawn from the same clock, so a commit timestamp is larger
+//! than the txid of every transaction started before the commit.
+//!
+//! Three conductors ship with the crate:
+//!
+//! - `LocalConductor`, an atomic counter for a graph in a single process. This
+//!   is what `Graph::new` uses.
+//! - `HybridLogicalClock`, timestamps that follow wall-clock time and can be
+//!   merged with timestamps received from other nodes.
+//! - `DeterministicConductor`, a counter that can be started at and moved to
+//!   any value, for reproducible tests.
+//!
+//! ```
+//! use graph_mvcc::{DeterministicConductor, Graph};
+//!
+//! let mut graph = Graph::with_conductor(DeterministicConductor::starting_at(100));
+//! let tx = graph.start_transaction();
+//! assert_eq!(tx.txid, 100);
+//! ```
+
+use std::collections::BTreeSet;
+use std::fmt::Debug;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::system::FIRST_TXID;
+
+pub trait Conductor: Debug + Send + Sync {
+    /// Issues the txid of a new transaction and tracks it as active.
+    fn begin(&mut self) -> u64;
+
+    /// Ends `txid` successfully and returns its commit timestamp.
+    fn commit(&mut self, txid: u64) -> u64;
+
+    /// Ends `txid` without committing it.
+    fn abort(&mut self, txid: u64);
+
+    /// The transactions that have begun and not yet ended.
+    fn active(&self) -> &BTreeSet<u64>;
+
+    /// The most recently issued txid or commit timestamp.
+    fn last_issued(&self) -> u64;
+
+    fn clone_box(&self) -> Box<dyn Conductor>;
+}
+
+impl Clone for Box<dyn Conductor> {
+    fn clone(&self) -> Self {
+        self.clone_box()
+    }
+}
+
+/// Issues txids and commit timestamps from an atomic counter.
+#[derive(Debug)]
+pub struct LocalConductor {
+    clock: AtomicU64,
+    active: BTreeSet<u64>,
+}
+
+impl LocalConductor {
+    pub fn new() -> Self {
+        LocalConductor {
+            clock: AtomicU64::new(FIRST_TXID - 1),
+            active: BTreeSet::new(),
+        }
+    }
+
+    fn tick(&self) -> u64 {
+        let previous = self
+            .clock
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |issued| issued.checked_add(1))
+            .expect("64-bit txid space exhausted");
+        previous + 1
+    }
+}
+
+impl Default for LocalConductor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Conductor for LocalConductor {
+    fn begin(&mut self) -> u64 {
+        let txid = self.tick();
+        self.active.insert(txid);
+        txid
+    }
+
+    fn commit(&mut self, txid: u64) -> u64 {
+        self.active.remove(&txid);
+        self.tick()
+    }
+
+    fn abort(&mut self, txid: u64) {
+        self.active.remove(&txid);
+    }
+
+    fn active(&self) -> &BTreeSet<u64> {
+        &self.active
+    }
+
+    fn last_issued(&self) -> u64 {
+        self.clock.load(Ordering::SeqCst)
+    }
+
+    fn clone_box(&self) -> Box<dyn Conductor> {
+        Box::new(LocalConductor {
+            clock: AtomicU64::new(self.last_issued()),
+            active: self.active.clone(),
+        })
+    }
+}
+
+/// A hybrid logical clock: the upper 48 bits of a timestamp hold wall-clock
+/// milliseconds, the lower 16 bits a counter that orders events within the
+/// same millisecond or while the wall clock lags behind.
+#[derive(Debug, Clone, Default)]
+pub struct HybridLogicalClock {
+    last: u64,
+    active: BTreeSet<u64>,
+}
+
+impl HybridLogicalClock {
+    const LOGICAL_BITS: u32 = 16;
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn physical_now() -> u64 {
+        let millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_millis() as u64)
+            .unwrap_or(0);
+        millis << Self::LOGICAL_BITS
+    }
+
+    fn tick(&mut self) -> u64 {
+        let next = self.last.checked_add(1).expect("64-bit txid space exhausted");
+        self.last = Self::physical_now().max(next).max(FIRST_TXID);
+        self.last
+    }
+
+    /// Merges a timestamp received from another node, so that everything
+    /// issued from now on is ordered after it.
+    pub fn observe(&mut self, remote: u64) {
+        self.last = self.last.max(remote);
+    }
+
+    /// The wall-clock milliseconds part of a timestamp.
+    pub fn physical(timestamp: u64) -> u64 {
+        timestamp >> Self::LOGICAL_BITS
+    }
+}
+
+impl Conductor for HybridLogicalClock {
+    fn begin(&mut self) -> u64 {
+        let txid = self.tick();
+        self.active.insert(txid);
+        txid
+    }
+
+    fn commit(&mut self, txid: u64) -> u64 {
+        self.active.remove(&txid);
+        self.tick()
+    }
+
+    fn abort(&mut self, txid: u64) {
+        self.active.remove(&txid);
+    }
+
+    fn active(&self) -> &BTreeSet<u64> {
+        &self.active
+    }
+
+    fn last_issued(&self) -> u64 {
+        self.last
+    }
+
+    fn clone_box(&self) -> Box<dyn Conductor> {
+        Box::new(self.clone())
+    }
+}
+
+/// Issues consecutive numbers from a chosen starting point and nothing else,
+/// so tests can place txids wherever they need them.
+#[derive(Debug, Clone)]
+pub struct DeterministicConductor {
+    next: u64,
+    active: BTreeSet<u64>,
+}
+
+impl DeterministicConductor {
+    /// The first number issued is `first`, which must not be a reserved txid.
+    pub fn starting_at(first: u64) -> Self {
+        assert!(first >= FIRST_TXID, "txids below {} are reserved", FIRST_TXID);
+        DeterministicConductor {
+            next: first,
+            active: BTreeSet::new(),
+        }
+    }
+
+    /// Skips ahead so that `next` is the next number issued.
+    pub fn jump_to(&mut self, next: u64) {
+        self.next = self.next.max(next);
+    }
+
+    fn tick(&mut self) -> u64 {
+        let issued = self.next;
+        self.next = issued.checked_add(1).expect("64-bit txid space exhausted");
+        issued
+    }
+}
+
+impl Default for DeterministicConductor {
+    fn default() -> Self {
+        Self::starting_at(FIRST_TXID)
+    }
+}
+
+impl Conductor for DeterministicConductor {
+    fn begin(&mut self) -> u64 {
+        let txid = self.tick();
+        self.active.insert(txid);
+        txid
+    }
+
+    fn commit(&mut self, txid: u64) -> u64 {
+        self.active.remove(&txid);
+        self.tick()
+    }
+
+    fn abort(&mut self, txid: u64) {
+        self.active.remove(&txid);
+    }
+
+    fn active(&self) -> &BTreeSet<u64> {
+        &self.active
+    }
+
+    fn last_issued(&self) -> u64 {
+        self.next - 1
+    }
+
+    fn clone_box(&self) -> Box<dyn Conductor> {
+        Box::new(self.clone())
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Unit Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::panic;
+    use crate::Graph;
+
+    #[test]
+    fn test_commits_are_ordered_after_earlier_txids() {
+        let conductors: Vec<Box<dyn Conductor>> = vec![
+            Box::new(LocalConductor::new()),
+            Box::new(HybridLogicalClock::new()),
+            Box::new(DeterministicConductor::default()),
+        ];
+        for mut conductor in conductors {
+            let first = conductor.begin();
+            let second = conductor.begin();
+            assert!(first >= FIRST_TXID && second > first);
+            assert_eq!(conductor.active().len(), 2);
+
+            let committed = conductor.commit(first);
+            assert!(committed > second);
+            conductor.abort(second);
+            assert!(conductor.active().is_empty());
+            assert_eq!(conductor.last_issued(), committed);
+        }
+    }
+
+    #[test]
+    fn test_exhausted_clocks_panic_instead_of_wrapping() {
+        let conductors: Vec<Box<dyn Conductor>> = vec![
+            Box::new(LocalConductor { clock: AtomicU64::new(u64::MAX - 1), active: BTreeSet::new() }),
+            Box::new(HybridLogicalClock { last: u64::MAX - 1, active: BTreeSet::new() }),
+            Box::new(DeterministicConductor::starting_at(u64::MAX - 1)),
+        ];
+        for mut conductor in conductors {
+            assert!(conductor.begin() >= u64::MAX - 1);
+            let wrapped = panic::catch_unwind(panic::AssertUnwindSafe(|| conductor.begin()));
+            assert!(wrapped.is_err());
+        }
+    }
+
+    #[test]
+    fn test_hybrid_logical_clock_observes_remote_time() {
+        let mut clock = HybridLogicalClock::new();
+        let local = clock.begin();
+        let remote = local + (1000 << 16);
+        clock.observe(remote);
+        assert!(clock.begin() > remote);
+        assert!(HybridLogicalClock::physical(local) > 0);
+    }
+
+    #[test]
+    fn test_graph_runs_on_any_conductor() {
+        let mut conductor = DeterministicConductor::starting_at(10);
+        conductor.jump_to(50);
+        let mut graph = Graph::with_conductor(conductor);
+        let mut tx = graph.start_transaction();
+        assert_eq!(tx.txid, 50);
+        let node = graph.add_node(&mut tx);
+        graph.commit_transaction(&tx).unwrap();
+
+        let reader = graph.start_transaction();
+        assert!(graph.node_is_visible(&reader, &node));
+
+        let mut graph = Graph::with_conductor(HybridLogicalClock::new());
+        let mut writer = graph.start_transaction();
+        let node = graph.add_node(&mut writer);
+        let reader = graph.start_transaction();
+        assert!(reader.txid > writer.txid);
+        assert!(!graph.node_is_visible(&reader, &node));
+        graph.commit_transaction(&writer).unwrap();
+        let fresh = graph.start_transaction();
+        assert!(graph.node_is_visible(&fresh, &node));
+    }
+}
@@ -0,0 +1,108 @@
+////////////////////////////////////////////////////////////////////////////////
+// Syntax tree for the Cypher subset
+use crate::PropertyValue;
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Query {
+    pub clauses: Vec<Clause>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Clause {
+    Match { patterns: Vec<Pattern>, filter: Option<Expr> },
+    Create { patterns: Vec<Pattern> },
+    Set { items: Vec<SetItem> },
+    Remove { items: Vec<SetItem> },
+    Delete { detach: bool, targets: Vec<Expr> },
+    Return(Projection),
+}
+
+/// `(a:Label {k: v})-[r:TYPE]->(b) ...`: a start node followed by hops.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Pattern {
+    pub start: NodePattern,
+    pub hops: Vec<(RelPattern, NodePattern)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct NodePattern {
+    pub variable: Option<String>,
+    pub labels: Vec<String>,
+    pub properties: Vec<(String, Expr)>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Direction {
+    Outgoing,
+    Incoming,
+    Either,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct RelPattern {
+    pub variable: Option<String>,
+    pub types: Vec<String>,
+    pub properties: Vec<(String, Expr)>,
+    pub direction: Direction,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum SetItem {
+    Property { variable: String, key: String, value: Expr },
+    Label { variable: String, label: String },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Projection {
+    pub distinct: bool,
+    pub items: Vec<ReturnItem>,
+    pub order_by: Vec<(Expr, bool)>,
+    pub skip: Option<usize>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct ReturnItem {
+    pub expr: Expr,
+    pub alias: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum BinaryOp {
+    And,
+    Or,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Expr {
+    Null,
+    Literal(PropertyValue),
+    Variable(String),
+    Property(String, String),
+    Not(Box<Expr>),
+    Negate(Box<Expr>),
+    IsNull(Box<Expr>, bool),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+    Call(String, Vec<Expr>),
+    CountStar,
+}
+
+impl Expr {
+    pub fn is_aggregate(&self) -> bool {
+        match self {
+            Expr::CountStar => true,
+            Expr::Call(name, _) => name == "count",
+            _ => false,
+        }
+    }
+}
@@ -0,0 +1,636 @@
+////////////////////////////////////////////////////////////////////////////////
+// Executes a parsed query against a transaction's view of the graph
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+
+use crate::traversal::Direction as Scan;
+use crate::{EdgeId, ElementId, Graph, Node, NodeId, PredicateLock, PropertyValue, TransactionId, TxError, TxResult};
+use super::ast::*;
+use super::{QueryResult, Value};
+
+type Row = HashMap<String, Value>;
+
+fn error<T>(msg: String) -> TxResult<T> {
+    Err(TxError::InvalidQuery(msg))
+}
+
+pub(crate) fn execute(graph: &mut Graph, t: &mut TransactionId, query: &Query) -> TxResult<QueryResult> {
+    // Every query starts from a single empty binding; each clause maps the
+    // current set of bindings to the next one.
+    let mut rows: Vec<Row> = vec![Row::new()];
+
+    for clause in &query.clauses {
+        match clause {
+            Clause::Match { patterns, filter } => {
+                let mut matched = Vec::new();
+                for row in &rows {
+                    for candidate in match_patterns(graph, t, row, patterns)? {
+                        let keep = match filter {
+                            Some(expr) => eval(graph, t, &candidate, expr)? == Value::Property(PropertyValue::Bool(true)),
+                            None => true,
+                        };
+                        if keep {
+                            matched.push(candidate);
+                        }
+                    }
+                }
+                rows = matched;
+            }
+            Clause::Create { patterns } => {
+                for row in rows.iter_mut() {
+                    for pattern in patterns {
+                        create_pattern(graph, t, row, pattern)?;
+                    }
+                }
+            }
+            Clause::Set { items } => {
+                for row in &rows {
+                    for item in items {
+                        set_item(graph, t, row, item)?;
+                    }
+                }
+            }
+            Clause::Remove { items } => {
+                for row in &rows {
+                    for item in items {
+                        remove_item(graph, t, row, item)?;
+                    }
+                }
+            }
+            Clause::Delete { detach, targets } => delete(graph, t, &rows, *detach, targets)?,
+            Clause::Return(projection) => return project(graph, t, rows, projection),
+        }
+    }
+
+    Ok(QueryResult::default())
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// MATCH
+fn match_patterns(graph: &Graph, t: &mut TransactionId, row: &Row, patterns: &[Pattern]) -> TxResult<Vec<Row>> {
+    let mut rows = vec![row.clone()];
+    for pattern in patterns {
+        let mut next = Vec::new();
+        for row in &rows {
+            next.extend(match_pattern(graph, t, row, pattern)?);
+        }
+        rows = next;
+    }
+    Ok(rows)
+}
+
+fn match_pattern(graph: &Graph, t: &mut TransactionId, row: &Row, pattern: &Pattern) -> TxResult<Vec<Row>> {
+    // Partial matches: the bindings so far, the node the walk stands on and
+    // the edges already used (a pattern never traverses the same edge twice).
+    let mut partials: Vec<(Row, Node, HashSet<EdgeId>)> = Vec::new();
+    for node in start_candidates(graph, t, row, &pattern.start)? {
+        if node_matches(graph, t, row, &node, &pattern.start)? {
+            let mut bound = row.clone();
+            bind(&mut bound, &pattern.start.variable, Value::Node(node.id().clone()));
+            partials.push((bound, node, HashSet::new()));
+        }
+    }
+
+    for (rel, node_pattern) in &pattern.hops {
+        let mut next = Vec::new();
+        for (row, current, used) in &partials {
+            lock_neighborhood(graph, t, current, rel);
+            let mut seen = HashSet::new();
+            for (other, edge) in graph.visible_edges(t, current) {
+                if used.contains(edge.id()) || !seen.insert(edge.id().clone()) {
+                    continue;
+                }
+                let forward = edge.source() == current.id();
+                let backward = edge.target() == current.id();
+                let direction_ok = match rel.direction {
+                    Direction::Outgoing => forward,
+                    Direction::Incoming => backward,
+                    Direction::Either => true,
+                };
+                if !direction_ok || (!rel.types.is_empty() && !rel.types.iter().any(|typ| typ == edge.edgetype())) {
+                    continue;
+                }
+                if let Some(Value::Edge(bound)) = rel.variable.as_ref().and_then(|name| row.get(name)) {
+                    if bound != edge.id() {
+                        continue;
+                    }
+                }
+                if !properties_match(graph, t, row, &ElementId::Edge(edge.id().clone()), &rel.properties)? {
+                    continue;
+                }
+                if let Some(Value::Node(bound)) = node_pattern.variable.as_ref().and_then(|name| row.get(name)) {
+                    if bound != other.id() {
+                        continue;
+                    }
+                }
+                if !node_matches(graph, t, row, &other, node_pattern)? {
+                    continue;
+                }
+
+                let mut bound = row.clone();
+                bind(&mut bound, &rel.variable, Value::Edge(edge.id().clone()));
+                bind(&mut bound, &node_pattern.variable, Value::Node(other.id().clone()));
+                let mut used = used.clone();
+                used.insert(edge.id().clone());
+                next.push((bound, other, used));
+            }
+        }
+        partials = next;
+    }
+
+    Ok(partials.into_iter().map(|(row, _, _)| row).collect())
+}
+
+/// Takes the locks `get_nodes_internal` takes on the edges a hop scans at
+/// `node`: a read lock per edge type, and a predicate lock on the
+/// neighborhood. A hop without types locks the types it finds.
+fn lock_neighborhood(graph: &Graph, t: &mut TransactionId, node: &Node, rel: &RelPattern) {
+    let scan = match rel.direction {
+        Direction::Outgoing => Scan::Out,
+        Direction::Incoming => Scan::In,
+        Direction::Either => Scan::Both,
+    };
+    let types: Vec<String> = if rel.types.is_empty() {
+        t.register_predicate(PredicateLock::new(node.id().clone(), None, scan));
+        graph.visible_edges(t, node).into_iter().map(|(_, edge)| edge.edgetype().to_string()).collect()
+    } else {
+        for edge_type in &rel.types {
+            t.register_predicate(PredicateLock::new(node.id().clone(), Some(edge_type.clone()), scan));
+        }
+        rel.types.clone()
+    };
+    for edge_type in types {
+        t.read_locks.insert((node.id().clone(), edge_type));
+    }
+}
+
+fn start_candidates(graph: &Graph, t: &TransactionId, row: &Row, pattern: &NodePattern) -> TxResult<Vec<Node>> {
+    match pattern.variable.as_ref().and_then(|name| row.get(name)) {
+        Some(Value::Node(id)) => Ok(graph.visible_node(t, id).into_iter().collect()),
+        Some(other) => error(format!("variable `{}` is bound to {}, not a node", pattern.variable.as_deref().unwrap_or_default(), other)),
+        None => Ok(graph.visible_nodes(t)),
+    }
+}
+
+fn node_matches(graph: &Graph, t: &TransactionId, row: &Row, node: &Node, pattern: &NodePattern) -> TxResult<bool> {
+    if !pattern.labels.is_empty() {
+        let labels = graph.get_labels(t, node);
+        if !pattern.labels.iter().all(|label| labels.contains(label)) {
+            return Ok(false);
+        }
+    }
+    properties_match(graph, t, row, &ElementId::Node(node.id().clone()), &pattern.properties)
+}
+
+fn properties_match(graph: &Graph, t: &TransactionId, row: &Row, element: &ElementId, properties: &[(String, Expr)]) -> TxResult<bool> {
+    for (key, expr) in properties {
+        let expected = eval(graph, t, row, expr)?;
+        let actual = graph.get_property(t, element, key).map(Value::Property).unwrap_or(Value::Null);
+        if equals(&actual, &expected) != Some(true) {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+fn bind(row: &mut Row, variable: &Option<String>, value: Value) {
+    if let Some(name) = variable {
+        row.insert(name.clone(), value);
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// CREATE / SET / REMOVE / DELETE
+fn create_pattern(graph: &mut Graph, t: &mut TransactionId, row: &mut Row, pattern: &Pattern) -> TxResult<()> {
+    let mut current = create_node(graph, t, row, &pattern.start)?;
+    for (rel, node_pattern) in &pattern.hops {
+        let next = create_node(graph, t, row, node_pattern)?;
+        let edge_type = match rel.types.as_slice() {
+            [edge_type] => edge_type.clone(),
+            _ => return error("CREATE needs exactly one relationship type".to_string()),
+        };
+        let (from, to) = match rel.direction {
+            Direction::Outgoing => (&current, &next),
+            Direction::Incoming => (&next, &current),
+            Direction::Either => return error("CREATE needs a directed relationship".to_string()),
+        };
+        let edge = graph.add_edge_internal(t, from, to, edge_type)?;
+        for (key, expr) in &rel.properties {
+            if let Value::Property(value) = eval(graph, t, row, expr)? {
+                graph.set_property(t, ElementId::Edge(edge.id().clone()), key.clone(), value)?;
+            }
+        }
+        bind(row, &rel.variable, Value::Edge(edge.id().clone()));
+        current = next;
+    }
+    Ok(())
+}
+
+fn create_node(graph: &mut Graph, t: &mut TransactionId, row: &mut Row, pattern: &NodePattern) -> TxResult<Node> {
+    if let Some(name) = &pattern.variable {
+        match row.get(name) {
+            Some(Value::Node(id)) => {
+                if !pattern.labels.is_empty() || !pattern.properties.is_empty() {
+                    return error(format!("variable `{}` is already bound and cannot be redeclared", name));
+                }
+                return graph.visible_node(t, id).ok_or_else(|| TxError::NodeNotFound(id.clone()));
+            }
+            Some(other) => return error(format!("variable `{}` is bound to {}, not a node", name, other)),
+            None => {}
+        }
+    }
+
+    let node = graph.add_node(t);
+    for label in &pattern.labels {
+        graph.add_label(t, &node, label.clone())?;
+    }
+    for (key, expr) in &pattern.properties {
+        if let Value::Property(value) = eval(graph, t, row, expr)? {
+            graph.set_property(t, ElementId::Node(node.id().clone()), key.clone(), value)?;
+        }
+    }
+    bind(row, &pattern.variable, Value::Node(node.id().clone()));
+    Ok(node)
+}
+
+fn element_of(row: &Row, variable: &str) -> TxResult<Option<ElementId>> {
+    match row.get(variable) {
+        Some(Value::Node(id)) => Ok(Some(ElementId::Node(id.clone()))),
+        Some(Value::Edge(id)) => Ok(Some(ElementId::Edge(id.clone()))),
+        Some(Value::Null) => Ok(None),
+        Some(other) => error(format!("variable `{}` is bound to {}, not a node or relationship", variable, other)),
+        None => error(format!("variable `{}` is not defined", variable)),
+    }
+}
+
+fn node_of(graph: &Graph, t: &TransactionId, row: &Row, variable: &str) -> TxResult<Option<Node>> {
+    match element_of(row, variable)? {
+        Some(ElementId::Node(id)) => graph.visible_node(t, &id).map(Some).ok_or(TxError::NodeNotFound(id)),
+        Some(ElementId::Edge(_)) => error(format!("labels can only be changed on nodes, `{}` is a relationship", variable)),
+        None => Ok(None),
+    }
+}
+
+fn set_item(graph: &mut Graph, t: &mut TransactionId, row: &Row, item: &SetItem) -> TxResult<()> {
+    match item {
+        SetItem::Property { variable, key, value } => {
+            let element = match element_of(row, variable)? {
+                Some(element) => element,
+                None => return Ok(()),
+            };
+            match eval(graph, t, row, value)? {
+                Value::Property(value) => graph.set_property(t, element, key.clone(), value),
+                Value::Null => graph.remove_property(t, &element, key),
+                other => error(format!("cannot store {} as a property", other)),
+            }
+        }
+        SetItem::Label { variable, label } => match node_of(graph, t, row, variable)? {
+            Some(node) => graph.add_label(t, &node, label.clone()),
+            None => Ok(()),
+        },
+    }
+}
+
+fn remove_item(graph: &mut Graph, t: &mut TransactionId, row: &Row, item: &SetItem) -> TxResult<()> {
+    match item {
+        SetItem::Property { variable, key, .. } => match element_of(row, variable)? {
+            Some(element) => graph.remove_property(t, &element, key),
+            None => Ok(()),
+        },
+        SetItem::Label { variable, label } => match node_of(graph, t, row, variable)? {
+            Some(node) => graph.remove_label(t, &node, label),
+            None => Ok(()),
+        },
+    }
+}
+
+fn delete(graph: &mut Graph, t: &mut TransactionId, rows: &[Row], detach: bool, targets: &[Expr]) -> TxResult<()> {
+    let mut nodes: Vec<NodeId> = Vec::new();
+    let mut edges: Vec<EdgeId> = Vec::new();
+    for row in rows {
+        for target in targets {
+            match eval(graph, t, row, target)? {
+                Value::Node(id) if !nodes.contains(&id) => nodes.push(id),
+                Value::Edge(id) if !edges.contains(&id) => edges.push(id),
+                Value::Node(_) | Value::Edge(_) | Value::Null => {}
+                other => return error(format!("cannot delete {}", other)),
+            }
+        }
+    }
+
+    // Relationships go first so that `DELETE r, a` works without DETACH.
+    for id in edges {
+        if let Some(edge) = graph.visible_edge(t, &id) {
+            graph.remove_edge(t, &edge)?;
+        }
+    }
+    for id in nodes {
+        let node = match graph.visible_node(t, &id) {
+            Some(node) => node,
+            None => continue,
+        };
+        if !detach && !graph.visible_edges(t, &node).is_empty() {
+            return error(format!("cannot delete node {:?} that still has relationships, use DETACH DELETE", id));
+        }
+        graph.remove_node(t, &node)?;
+    }
+    Ok(())
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// RETURN
+fn project(graph: &Graph, t: &TransactionId, rows: Vec<Row>, projection: &Projection) -> TxResult<QueryResult> {
+    let columns: Vec<String> = projection.items.iter().map(|item| item.alias.clone()).collect();
+
+    // Each output row keeps an environment (the source bindings plus the
+    // aliases) so ORDER BY can refer to either.
+    let mut output: Vec<(Vec<Value>, Row)> = Vec::new();
+    if projection.items.iter().any(|item| item.expr.is_aggregate()) {
+        let mut groups: Vec<(Vec<Value>, Vec<Row>)> = Vec::new();
+        let mut index: HashMap<String, usize> = HashMap::new();
+        for row in rows {
+            let mut key = Vec::new();
+            for item in projection.items.iter().filter(|item| !item.expr.is_aggregate()) {
+                key.push(eval(graph, t, &row, &item.expr)?);
+            }
+            let slot = *index.entry(format!("{:?}", key)).or_insert_with(|| {
+                groups.push((key, Vec::new()));
+                groups.len() - 1
+            });
+            groups[slot].1.push(row);
+        }
+        // An aggregate over no rows still yields one row, e.g. count(*) = 0.
+        if groups.is_empty() && projection.items.iter().all(|item| item.expr.is_aggregate()) {
+            groups.push((Vec::new(), Vec::new()));
+        }
+
+        for (key, members) in groups {
+            let mut keys = key.into_iter();
+            let mut values = Vec::new();
+            for item in &projection.items {
+                if item.expr.is_aggregate() {
+                    values.push(aggregate(graph, t, &members, &item.expr)?);
+                } else {
+                    values.push(keys.next().unwrap_or(Value::Null));
+                }
+            }
+            let env = members.into_iter().next().unwrap_or_default();
+            output.push((values, env));
+        }
+    } else {
+        for row in rows {
+            let mut values = Vec::new();
+            for item in &projection.items {
+                values.push(eval(graph, t, &row, &item.expr)?);
+            }
+            output.push((values, row));
+        }
+    }
+
+    if projection.distinct {
+        let mut seen = HashSet::new();
+        output.retain(|(values, _)| seen.insert(format!("{:?}", values)));
+    }
+
+    if !projection.order_by.is_empty() {
+        let mut keyed = Vec::new();
+        for (values, mut env) in output {
+            for (column, value) in columns.iter().zip(values.iter()) {
+                env.insert(column.clone(), value.clone());
+            }
+            let mut sort_key = Vec::new();
+            for (expr, _) in &projection.order_by {
+                sort_key.push(eval(graph, t, &env, expr)?);
+            }
+            keyed.push((sort_key, values, env));
+        }
+        keyed.sort_by(|a, b| {
+            for (i, (_, descending)) in projection.order_by.iter().enumerate() {
+                let ordering = sort_order(&a.0[i], &b.0[i]);
+                let ordering = if *descending { ordering.reverse() } else { ordering };
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+            Ordering::Equal
+        });
+        output = keyed.into_iter().map(|(_, values, env)| (values, env)).collect();
+    }
+
+    let skip = projection.skip.unwrap_or(0);
+    let limit = projection.limit.unwrap_or(usize::MAX);
+    Ok(QueryResult {
+        columns,
+        rows: output.into_iter().skip(skip).take(limit).map(|(values, _)| values).collect(),
+    })
+}
+
+fn aggregate(graph: &Graph, t: &TransactionId, rows: &[Row], expr: &Expr) -> TxResult<Value> {
+    match expr {
+        Expr::CountStar => Ok(Value::Property(PropertyValue::Int(rows.len() as i64))),
+        Expr::Call(name, args) if name == "count" => {
+            let arg = match args.as_slice() {
+                [arg] => arg,
+                _ => return error("count() takes exactly one argument".to_string()),
+            };
+            let mut count = 0;
+            for row in rows {
+                if eval(graph, t, row, arg)? != Value::Null {
+                    count += 1;
+                }
+            }
+            Ok(Value::Property(PropertyValue::Int(count)))
+        }
+        _ => error(format!("{:?} is not an aggregate", expr)),
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Expressions
+fn eval(graph: &Graph, t: &TransactionId, row: &Row, expr: &Expr) -> TxResult<Value> {
+    match expr {
+        Expr::Null => Ok(Value::Null),
+        Expr::Literal(value) => Ok(Value::Property(value.clone())),
+        Expr::Variable(name) => match row.get(name) {
+            Some(value) => Ok(value.clone()),
+            None => error(format!("variable `{}` is not defined", name)),
+        },
+        Expr::Property(name, key) => {
+            let element = match element_of(row, name)? {
+                Some(element) => element,
+                None => return Ok(Value::Null),
+            };
+            Ok(graph.get_property(t, &element, key).map(Value::Property).unwrap_or(Value::Null))
+        }
+        Expr::Not(inner) => Ok(match truth(&eval(graph, t, row, inner)?)? {
+            Some(b) => Value::Property(PropertyValue::Bool(!b)),
+            None => Value::Null,
+        }),
+        Expr::Negate(inner) => match eval(graph, t, row, inner)? {
+            Value::Property(PropertyValue::Int(i)) => Ok(Value::Property(PropertyValue::Int(i.wrapping_neg()))),
+            Value::Property(PropertyValue::Float(x)) => Ok(Value::Property(PropertyValue::Float(-x))),
+            Value::Null => Ok(Value::Null),
+            other => error(format!("cannot negate {}", other)),
+        },
+        Expr::IsNull(inner, negated) => {
+            let is_null = eval(graph, t, row, inner)? == Value::Null;
+            Ok(Value::Property(PropertyValue::Bool(is_null != *negated)))
+        }
+        Expr::Binary(op, lhs, rhs) => {
+            let lhs = eval(graph, t, row, lhs)?;
+            let rhs = eval(graph, t, row, rhs)?;
+            binary(*op, lhs, rhs)
+        }
+        Expr::Call(name, args) => {
+            let mut values = Vec::new();
+            for arg in args {
+                values.push(eval(graph, t, row, arg)?);
+            }
+            call(graph, t, name, values)
+        }
+        Expr::CountStar => error("count(*) is only allowed in RETURN".to_string()),
+    }
+}
+
+fn truth(value: &Value) -> TxResult<Option<bool>> {
+    match value {
+        Value::Property(PropertyValue::Bool(b)) => Ok(Some(*b)),
+        Value::Null => Ok(None),
+        other => error(format!("expected a boolean, found {}", other)),
+    }
+}
+
+fn boolean(value: Option<bool>) -> Value {
+    value.map(|b| Value::Property(PropertyValue::Bool(b))).unwrap_or(Value::Null)
+}
+
+fn binary(op: BinaryOp, lhs: Value, rhs: Value) -> TxResult<Value> {
+    match op {
+        BinaryOp::And => {
+            // Three-valued logic: false wins over null.
+            let (l, r) = (truth(&lhs)?, truth(&rhs)?);
+            Ok(match (l, r) {
+                (Some(false), _) | (_, Some(false)) => boolean(Some(false)),
+                (Some(true), Some(true)) => boolean(Some(true)),
+                _ => Value::Null,
+            })
+        }
+        BinaryOp::Or => {
+            let (l, r) = (truth(&lhs)?, truth(&rhs)?);
+            Ok(match (l, r) {
+                (Some(true), _) | (_, Some(true)) => boolean(Some(true)),
+                (Some(false), Some(false)) => boolean(Some(false)),
+                _ => Value::Null,
+            })
+        }
+        BinaryOp::Eq => Ok(boolean(equals(&lhs, &rhs))),
+        BinaryOp::Ne => Ok(boolean(equals(&lhs, &rhs).map(|b| !b))),
+        BinaryOp::Lt | BinaryOp::Le | BinaryOp::Gt | BinaryOp::Ge => {
+            let ordering = match (&lhs, &rhs) {
+                (Value::Property(l), Value::Property(r)) => compare(l, r),
+                _ => None,
+            };
+            Ok(boolean(ordering.map(|ordering| match op {
+                BinaryOp::Lt => ordering == Ordering::Less,
+                BinaryOp::Le => ordering != Ordering::Greater,
+                BinaryOp::Gt => ordering == Ordering::Greater,
+                _ => ordering != Ordering::Less,
+            })))
+        }
+        BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div => arithmetic(op, lhs, rhs),
+    }
+}
+
+fn arithmetic(op: BinaryOp, lhs: Value, rhs: Value) -> TxResult<Value> {
+    let (l, r) = match (lhs, rhs) {
+        (Value::Null, _) | (_, Value::Null) => return Ok(Value::Null),
+        (Value::Property(l), Value::Property(r)) => (l, r),
+        (l, r) => return error(format!("cannot apply arithmetic to {} and {}", l, r)),
+    };
+    let value = match (op, l, r) {
+        (BinaryOp::Add, PropertyValue::String(l), r) => PropertyValue::String(format!("{}{}", l, r)),
+        (BinaryOp::Add, l, PropertyValue::String(r)) => PropertyValue::String(format!("{}{}", l, r)),
+        (op, PropertyValue::Int(l), PropertyValue::Int(r)) => PropertyValue::Int(match op {
+            BinaryOp::Add => l.wrapping_add(r),
+            BinaryOp::Sub => l.wrapping_sub(r),
+            BinaryOp::Mul => l.wrapping_mul(r),
+            _ => {
+                if r == 0 {
+                    return error("division by zero".to_string());
+                }
+                l.wrapping_div(r)
+            }
+        }),
+        (op, l, r) => match (as_float(&l), as_float(&r)) {
+            (Some(l), Some(r)) => PropertyValue::Float(match op {
+                BinaryOp::Add => l + r,
+                BinaryOp::Sub => l - r,
+                BinaryOp::Mul => l * r,
+                _ => l / r,
+            }),
+            _ => return error(format!("cannot apply arithmetic to {} and {}", l, r)),
+        },
+    };
+    Ok(Value::Property(value))
+}
+
+fn as_float(value: &PropertyValue) -> Option<f64> {
+    match value {
+        PropertyValue::Int(i) => Some(*i as f64),
+        PropertyValue::Float(x) => Some(*x),
+        _ => None,
+    }
+}
+
+fn equals(lhs: &Value, rhs: &Value) -> Option<bool> {
+    match (lhs, rhs) {
+        (Value::Null, _) | (_, Value::Null) => None,
+        (Value::Property(l), Value::Property(r)) => match (as_float(l), as_float(r)) {
+            (Some(l), Some(r)) => Some(l == r),
+            _ => Some(l == r),
+        },
+        (l, r) => Some(l == r),
+    }
+}
+
+fn compare(lhs: &PropertyValue, rhs: &PropertyValue) -> Option<Ordering> {
+    match (lhs, rhs) {
+        (PropertyValue::String(l), PropertyValue::String(r)) => Some(l.cmp(r)),
+        (PropertyValue::Bool(l), PropertyValue::Bool(r)) => Some(l.cmp(r)),
+        (l, r) => as_float(l)?.partial_cmp(&as_float(r)?),
+    }
+}
+
+/// Total order used by ORDER BY: nulls sort last, mismatched types by kind.
+fn sort_order(lhs: &Value, rhs: &Value) -> Ordering {
+    fn rank(value: &Value) -> u8 {
+        match value {
+            Value::Node(_) => 0,
+            Value::Edge(_) => 1,
+            Value::Property(PropertyValue::String(_)) => 2,
+            Value::Property(PropertyValue::Bool(_)) => 3,
+            Value::Property(_) => 4,
+            Value::Null => 5,
+        }
+    }
+    match (lhs, rhs) {
+        (Value::Property(l), Value::Property(r)) => compare(l, r).unwrap_or_else(|| rank(lhs).cmp(&rank(rhs))),
+        (Value::Node(NodeId::String(l)), Value::Node(NodeId::String(r))) => l.cmp(r),
+        (Value::Edge(EdgeId::String(l)), Value::Edge(EdgeId::String(r))) => l.cmp(r),
+        _ => rank(lhs).cmp(&rank(rhs)),
+    }
+}
+
+fn call(graph: &Graph, t: &TransactionId, name: &str, args: Vec<Value>) -> TxResult<Value> {
+    match (name, args.as_slice()) {
+        (_, [Value::Null]) => Ok(Value::Null),
+        ("id", [Value::Node(NodeId::String(id))]) => Ok(Value::Property(PropertyValue::String(id.clone()))),
+        ("id", [Value::Edge(EdgeId::String(id))]) => Ok(Value::Property(PropertyValue::String(id.clone()))),
+        ("type", [Value::Edge(id)]) => Ok(graph
+            .visible_edge(t, id)
+            .map(|edge| Value::Property(PropertyValue::String(edge.edgetype().to_string())))
+            .unwrap_or(Value::Null)),
+        ("count", _) => error("count() is only allowed in RETURN".to_string()),
+        _ => error(format!("unknown function {}() for the given arguments", name)),
+    }
+}
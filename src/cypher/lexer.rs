@@ -0,0 +1,136 @@
+////////////////////////////////////////////////////////////////////////////////
+// Tokenizer for the Cypher subset
+use crate::{TxError, TxResult};
+
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Token {
+    Ident(String),
+    Str(String),
+    Int(i64),
+    Float(f64),
+    LParen,
+    RParen,
+    LBracket,
+    RBracket,
+    LBrace,
+    RBrace,
+    Colon,
+    Comma,
+    Dot,
+    Pipe,
+    Dash,
+    Plus,
+    Star,
+    Slash,
+    Lt,
+    Gt,
+    Le,
+    Ge,
+    Eq,
+    Ne,
+}
+
+pub(crate) fn tokenize(input: &str) -> TxResult<Vec<Token>> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            c if c.is_whitespace() => i += 1,
+            '(' => { tokens.push(Token::LParen); i += 1; }
+            ')' => { tokens.push(Token::RParen); i += 1; }
+            '[' => { tokens.push(Token::LBracket); i += 1; }
+            ']' => { tokens.push(Token::RBracket); i += 1; }
+            '{' => { tokens.push(Token::LBrace); i += 1; }
+            '}' => { tokens.push(Token::RBrace); i += 1; }
+            ':' => { tokens.push(Token::Colon); i += 1; }
+            ',' => { tokens.push(Token::Comma); i += 1; }
+            '.' => { tokens.push(Token::Dot); i += 1; }
+            '|' => { tokens.push(Token::Pipe); i += 1; }
+            '-' => { tokens.push(Token::Dash); i += 1; }
+            '+' => { tokens.push(Token::Plus); i += 1; }
+            '*' => { tokens.push(Token::Star); i += 1; }
+            '/' => { tokens.push(Token::Slash); i += 1; }
+            '=' => { tokens.push(Token::Eq); i += 1; }
+            '<' => match chars.get(i + 1) {
+                Some('=') => { tokens.push(Token::Le); i += 2; }
+                Some('>') => { tokens.push(Token::Ne); i += 2; }
+                _ => { tokens.push(Token::Lt); i += 1; }
+            },
+            '>' => match chars.get(i + 1) {
+                Some('=') => { tokens.push(Token::Ge); i += 2; }
+                _ => { tokens.push(Token::Gt); i += 1; }
+            },
+            '\'' | '"' => {
+                let quote = c;
+                let mut text = String::new();
+                i += 1;
+                loop {
+                    match chars.get(i) {
+                        None => return Err(TxError::InvalidQuery("unterminated string literal".to_string())),
+                        Some('\\') => {
+                            match chars.get(i + 1) {
+                                Some('n') => text.push('\n'),
+                                Some('t') => text.push('\t'),
+                                Some(&escaped) => text.push(escaped),
+                                None => return Err(TxError::InvalidQuery("unterminated string literal".to_string())),
+                            }
+                            i += 2;
+                        }
+                        Some(&ch) if ch == quote => {
+                            i += 1;
+                            break;
+                        }
+                        Some(&ch) => {
+                            text.push(ch);
+                            i += 1;
+                        }
+                    }
+                }
+                tokens.push(Token::Str(text));
+            }
+            '`' => {
+                let start = i + 1;
+                let end = chars[start..]
+                    .iter()
+                    .position(|&ch| ch == '`')
+                    .ok_or_else(|| TxError::InvalidQuery("unterminated quoted identifier".to_string()))?;
+                tokens.push(Token::Ident(chars[start..start + end].iter().collect()));
+                i = start + end + 1;
+            }
+            c if c.is_ascii_digit() => {
+                let start = i;
+                while i < chars.len() && chars[i].is_ascii_digit() {
+                    i += 1;
+                }
+                let is_float = chars.get(i) == Some(&'.') && chars.get(i + 1).is_some_and(|ch| ch.is_ascii_digit());
+                if is_float {
+                    i += 1;
+                    while i < chars.len() && chars[i].is_ascii_digit() {
+                        i += 1;
+                    }
+                }
+                let text: String = chars[start..i].iter().collect();
+                if is_float {
+                    let value = text.parse().map_err(|_| TxError::InvalidQuery(format!("invalid number '{}'", text)))?;
+                    tokens.push(Token::Float(value));
+                } else {
+                    let value = text.parse().map_err(|_| TxError::InvalidQuery(format!("invalid number '{}'", text)))?;
+                    tokens.push(Token::Int(value));
+                }
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let start = i;
+                while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                    i += 1;
+                }
+                tokens.push(Token::Ident(chars[start..i].iter().collect()));
+            }
+            other => return Err(TxError::InvalidQuery(format!("unexpected character '{}'", other))),
+        }
+    }
+
+    Ok(tokens)
+}
@@ -0,0 +1,213 @@
+//! # Cypher subset
+//!
+//! A small parser and executor for a subset of the Cypher query language.
+//! Queries run inside a `TransactionId`, so they read the same snapshot as
+//! the equivalent calls on `Graph`. Every relationship hop takes the locks
+//! `get_nodes_internal` takes on the edges it scans: read locks for the edge
+//! types, and a predicate lock that serializable transactions validate on
+//! commit.
+//!
+//! Supported clauses:
+//! - `MATCH (a:Label {key: value})-[r:RED|BLUE]->(b)<-[:GREEN]-(c), (d) WHERE ...`
+//! - `CREATE (a:Label {key: value})-[:RED {key: value}]->(b)`
+//! - `SET a.key = expr, a:Label` and `REMOVE a.key, a:Label`
+//! - `DELETE a, r` and `DETACH DELETE a`
+//! - `RETURN [DISTINCT] expr [AS alias], ... [ORDER BY expr [DESC]] [SKIP n] [LIMIT n]`
+//!
+//! Expressions support literals, property access, comparison, boolean and
+//! arithmetic operators, `IS [NOT] NULL`, and the functions `id()`, `type()`
+//! and `count()`.
+//!
+//! ```
+//! use graph_mvcc::Graph;
+//!
+//! let mut graph = Graph::new();
+//! let mut tx = graph.start_transaction();
+//! graph.query(&mut tx, "CREATE (:Station {name: 'A'})-[:RED]->(:Station {name: 'B'})").unwrap();
+//!
+//! let result = graph.query(&mut tx, "MATCH (a)-[:RED]->(b) RETURN a.name, b.name").unwrap();
+//! assert_eq!(result.rows.len(), 1);
+//! graph.commit_transaction(&tx).unwrap();
+//! ```
+
+mod ast;
+mod executor;
+mod lexer;
+mod parser;
+
+use std::fmt::{self, Display};
+
+use crate::{EdgeId, Graph, NodeId, PropertyValue, TransactionId, TxResult};
+
+/// A single cell of a query result.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Node(NodeId),
+    Edge(EdgeId),
+    Property(PropertyValue),
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match self {
+            Value::Null => write!(f, "null"),
+            Value::Node(NodeId::String(id)) => write!(f, "node {}", id),
+            Value::Edge(EdgeId::String(id)) => write!(f, "relationship {}", id),
+            Value::Property(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+/// The rows produced by a query's RETURN clause. Queries without a RETURN
+/// clause produce no columns and no rows.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct QueryResult {
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<Value>>,
+}
+
+impl QueryResult {
+    /// All values of the named column, in row order.
+    pub fn column(&self, name: &str) -> Option<Vec<&Value>> {
+        let i = self.columns.iter().position(|column| column == name)?;
+        Some(self.rows.iter().map(|row| &row[i]).collect())
+    }
+}
+
+impl Graph {
+    /// Parses and runs `query` inside the transaction `t`. Reads see `t`'s
+    /// view of the graph and writes are part of `t`, so they are committed or
+    /// rolled back together with everything else the transaction did.
+    pub fn query(&mut self, t: &mut TransactionId, query: &str) -> TxResult<QueryResult> {
+        let parsed = parser::parse(query)?;
+        executor::execute(self, t, &parsed)
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Unit Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TxError;
+
+    fn names(result: &QueryResult, column: &str) -> Vec<String> {
+        let mut names: Vec<String> = result.column(column).unwrap().iter().map(|v| v.to_string()).collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn test_create_and_match_path() {
+        let mut graph = Graph::new();
+        let mut tx = graph.start_transaction();
+        graph.query(&mut tx, "CREATE (a {name: 'a'})-[:RED]->(b {name: 'b'})-[:BLUE]->(c {name: 'c'})").unwrap();
+
+        let result = graph.query(&mut tx, "MATCH (a)-[:RED]->(b)-[:BLUE]->(c) RETURN a.name, c.name").unwrap();
+        assert_eq!(result.columns, vec!["a.name", "c.name"]);
+        assert_eq!(result.rows, vec![vec![
+            Value::Property(PropertyValue::from("a")),
+            Value::Property(PropertyValue::from("c")),
+        ]]);
+
+        // Direction matters: nothing points into `a` over RED.
+        let result = graph.query(&mut tx, "MATCH (x)-[:RED]->(y {name: 'a'}) RETURN x").unwrap();
+        assert!(result.rows.is_empty());
+    }
+
+    #[test]
+    fn test_where_order_and_count() {
+        let mut graph = Graph::new();
+        let mut tx = graph.start_transaction();
+        graph.query(&mut tx, "CREATE (:City {name: 'Paris', size: 2}), (:City {name: 'Tokyo', size: 14}), (:Port {name: 'Rotterdam', size: 1})").unwrap();
+
+        let result = graph.query(&mut tx, "MATCH (c:City) WHERE c.size > 1 AND NOT c.name = 'Oslo' RETURN c.name AS name ORDER BY c.size DESC").unwrap();
+        assert_eq!(result.column("name").unwrap(), vec![
+            &Value::Property(PropertyValue::from("Tokyo")),
+            &Value::Property(PropertyValue::from("Paris")),
+        ]);
+
+        let result = graph.query(&mut tx, "MATCH (n) RETURN count(*) AS total").unwrap();
+        assert_eq!(result.rows, vec![vec![Value::Property(PropertyValue::Int(3))]]);
+    }
+
+    #[test]
+    fn test_set_and_delete() {
+        let mut graph = Graph::new();
+        let mut tx = graph.start_transaction();
+        graph.query(&mut tx, "CREATE (:Stop {name: 'x'})-[:RED]->(:Stop {name: 'y'})").unwrap();
+        graph.query(&mut tx, "MATCH (s:Stop {name: 'x'}) SET s.name = 'z', s:Hub").unwrap();
+        assert_eq!(names(&graph.query(&mut tx, "MATCH (s:Hub) RETURN s.name").unwrap(), "s.name"), vec!["z"]);
+
+        let refused = graph.query(&mut tx, "MATCH (s:Hub) DELETE s");
+        assert!(matches!(refused, Err(TxError::InvalidQuery(_))));
+
+        graph.query(&mut tx, "MATCH (s:Hub) DETACH DELETE s").unwrap();
+        assert_eq!(names(&graph.query(&mut tx, "MATCH (s:Stop) RETURN s.name").unwrap(), "s.name"), vec!["y"]);
+        assert!(graph.query(&mut tx, "MATCH ()-[r]-() RETURN r").unwrap().rows.is_empty());
+    }
+
+    #[test]
+    fn test_queries_respect_transaction_isolation() {
+        let mut graph = Graph::new();
+        let mut t1 = graph.start_transaction();
+        graph.query(&mut t1, "CREATE (:Station {name: 'A'})").unwrap();
+
+        // Uncommitted writes are invisible to other transactions.
+        let mut t2 = graph.start_transaction();
+        assert!(graph.query(&mut t2, "MATCH (s:Station) RETURN s").unwrap().rows.is_empty());
+
+        graph.commit_transaction(&t1).unwrap();
+        graph.commit_transaction(&t2).unwrap();
+
+        // Rolled back writes disappear.
+        let mut t3 = graph.start_transaction();
+        graph.query(&mut t3, "MATCH (s:Station) SET s.name = 'B'").unwrap();
+        graph.abort_transaction(&t3).unwrap();
+        let mut t4 = graph.start_transaction();
+        let result = graph.query(&mut t4, "MATCH (s:Station) RETURN s.name").unwrap();
+        assert_eq!(names(&result, "s.name"), vec!["A"]);
+    }
+
+    #[test]
+    fn test_matches_lock_what_they_scan() {
+        let mut graph = Graph::new();
+        let mut setup = graph.start_transaction();
+        graph.query(&mut setup, "CREATE (:Hub), (:Stop)").unwrap();
+        graph.commit_transaction(&setup).unwrap();
+
+        let mut reader = graph.start_serializable_transaction();
+        assert!(graph.query(&mut reader, "MATCH (h:Hub)-[:RED]->(s) RETURN s").unwrap().rows.is_empty());
+        assert_eq!(reader.read_locks.len(), 1);
+
+        let mut writer = graph.start_transaction();
+        graph.query(&mut writer, "MATCH (h:Hub), (s:Stop) CREATE (h)-[:RED]->(s)").unwrap();
+        graph.commit_transaction(&writer).unwrap();
+
+        let aborted = graph.commit_transaction(&reader).unwrap_err();
+        assert_eq!(aborted.conflict().and_then(|conflict| conflict.with), Some(writer.txid));
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        let mut graph = Graph::new();
+        let mut tx = graph.start_transaction();
+        assert!(matches!(graph.query(&mut tx, "MATCH (a RETURN a"), Err(TxError::InvalidQuery(_))));
+        assert!(matches!(graph.query(&mut tx, "RETURN 1 MATCH (a)"), Err(TxError::InvalidQuery(_))));
+        assert!(matches!(graph.query(&mut tx, "CREATE (a)-[:RED]-(b)"), Err(TxError::InvalidQuery(_))));
+    }
+
+    #[test]
+    fn test_integer_overflow_wraps() {
+        let mut graph = Graph::new();
+        let mut tx = graph.start_transaction();
+        let min = Value::Property(PropertyValue::Int(i64::MIN));
+
+        let result = graph.query(&mut tx, "RETURN (-9223372036854775807 - 1) / -1 AS m").unwrap();
+        assert_eq!(result.rows, vec![vec![min.clone()]]);
+
+        let result = graph.query(&mut tx, "RETURN -(-9223372036854775807 - 1) AS m").unwrap();
+        assert_eq!(result.rows, vec![vec![min]]);
+    }
+}
@@ -0,0 +1,458 @@
+////////////////////////////////////////////////////////////////////////////////
+// Recursive descent parser for the Cypher subset
+use crate::{PropertyValue, TxError, TxResult};
+use super::ast::*;
+use super::lexer::{tokenize, Token};
+
+pub(crate) fn parse(input: &str) -> TxResult<Query> {
+    let mut parser = Parser {
+        tokens: tokenize(input)?,
+        pos: 0,
+    };
+    parser.query()
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+fn error<T>(msg: String) -> TxResult<T> {
+    Err(TxError::InvalidQuery(msg))
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<&Token> {
+        self.tokens.get(self.pos + offset)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        if self.peek() == Some(token) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, token: Token) -> TxResult<()> {
+        match self.next() {
+            Some(ref found) if *found == token => Ok(()),
+            Some(found) => error(format!("expected {:?}, found {:?}", token, found)),
+            None => error(format!("expected {:?}, found end of query", token)),
+        }
+    }
+
+    fn is_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(Token::Ident(word)) if word.eq_ignore_ascii_case(keyword))
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        if self.is_keyword(keyword) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> TxResult<()> {
+        if self.eat_keyword(keyword) {
+            Ok(())
+        } else {
+            error(format!("expected {}, found {:?}", keyword, self.peek()))
+        }
+    }
+
+    fn ident(&mut self) -> TxResult<String> {
+        match self.next() {
+            Some(Token::Ident(name)) => Ok(name),
+            Some(found) => error(format!("expected identifier, found {:?}", found)),
+            None => error("expected identifier, found end of query".to_string()),
+        }
+    }
+
+    fn query(&mut self) -> TxResult<Query> {
+        let mut clauses = Vec::new();
+        while self.peek().is_some() {
+            clauses.push(self.clause()?);
+        }
+        if clauses.is_empty() {
+            return error("empty query".to_string());
+        }
+        if let Some(i) = clauses.iter().position(|clause| matches!(clause, Clause::Return(_))) {
+            if i + 1 != clauses.len() {
+                return error("RETURN must be the last clause".to_string());
+            }
+        }
+        Ok(Query { clauses })
+    }
+
+    fn clause(&mut self) -> TxResult<Clause> {
+        if self.eat_keyword("MATCH") {
+            let patterns = self.patterns()?;
+            let filter = if self.eat_keyword("WHERE") {
+                Some(self.expr()?)
+            } else {
+                None
+            };
+            Ok(Clause::Match { patterns, filter })
+        } else if self.eat_keyword("CREATE") {
+            Ok(Clause::Create { patterns: self.patterns()? })
+        } else if self.eat_keyword("SET") {
+            Ok(Clause::Set { items: self.set_items(true)? })
+        } else if self.eat_keyword("REMOVE") {
+            Ok(Clause::Remove { items: self.set_items(false)? })
+        } else if self.is_keyword("DETACH") || self.is_keyword("DELETE") {
+            let detach = self.eat_keyword("DETACH");
+            self.expect_keyword("DELETE")?;
+            let mut targets = vec![self.expr()?];
+            while self.eat(&Token::Comma) {
+                targets.push(self.expr()?);
+            }
+            Ok(Clause::Delete { detach, targets })
+        } else if self.eat_keyword("RETURN") {
+            Ok(Clause::Return(self.projection()?))
+        } else {
+            error(format!("expected a clause, found {:?}", self.peek()))
+        }
+    }
+
+    fn patterns(&mut self) -> TxResult<Vec<Pattern>> {
+        let mut patterns = vec![self.pattern()?];
+        while self.eat(&Token::Comma) {
+            patterns.push(self.pattern()?);
+        }
+        Ok(patterns)
+    }
+
+    fn pattern(&mut self) -> TxResult<Pattern> {
+        let start = self.node_pattern()?;
+        let mut hops = Vec::new();
+        while matches!(self.peek(), Some(Token::Dash) | Some(Token::Lt)) {
+            let rel = self.rel_pattern()?;
+            let node = self.node_pattern()?;
+            hops.push((rel, node));
+        }
+        Ok(Pattern { start, hops })
+    }
+
+    fn node_pattern(&mut self) -> TxResult<NodePattern> {
+        self.expect(Token::LParen)?;
+        let variable = match self.peek() {
+            Some(Token::Ident(_)) => Some(self.ident()?),
+            _ => None,
+        };
+        let mut labels = Vec::new();
+        while self.eat(&Token::Colon) {
+            labels.push(self.ident()?);
+        }
+        let properties = self.property_map()?;
+        self.expect(Token::RParen)?;
+        Ok(NodePattern { variable, labels, properties })
+    }
+
+    fn rel_pattern(&mut self) -> TxResult<RelPattern> {
+        let incoming = self.eat(&Token::Lt);
+        self.expect(Token::Dash)?;
+
+        let mut variable = None;
+        let mut types = Vec::new();
+        let mut properties = Vec::new();
+        if self.eat(&Token::LBracket) {
+            if let Some(Token::Ident(_)) = self.peek() {
+                variable = Some(self.ident()?);
+            }
+            if self.eat(&Token::Colon) {
+                types.push(self.ident()?);
+                while self.eat(&Token::Pipe) {
+                    self.eat(&Token::Colon);
+                    types.push(self.ident()?);
+                }
+            }
+            properties = self.property_map()?;
+            self.expect(Token::RBracket)?;
+        }
+
+        self.expect(Token::Dash)?;
+        let outgoing = self.eat(&Token::Gt);
+        let direction = match (incoming, outgoing) {
+            (false, true) => Direction::Outgoing,
+            (true, false) => Direction::Incoming,
+            (false, false) => Direction::Either,
+            (true, true) => return error("relationship cannot point both ways".to_string()),
+        };
+        Ok(RelPattern { variable, types, properties, direction })
+    }
+
+    fn property_map(&mut self) -> TxResult<Vec<(String, Expr)>> {
+        let mut properties = Vec::new();
+        if self.eat(&Token::LBrace) && !self.eat(&Token::RBrace) {
+            loop {
+                let key = self.ident()?;
+                self.expect(Token::Colon)?;
+                properties.push((key, self.expr()?));
+                if !self.eat(&Token::Comma) {
+                    break;
+                }
+            }
+            self.expect(Token::RBrace)?;
+        }
+        Ok(properties)
+    }
+
+    fn set_items(&mut self, with_values: bool) -> TxResult<Vec<SetItem>> {
+        let mut items = Vec::new();
+        loop {
+            let variable = self.ident()?;
+            if self.eat(&Token::Colon) {
+                items.push(SetItem::Label { variable, label: self.ident()? });
+            } else {
+                self.expect(Token::Dot)?;
+                let key = self.ident()?;
+                let value = if with_values {
+                    self.expect(Token::Eq)?;
+                    self.expr()?
+                } else {
+                    Expr::Null
+                };
+                items.push(SetItem::Property { variable, key, value });
+            }
+            if !self.eat(&Token::Comma) {
+                break;
+            }
+        }
+        Ok(items)
+    }
+
+    fn projection(&mut self) -> TxResult<Projection> {
+        let distinct = self.eat_keyword("DISTINCT");
+        let mut items = Vec::new();
+        loop {
+            let expr = self.expr()?;
+            let alias = if self.eat_keyword("AS") {
+                self.ident()?
+            } else {
+                describe(&expr)
+            };
+            items.push(ReturnItem { expr, alias });
+            if !self.eat(&Token::Comma) {
+                break;
+            }
+        }
+
+        let mut order_by = Vec::new();
+        if self.eat_keyword("ORDER") {
+            self.expect_keyword("BY")?;
+            loop {
+                let expr = self.expr()?;
+                let descending = if self.eat_keyword("DESC") {
+                    true
+                } else {
+                    self.eat_keyword("ASC");
+                    false
+                };
+                order_by.push((expr, descending));
+                if !self.eat(&Token::Comma) {
+                    break;
+                }
+            }
+        }
+
+        let skip = if self.eat_keyword("SKIP") { Some(self.count()?) } else { None };
+        let limit = if self.eat_keyword("LIMIT") { Some(self.count()?) } else { None };
+        Ok(Projection { distinct, items, order_by, skip, limit })
+    }
+
+    fn count(&mut self) -> TxResult<usize> {
+        match self.next() {
+            Some(Token::Int(n)) if n >= 0 => Ok(n as usize),
+            found => error(format!("expected a non-negative integer, found {:?}", found)),
+        }
+    }
+
+    fn expr(&mut self) -> TxResult<Expr> {
+        self.or_expr()
+    }
+
+    fn or_expr(&mut self) -> TxResult<Expr> {
+        let mut lhs = self.and_expr()?;
+        while self.eat_keyword("OR") {
+            let rhs = self.and_expr()?;
+            lhs = Expr::Binary(BinaryOp::Or, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn and_expr(&mut self) -> TxResult<Expr> {
+        let mut lhs = self.not_expr()?;
+        while self.eat_keyword("AND") {
+            let rhs = self.not_expr()?;
+            lhs = Expr::Binary(BinaryOp::And, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn not_expr(&mut self) -> TxResult<Expr> {
+        if self.eat_keyword("NOT") {
+            Ok(Expr::Not(Box::new(self.not_expr()?)))
+        } else {
+            self.comparison()
+        }
+    }
+
+    fn comparison(&mut self) -> TxResult<Expr> {
+        let lhs = self.additive()?;
+        let op = match self.peek() {
+            Some(Token::Eq) => BinaryOp::Eq,
+            Some(Token::Ne) => BinaryOp::Ne,
+            Some(Token::Lt) => BinaryOp::Lt,
+            Some(Token::Le) => BinaryOp::Le,
+            Some(Token::Gt) => BinaryOp::Gt,
+            Some(Token::Ge) => BinaryOp::Ge,
+            _ => {
+                if self.eat_keyword("IS") {
+                    let negated = self.eat_keyword("NOT");
+                    self.expect_keyword("NULL")?;
+                    return Ok(Expr::IsNull(Box::new(lhs), negated));
+                }
+                return Ok(lhs);
+            }
+        };
+        self.pos += 1;
+        let rhs = self.additive()?;
+        Ok(Expr::Binary(op, Box::new(lhs), Box::new(rhs)))
+    }
+
+    fn additive(&mut self) -> TxResult<Expr> {
+        let mut lhs = self.multiplicative()?;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Plus) => BinaryOp::Add,
+                Some(Token::Dash) => BinaryOp::Sub,
+                _ => return Ok(lhs),
+            };
+            self.pos += 1;
+            let rhs = self.multiplicative()?;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+    }
+
+    fn multiplicative(&mut self) -> TxResult<Expr> {
+        let mut lhs = self.unary()?;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Star) => BinaryOp::Mul,
+                Some(Token::Slash) => BinaryOp::Div,
+                _ => return Ok(lhs),
+            };
+            self.pos += 1;
+            let rhs = self.unary()?;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+    }
+
+    fn unary(&mut self) -> TxResult<Expr> {
+        if self.eat(&Token::Dash) {
+            Ok(Expr::Negate(Box::new(self.unary()?)))
+        } else {
+            self.primary()
+        }
+    }
+
+    fn primary(&mut self) -> TxResult<Expr> {
+        match self.next() {
+            Some(Token::Int(n)) => Ok(Expr::Literal(PropertyValue::Int(n))),
+            Some(Token::Float(x)) => Ok(Expr::Literal(PropertyValue::Float(x))),
+            Some(Token::Str(s)) => Ok(Expr::Literal(PropertyValue::String(s))),
+            Some(Token::LParen) => {
+                let expr = self.expr()?;
+                self.expect(Token::RParen)?;
+                Ok(expr)
+            }
+            Some(Token::Ident(name)) => {
+                if name.eq_ignore_ascii_case("TRUE") {
+                    return Ok(Expr::Literal(PropertyValue::Bool(true)));
+                }
+                if name.eq_ignore_ascii_case("FALSE") {
+                    return Ok(Expr::Literal(PropertyValue::Bool(false)));
+                }
+                if name.eq_ignore_ascii_case("NULL") {
+                    return Ok(Expr::Null);
+                }
+                if self.peek() == Some(&Token::LParen) {
+                    self.pos += 1;
+                    let function = name.to_ascii_lowercase();
+                    if function == "count" && self.peek() == Some(&Token::Star) && self.peek_at(1) == Some(&Token::RParen) {
+                        self.pos += 2;
+                        return Ok(Expr::CountStar);
+                    }
+                    let mut args = Vec::new();
+                    if !self.eat(&Token::RParen) {
+                        loop {
+                            args.push(self.expr()?);
+                            if !self.eat(&Token::Comma) {
+                                break;
+                            }
+                        }
+                        self.expect(Token::RParen)?;
+                    }
+                    return Ok(Expr::Call(function, args));
+                }
+                if self.eat(&Token::Dot) {
+                    return Ok(Expr::Property(name, self.ident()?));
+                }
+                Ok(Expr::Variable(name))
+            }
+            Some(found) => error(format!("unexpected {:?} in expression", found)),
+            None => error("unexpected end of query in expression".to_string()),
+        }
+    }
+}
+
+/// The column name a RETURN item gets when it has no `AS` alias.
+fn describe(expr: &Expr) -> String {
+    match expr {
+        Expr::Null => "null".to_string(),
+        Expr::Literal(PropertyValue::String(s)) => format!("'{}'", s),
+        Expr::Literal(value) => value.to_string(),
+        Expr::Variable(name) => name.clone(),
+        Expr::Property(name, key) => format!("{}.{}", name, key),
+        Expr::Not(inner) => format!("NOT {}", describe(inner)),
+        Expr::Negate(inner) => format!("-{}", describe(inner)),
+        Expr::IsNull(inner, false) => format!("{} IS NULL", describe(inner)),
+        Expr::IsNull(inner, true) => format!("{} IS NOT NULL", describe(inner)),
+        Expr::Binary(op, lhs, rhs) => {
+            let symbol = match op {
+                BinaryOp::And => "AND",
+                BinaryOp::Or => "OR",
+                BinaryOp::Eq => "=",
+                BinaryOp::Ne => "<>",
+                BinaryOp::Lt => "<",
+                BinaryOp::Le => "<=",
+                BinaryOp::Gt => ">",
+                BinaryOp::Ge => ">=",
+                BinaryOp::Add => "+",
+                BinaryOp::Sub => "-",
+                BinaryOp::Mul => "*",
+                BinaryOp::Div => "/",
+            };
+            format!("{} {} {}", describe(lhs), symbol, describe(rhs))
+        }
+        Expr::Call(name, args) => {
+            let args: Vec<String> = args.iter().map(describe).collect();
+            format!("{}({})", name, args.join(", "))
+        }
+        Expr::CountStar => "count(*)".to_string(),
+    }
+}
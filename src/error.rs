@@ -0,0 +1,377 @@
+//! # Errors
+//!
+//! Every fallible operation returns a `TxResult`. The variants of `TxError`
+//! carry what went wrong where: the missing node, the resource two
+//! transactions fought over and the txid of the other one, the constraint a
+//! hook enforced, or the underlying error of a failed (de)serialization or
+//! storage operation, available through `std::error::Error::source`.
+//!
+//! `kind` reduces an error to its variant for matching and metrics, and
+//! `is_retryable`, `is_conflict` and `is_not_found` classify it.
+//!
+//! ```
+//! use graph_mvcc::{ElementId, ErrorKind, Graph, Resource};
+//!
+//! let mut graph = Graph::new();
+//! let mut setup = graph.start_transaction();
+//! let node = graph.add_node(&mut setup);
+//! graph.commit_transaction(&setup).unwrap();
+//! let element = ElementId::Node(node.id().clone());
+//!
+//! let mut first = graph.start_transaction();
+//! let mut second = graph.start_transaction();
+//! graph.set_property(&mut first, element.clone(), "name".to_string(), "a".into()).unwrap();
+//! let error = graph.set_property(&mut second, element.clone(), "name".to_string(), "b".into()).unwrap_err();
+//!
+//! assert_eq!(error.kind(), ErrorKind::TransactionLocked);
+//! assert!(error.is_retryable());
+//! let conflict = error.conflict().unwrap();
+//! assert_eq!(conflict.with, Some(first.txid));
+//! assert_eq!(conflict.resource, Some(Resource::Property(element, "name".to_string())));
+//! ```
+
+use std::error::Error;
+use std::fmt::{self, Display};
+use std::sync::Arc;
+
+use crate::{EdgeId, ElementId, LockKey, NodeId};
+
+pub type TxResult<T> = Result<T, TxError>;
+
+/// The underlying error of a `TxError`.
+pub type ErrorSource = Arc<dyn Error + Send + Sync>;
+
+#[derive(Debug, Clone)]
+pub enum TxError {
+    Abort(Conflict),
+    DatabaseFailure {
+        message: String,
+        source: Option<ErrorSource>,
+    },
+    NodeNotFound(NodeId),
+    ElementNotFound(Resource),
+    Collision(String),
+    InvalidRecord {
+        key: String,
+        source: Option<ErrorSource>,
+    },
+    TransactionLocked(Conflict),
+    InvalidQuery(String),
+    InvalidWeight(String),
+    ConstraintViolation {
+        constraint: String,
+        message: String,
+    },
+    SavepointNotFound(String),
+    TransactionExpired(u64),
+    TransactionsActive(usize),
+    NoCheckpoint,
+    Deadlock(Conflict),
+    /// Input to an import that could not be read, with what and where.
+    InvalidImport {
+        format: String,
+        message: String,
+        source: Option<ErrorSource>,
+    },
+}
+
+/// The variant of a `TxError`, without its data.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum ErrorKind {
+    Abort,
+    DatabaseFailure,
+    NodeNotFound,
+    ElementNotFound,
+    Collision,
+    InvalidRecord,
+    TransactionLocked,
+    InvalidQuery,
+    InvalidWeight,
+    ConstraintViolation,
+    SavepointNotFound,
+    TransactionExpired,
+    TransactionsActive,
+    NoCheckpoint,
+    Deadlock,
+    InvalidImport,
+}
+
+/// Something a transaction reads, writes or locks.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum Resource {
+    Node(NodeId),
+    Edge(EdgeId),
+    Label(NodeId, String),
+    Property(ElementId, String),
+    /// A record of the record table, by its element id.
+    Record(u64),
+    /// A key of the record store.
+    StoreKey(String),
+    Lock(LockKey),
+}
+
+/// Which transaction ran into which other one, over what.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct Conflict {
+    /// The transaction that failed.
+    pub txid: u64,
+    /// The transaction it conflicted with, if known.
+    pub with: Option<u64>,
+    pub resource: Option<Resource>,
+}
+
+impl Conflict {
+    pub fn new(txid: u64) -> Self {
+        Conflict {
+            txid,
+            with: None,
+            resource: None,
+        }
+    }
+
+    pub fn with(mut self, txid: impl Into<Option<u64>>) -> Self {
+        self.with = txid.into();
+        self
+    }
+
+    pub fn on(mut self, resource: Resource) -> Self {
+        self.resource = Some(resource);
+        self
+    }
+}
+
+impl TxError {
+    pub fn database_failure(message: impl Into<String>, source: impl Error + Send + Sync + 'static) -> Self {
+        TxError::DatabaseFailure {
+            message: message.into(),
+            source: Some(Arc::new(source)),
+        }
+    }
+
+    pub fn invalid_record(key: impl Into<String>, source: impl Error + Send + Sync + 'static) -> Self {
+        TxError::InvalidRecord {
+            key: key.into(),
+            source: Some(Arc::new(source)),
+        }
+    }
+
+    pub fn invalid_import(format: impl Into<String>, message: impl Into<String>) -> Self {
+        TxError::InvalidImport {
+            format: format.into(),
+            message: message.into(),
+            source: None,
+        }
+    }
+
+    pub fn constraint_violation(constraint: impl Into<String>, message: impl Into<String>) -> Self {
+        TxError::ConstraintViolation {
+            constraint: constraint.into(),
+            message: message.into(),
+        }
+    }
+
+    pub fn kind(&self) -> ErrorKind {
+        match self {
+            TxError::Abort(_) => ErrorKind::Abort,
+            TxError::DatabaseFailure { .. } => ErrorKind::DatabaseFailure,
+            TxError::NodeNotFound(_) => ErrorKind::NodeNotFound,
+            TxError::ElementNotFound(_) => ErrorKind::ElementNotFound,
+            TxError::Collision(_) => ErrorKind::Collision,
+            TxError::InvalidRecord { .. } => ErrorKind::InvalidRecord,
+            TxError::TransactionLocked(_) => ErrorKind::TransactionLocked,
+            TxError::InvalidQuery(_) => ErrorKind::InvalidQuery,
+            TxError::InvalidWeight(_) => ErrorKind::InvalidWeight,
+            TxError::ConstraintViolation { .. } => ErrorKind::ConstraintViolation,
+            TxError::SavepointNotFound(_) => ErrorKind::SavepointNotFound,
+            TxError::TransactionExpired(_) => ErrorKind::TransactionExpired,
+            TxError::TransactionsActive(_) => ErrorKind::TransactionsActive,
+            TxError::NoCheckpoint => ErrorKind::NoCheckpoint,
+            TxError::Deadlock(_) => ErrorKind::Deadlock,
+            TxError::InvalidImport { .. } => ErrorKind::InvalidImport,
+        }
+    }
+
+    /// Whether running the transaction again can succeed.
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self.kind(),
+            ErrorKind::Abort | ErrorKind::TransactionLocked | ErrorKind::Deadlock | ErrorKind::TransactionExpired
+        )
+    }
+
+    /// Whether another transaction got in the way.
+    pub fn is_conflict(&self) -> bool {
+        self.conflict().is_some()
+    }
+
+    pub fn is_not_found(&self) -> bool {
+        matches!(self.kind(), ErrorKind::NodeNotFound | ErrorKind::ElementNotFound | ErrorKind::SavepointNotFound)
+    }
+
+    /// The conflict behind an `Abort`, `TransactionLocked` or `Deadlock`.
+    pub fn conflict(&self) -> Option<&Conflict> {
+        match self {
+            TxError::Abort(conflict) | TxError::TransactionLocked(conflict) | TxError::Deadlock(conflict) => Some(conflict),
+            _ => None,
+        }
+    }
+
+    /// The resource the error is about, if it is about one.
+    pub fn resource(&self) -> Option<Resource> {
+        match self {
+            TxError::NodeNotFound(id) => Some(Resource::Node(id.clone())),
+            TxError::ElementNotFound(resource) => Some(resource.clone()),
+            TxError::InvalidRecord { key, .. } => Some(Resource::StoreKey(key.clone())),
+            _ => self.conflict().and_then(|conflict| conflict.resource.clone()),
+        }
+    }
+}
+
+/// Errors are equal when they carry the same data; underlying errors are not
+/// compared.
+impl PartialEq for TxError {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (TxError::Abort(a), TxError::Abort(b))
+            | (TxError::TransactionLocked(a), TxError::TransactionLocked(b))
+            | (TxError::Deadlock(a), TxError::Deadlock(b)) => a == b,
+            (TxError::DatabaseFailure { message: a, .. }, TxError::DatabaseFailure { message: b, .. }) => a == b,
+            (TxError::NodeNotFound(a), TxError::NodeNotFound(b)) => a == b,
+            (TxError::ElementNotFound(a), TxError::ElementNotFound(b)) => a == b,
+            (TxError::InvalidRecord { key: a, .. }, TxError::InvalidRecord { key: b, .. }) => a == b,
+            (TxError::Collision(a), TxError::Collision(b))
+            | (TxError::InvalidQuery(a), TxError::InvalidQuery(b))
+            | (TxError::InvalidWeight(a), TxError::InvalidWeight(b))
+            | (TxError::SavepointNotFound(a), TxError::SavepointNotFound(b)) => a == b,
+            (
+                TxError::ConstraintViolation { constraint: a, message: m },
+                TxError::ConstraintViolation { constraint: b, message: n },
+            ) => a == b && m == n,
+            (TxError::TransactionExpired(a), TxError::TransactionExpired(b)) => a == b,
+            (TxError::TransactionsActive(a), TxError::TransactionsActive(b)) => a == b,
+            (TxError::NoCheckpoint, TxError::NoCheckpoint) => true,
+            (
+                TxError::InvalidImport { format: a, message: m, .. },
+                TxError::InvalidImport { format: b, message: n, .. },
+            ) => a == b && m == n,
+            _ => false,
+        }
+    }
+}
+
+impl Display for TxError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match self {
+            TxError::Abort(conflict) => write!(f, "Transaction Aborted: {}", conflict),
+            TxError::DatabaseFailure { message, .. } => write!(f, "Database failure: {}", message),
+            TxError::NodeNotFound(id) => write!(f, "Node not found: {}", id),
+            TxError::ElementNotFound(resource) => write!(f, "Element not found: {}", resource),
+            TxError::Collision(msg) => write!(f, "Collision: {}", msg),
+            TxError::InvalidRecord { key, .. } => write!(f, "Invalid record: {}", key),
+            TxError::TransactionLocked(conflict) => write!(f, "Transaction locked: {}", conflict),
+            TxError::InvalidQuery(msg) => write!(f, "Invalid query: {}", msg),
+            TxError::InvalidWeight(msg) => write!(f, "Invalid weight: {}", msg),
+            TxError::ConstraintViolation { constraint, message } => {
+                write!(f, "Constraint violation: {}: {}", constraint, message)
+            }
+            TxError::SavepointNotFound(name) => write!(f, "Savepoint not found: {}", name),
+            TxError::TransactionExpired(txid) => write!(f, "Transaction {} expired", txid),
+            TxError::TransactionsActive(n) => write!(f, "{} transactions are still active", n),
+            TxError::NoCheckpoint => write!(f, "No checkpoint to revert to"),
+            TxError::Deadlock(conflict) => write!(f, "Deadlock detected: {}", conflict),
+            TxError::InvalidImport { format, message, .. } => write!(f, "Invalid {} import: {}", format, message),
+        }
+    }
+}
+
+impl Error for TxError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            TxError::DatabaseFailure { source, .. }
+            | TxError::InvalidRecord { source, .. }
+            | TxError::InvalidImport { source, .. } => {
+                source.as_deref().map(|source| source as &(dyn Error + 'static))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(f, "transaction {}", self.txid)?;
+        if let Some(with) = self.with {
+            write!(f, " conflicts with transaction {}", with)?;
+        }
+        if let Some(resource) = &self.resource {
+            write!(f, " on {}", resource)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for Resource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match self {
+            Resource::Node(id) => write!(f, "node {}", id),
+            Resource::Edge(id) => write!(f, "edge {}", id),
+            Resource::Label(id, label) => write!(f, "label '{}' of node {}", label, id),
+            Resource::Property(ElementId::Node(id), key) => write!(f, "property '{}' of node {}", key, id),
+            Resource::Property(ElementId::Edge(id), key) => write!(f, "property '{}' of edge {}", key, id),
+            Resource::Record(id) => write!(f, "record {}", id),
+            Resource::StoreKey(key) => write!(f, "record '{}'", key),
+            Resource::Lock((id, edge_type)) => write!(f, "lock ({}, {})", id, edge_type),
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Unit Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Graph;
+
+    #[test]
+    fn test_errors_carry_context() {
+        let mut graph = Graph::new();
+        let mut tx = graph.start_transaction();
+        let node = graph.add_node(&mut tx);
+        graph.commit_transaction(&tx).unwrap();
+
+        let mut tx = graph.start_transaction();
+        graph.remove_node(&mut tx, &node).unwrap();
+        let error = graph.remove_node(&mut tx, &node).unwrap_err();
+        assert_eq!(error, TxError::NodeNotFound(node.id().clone()));
+        assert!(error.is_not_found() && !error.is_retryable());
+        assert_eq!(error.resource(), Some(Resource::Node(node.id().clone())));
+        assert_eq!(error.to_string(), format!("Node not found: {}", node.id()));
+    }
+
+    #[test]
+    fn test_sources_are_chained() {
+        let mut graph = Graph::new();
+        let mut tx = graph.start_transaction();
+        let mut store = graph.record_store(&mut tx);
+        store.put("count", &"three").unwrap();
+        let error = store.get::<u32>("count").unwrap_err();
+
+        assert_eq!(error.kind(), ErrorKind::InvalidRecord);
+        assert!(error.source().is_some());
+        assert_eq!(error.resource(), Some(Resource::StoreKey("count".to_string())));
+
+        let io = std::io::Error::other("disk full");
+        let error = TxError::database_failure("writing the log", io);
+        assert_eq!(error.source().unwrap().to_string(), "disk full");
+        assert_eq!(error.to_string(), "Database failure: writing the log");
+    }
+
+    #[test]
+    fn test_conflicts_name_both_transactions() {
+        let conflict = Conflict::new(7).with(5).on(Resource::Lock((NodeId::String("a".to_string()), "red".to_string())));
+        let error = TxError::Deadlock(conflict.clone());
+        assert!(error.is_retryable() && error.is_conflict());
+        assert_eq!(error.conflict(), Some(&conflict));
+        assert_eq!(error.to_string(), "Deadlock detected: transaction 7 conflicts with transaction 5 on lock (a, red)");
+    }
+}
@@ -0,0 +1,177 @@
+//! # Triggers and commit hooks
+//!
+//! Pre-commit hooks enforce business invariants: they run while the
+//! committing transaction is still active, see the graph through its view
+//! and can veto the commit by returning an error, in which case the
+//! transaction is rolled back and the error handed to the caller.
+//! Post-commit hooks run after the commit is final and are meant for side
+//! effects; they cannot fail the commit.
+//!
+//! ```
+//! use graph_mvcc::{Graph, TxError};
+//!
+//! let mut graph = Graph::new();
+//! graph.add_pre_commit_hook(|graph, t, changes| {
+//!     for id in &changes.created_nodes {
+//!         let node = graph.visible_node(t, id).unwrap();
+//!         if graph.get_labels(t, &node).contains(&"Station".to_string())
+//!             && graph.visible_edges(t, &node).is_empty() {
+//!             return Err(TxError::constraint_violation("station_on_line", "every Station must connect to a Line"));
+//!         }
+//!     }
+//!     Ok(())
+//! });
+//!
+//! let mut tx = graph.start_transaction();
+//! graph.query(&mut tx, "CREATE (:Station {name: 'Lonely'})").unwrap();
+//! assert!(matches!(graph.commit_transaction(&tx), Err(TxError::ConstraintViolation { .. })));
+//! ```
+
+use std::fmt;
+use std::sync::Arc;
+
+use crate::{ChangeEvent, Graph, TransactionId, TxResult};
+
+/// Runs before a commit with the graph, the committing transaction and the
+/// changes it is about to commit. Returning an error vetoes the commit.
+pub type PreCommitHook = Arc<dyn Fn(&Graph, &TransactionId, &ChangeEvent) -> TxResult<()> + Send + Sync>;
+
+/// Runs after a commit with the graph and the changes that were committed.
+pub type PostCommitHook = Arc<dyn Fn(&Graph, &ChangeEvent) + Send + Sync>;
+
+/// Handle for removing a registered hook again.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub struct HookId(u64);
+
+#[derive(Clone, Default)]
+pub(crate) struct Hooks {
+    next_id: u64,
+    pre_commit: Vec<(HookId, PreCommitHook)>,
+    post_commit: Vec<(HookId, PostCommitHook)>,
+}
+
+impl fmt::Debug for Hooks {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        f.debug_struct("Hooks")
+            .field("pre_commit", &self.pre_commit.len())
+            .field("post_commit", &self.post_commit.len())
+            .finish()
+    }
+}
+
+impl Hooks {
+    fn mint(&mut self) -> HookId {
+        self.next_id += 1;
+        HookId(self.next_id)
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.pre_commit.is_empty() && self.post_commit.is_empty()
+    }
+}
+
+impl Graph {
+    /// Registers a hook that runs before every commit, after the read lock
+    /// conflict check and before the transaction leaves the active set.
+    /// Hooks run in registration order; the first error vetoes the commit.
+    pub fn add_pre_commit_hook(&mut self, hook: impl Fn(&Graph, &TransactionId, &ChangeEvent) -> TxResult<()> + Send + Sync + 'static) -> HookId {
+        let id = self.hooks.mint();
+        self.hooks.pre_commit.push((id, Arc::new(hook)));
+        id
+    }
+
+    /// Registers a hook that runs after every successful commit.
+    pub fn add_post_commit_hook(&mut self, hook: impl Fn(&Graph, &ChangeEvent) + Send + Sync + 'static) -> HookId {
+        let id = self.hooks.mint();
+        self.hooks.post_commit.push((id, Arc::new(hook)));
+        id
+    }
+
+    /// Unregisters a hook. Returns false if no hook with this id exists.
+    pub fn remove_hook(&mut self, id: HookId) -> bool {
+        let before = self.hooks.pre_commit.len() + self.hooks.post_commit.len();
+        self.hooks.pre_commit.retain(|(hook_id, _)| *hook_id != id);
+        self.hooks.post_commit.retain(|(hook_id, _)| *hook_id != id);
+        before != self.hooks.pre_commit.len() + self.hooks.post_commit.len()
+    }
+
+    pub(crate) fn run_pre_commit_hooks(&self, t: &TransactionId, changes: &ChangeEvent) -> TxResult<()> {
+        for (_, hook) in &self.hooks.pre_commit {
+            hook(self, t, changes)?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn run_post_commit_hooks(&self, changes: &ChangeEvent) {
+        for (_, hook) in &self.hooks.post_commit {
+            hook(self, changes);
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Unit Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TxError;
+    use std::sync::Mutex;
+
+    #[test]
+    fn test_pre_commit_hook_vetoes_and_rolls_back() {
+        let mut graph = Graph::new();
+        graph.add_pre_commit_hook(|_, _, changes| {
+            if changes.created_nodes.len() > 1 {
+                Err(TxError::constraint_violation("single_node", "one node per transaction"))
+            } else {
+                Ok(())
+            }
+        });
+
+        let mut tx = graph.start_transaction();
+        graph.add_node(&mut tx);
+        graph.add_node(&mut tx);
+        let result = graph.commit_transaction(&tx);
+        assert_eq!(result, Err(TxError::constraint_violation("single_node", "one node per transaction")));
+        assert!(!graph.conductor.active().contains(&tx.txid));
+
+        let reader = graph.start_transaction();
+        assert!(graph.visible_nodes(&reader).is_empty());
+    }
+
+    #[test]
+    fn test_pre_commit_hook_sees_transaction_view() {
+        let mut graph = Graph::new();
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let log = seen.clone();
+        graph.add_pre_commit_hook(move |graph, t, _| {
+            assert!(graph.conductor.active().contains(&t.txid));
+            log.lock().unwrap().push(graph.visible_nodes(t).len());
+            Ok(())
+        });
+
+        let mut tx = graph.start_transaction();
+        graph.add_node(&mut tx);
+        graph.commit_transaction(&tx).unwrap();
+        assert_eq!(*seen.lock().unwrap(), vec![1]);
+    }
+
+    #[test]
+    fn test_post_commit_hooks_and_removal() {
+        let mut graph = Graph::new();
+        let committed = Arc::new(Mutex::new(Vec::new()));
+        let log = committed.clone();
+        let id = graph.add_post_commit_hook(move |_, changes| log.lock().unwrap().push(changes.txid));
+
+        let mut tx = graph.start_transaction();
+        graph.add_node(&mut tx);
+        graph.commit_transaction(&tx).unwrap();
+        assert_eq!(*committed.lock().unwrap(), vec![tx.txid]);
+
+        assert!(graph.remove_hook(id));
+        assert!(!graph.remove_hook(id));
+        let tx = graph.start_transaction();
+        graph.commit_transaction(&tx).unwrap();
+        assert_eq!(committed.lock().unwrap().len(), 1);
+    }
+}
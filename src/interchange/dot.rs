@@ -0,0 +1,254 @@
+//! Graphviz DOT, for looking at a snapshot while debugging. Nodes show their
+//! id, labels and properties; edges their type and properties, and are
+//! coloured by type, the same type always in the same colour. With
+//! `DotOptions::versions` every element also shows the txids that created
+//! and expired it.
+//!
+//! ```
+//! use graph_mvcc::{DotOptions, Graph};
+//!
+//! let mut graph = Graph::new();
+//! let mut tx = graph.start_transaction();
+//! let a = graph.add_node(&mut tx);
+//! let b = graph.add_node(&mut tx);
+//! let c = graph.add_node(&mut tx);
+//! graph.add_edge(&mut tx, &a, &b, "red".to_string()).unwrap();
+//! graph.add_edge(&mut tx, &b, &c, "blue".to_string()).unwrap();
+//! graph.commit_transaction(&tx).unwrap();
+//!
+//! let reader = graph.start_read_transaction();
+//! let options = DotOptions::new().around(a.id().clone(), 1).versions(true);
+//! let dot = graph.export_dot(&reader, &options).unwrap();
+//! assert!(dot.contains(" -> ") && !dot.contains(&format!("\"{}\"", c.id())));
+//! ```
+
+use std::collections::{HashSet, VecDeque};
+use std::fmt::Write;
+
+use super::sort_key;
+use crate::{ElementId, Graph, Node, NodeId, PropertyValue, TransactionId, TxError, TxResult, Version};
+
+/// Colours that tell edge types apart, from ColorBrewer's Set1.
+const PALETTE: [&str; 8] = ["#e41a1c", "#377eb8", "#4daf4a", "#984ea3", "#ff7f00", "#a65628", "#f781bf", "#999999"];
+
+/// What `export_dot` draws.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct DotOptions {
+    around: Option<(NodeId, usize)>,
+    edge_types: Option<HashSet<String>>,
+    versions: bool,
+}
+
+impl DotOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Draws only the nodes at most `depth` edges away from `node`, in either
+    /// direction, instead of the whole snapshot.
+    pub fn around(mut self, node: NodeId, depth: usize) -> Self {
+        self.around = Some((node, depth));
+        self
+    }
+
+    /// Draws and follows only edges of these types.
+    pub fn edge_types(mut self, edge_types: &[&str]) -> Self {
+        self.edge_types = Some(edge_types.iter().map(|edge_type| edge_type.to_string()).collect());
+        self
+    }
+
+    /// Annotates every element with its creation and expiration txids.
+    pub fn versions(mut self, versions: bool) -> Self {
+        self.versions = versions;
+        self
+    }
+
+    fn follows(&self, edge_type: &str) -> bool {
+        self.edge_types.as_ref().is_none_or(|edge_types| edge_types.contains(edge_type))
+    }
+}
+
+impl Graph {
+    /// Draws what `t` sees as a DOT digraph. Fails with `NodeNotFound` if the
+    /// node of `DotOptions::around` is not visible.
+    pub fn export_dot(&self, t: &TransactionId, options: &DotOptions) -> TxResult<String> {
+        let mut nodes = match &options.around {
+            Some((id, depth)) => self.neighborhood(t, id, *depth, options)?,
+            None => self.visible_nodes(t),
+        };
+        nodes.sort_by(|a, b| sort_key(a.id()).cmp(sort_key(b.id())));
+        let drawn: HashSet<&NodeId> = nodes.iter().map(Node::id).collect();
+
+        let mut dot = String::from("digraph G {\n");
+        for node in &nodes {
+            let mut label = vec![node.id().to_string()];
+            let labels = self.get_labels(t, node);
+            if !labels.is_empty() {
+                label.push(labels.iter().map(|l| format!(":{}", l)).collect());
+            }
+            label.extend(properties(self, t, ElementId::Node(node.id().clone())));
+            if options.versions {
+                label.extend(self.nodes.get(node).map(versions));
+            }
+            writeln!(dot, "  {} [label={}];", quote(&node.id().to_string()), quote(&label.join("\n"))).unwrap();
+        }
+
+        for node in &nodes {
+            let mut edges = self.visible_edges(t, node);
+            edges.sort_by(|(a, _), (b, _)| sort_key(a.id()).cmp(sort_key(b.id())));
+            for (other, edge) in edges {
+                // Every edge is listed at both endpoints, draw it once.
+                if edge.source() != node.id() || !drawn.contains(other.id()) || !options.follows(edge.edgetype()) {
+                    continue;
+                }
+                let mut label = vec![edge.edgetype().to_string()];
+                label.extend(properties(self, t, ElementId::Edge(edge.id().clone())));
+                if options.versions {
+                    label.extend(self.edges.get(edge.id()).map(|(_, version)| versions(version)));
+                }
+                let color = PALETTE[fnv(edge.edgetype()) % PALETTE.len()];
+                writeln!(
+                    dot,
+                    "  {} -> {} [label={}, color=\"{}\", fontcolor=\"{}\"];",
+                    quote(&edge.source().to_string()),
+                    quote(&edge.target().to_string()),
+                    quote(&label.join("\n")),
+                    color,
+                    color
+                )
+                .unwrap();
+            }
+        }
+        dot.push_str("}\n");
+        Ok(dot)
+    }
+
+    /// The visible nodes within `depth` edges of `origin`, following the
+    /// edge types of `options` both ways.
+    fn neighborhood(&self, t: &TransactionId, origin: &NodeId, depth: usize, options: &DotOptions) -> TxResult<Vec<Node>> {
+        let origin = self.visible_node(t, origin).ok_or_else(|| TxError::NodeNotFound(origin.clone()))?;
+        let mut seen: HashSet<NodeId> = HashSet::new();
+        let mut found = vec![origin.clone()];
+        let mut queue = VecDeque::from([(origin, 0)]);
+        seen.insert(found[0].id().clone());
+        while let Some((node, distance)) = queue.pop_front() {
+            if distance == depth {
+                continue;
+            }
+            for (other, edge) in self.visible_edges(t, &node) {
+                if options.follows(edge.edgetype()) && seen.insert(other.id().clone()) {
+                    found.push(other.clone());
+                    queue.push_back((other, distance + 1));
+                }
+            }
+        }
+        Ok(found)
+    }
+}
+
+fn properties(graph: &Graph, t: &TransactionId, element: ElementId) -> Vec<String> {
+    graph
+        .get_properties(t, &element)
+        .into_iter()
+        .map(|(key, value)| match value {
+            PropertyValue::String(text) => format!("{} = '{}'", key, text),
+            value => format!("{} = {}", key, value),
+        })
+        .collect()
+}
+
+fn versions(version: &Version) -> String {
+    match version.expiration_id {
+        0 => format!("created by {}", version.creation_id),
+        expired => format!("created by {}, expired by {}", version.creation_id, expired),
+    }
+}
+
+/// `text` as a quoted DOT string; line breaks become `\n`.
+fn quote(text: &str) -> String {
+    let escaped = text.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n");
+    format!("\"{}\"", escaped)
+}
+
+/// FNV-1a, so a type gets the same colour in every export.
+fn fnv(text: &str) -> usize {
+    text.bytes().fold(0xcbf29ce484222325u64, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3)) as usize
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Unit Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    /// Five nodes in a line, joined by alternating red and blue edges; the
+    /// first `n - 1` edges join the first `n` nodes.
+    const LINE: [(usize, usize, &str); 4] = [(0, 1, "red"), (1, 2, "blue"), (2, 3, "red"), (3, 4, "blue")];
+
+    #[test]
+    fn test_whole_snapshot_is_drawn() {
+        let (mut graph, nodes) = testing::graph(3, &LINE[..2]);
+        let mut tx = graph.start_transaction();
+        graph.add_label(&mut tx, &nodes[0], "Station".to_string()).unwrap();
+        graph.set_property(&mut tx, ElementId::Node(nodes[0].id().clone()), "name".to_string(), "Say \"hi\"".into()).unwrap();
+        graph.commit_transaction(&tx).unwrap();
+
+        let reader = graph.start_read_transaction();
+        let dot = graph.export_dot(&reader, &DotOptions::new()).unwrap();
+        assert!(dot.starts_with("digraph G {\n") && dot.ends_with("}\n"));
+        assert!(dot.contains(&format!("label=\"{}\\n:Station\\nname = 'Say \\\"hi\\\"'\"", nodes[0].id())));
+        let red = format!("{} -> {} [label=\"red\", color=\"{}\"", quote(&nodes[0].id().to_string()), quote(&nodes[1].id().to_string()), PALETTE[fnv("red") % 8]);
+        assert!(dot.contains(&red));
+        assert_eq!(dot.matches(" -> ").count(), 2);
+        assert_eq!(graph.export_dot(&reader, &DotOptions::new()).unwrap(), dot);
+    }
+
+    #[test]
+    fn test_self_loops_are_drawn_once() {
+        let mut graph = Graph::new();
+        let mut tx = graph.start_transaction();
+        let a = graph.add_node(&mut tx);
+        graph.add_edge(&mut tx, &a, &a, "loop".to_string()).unwrap();
+        graph.commit_transaction(&tx).unwrap();
+
+        let reader = graph.start_read_transaction();
+        let dot = graph.export_dot(&reader, &DotOptions::new()).unwrap();
+        assert_eq!(dot.matches(" -> ").count(), 1);
+    }
+
+    #[test]
+    fn test_neighborhood_is_bounded() {
+        let (mut graph, nodes) = testing::graph(5, &LINE);
+        let reader = graph.start_read_transaction();
+        let contains = |dot: &str, node: &Node| dot.contains(&format!("{} [label=", quote(&node.id().to_string())));
+
+        let dot = graph.export_dot(&reader, &DotOptions::new().around(nodes[2].id().clone(), 1)).unwrap();
+        let drawn: Vec<bool> = nodes.iter().map(|node| contains(&dot, node)).collect();
+        assert_eq!(drawn, vec![false, true, true, true, false]);
+        assert_eq!(dot.matches(" -> ").count(), 2);
+
+        let options = DotOptions::new().around(nodes[2].id().clone(), 4).edge_types(&["red"]);
+        let dot = graph.export_dot(&reader, &options).unwrap();
+        let drawn: Vec<bool> = nodes.iter().map(|node| contains(&dot, node)).collect();
+        assert_eq!(drawn, vec![false, false, true, true, false]);
+
+        let missing = NodeId::String("missing".to_string());
+        let error = graph.export_dot(&reader, &DotOptions::new().around(missing.clone(), 1));
+        assert_eq!(error, Err(TxError::NodeNotFound(missing)));
+    }
+
+    #[test]
+    fn test_versions_show_mvcc_state() {
+        let (mut graph, nodes) = testing::graph(2, &LINE[..1]);
+        let created = graph.nodes[&nodes[0]].creation_id;
+        let reader = graph.start_read_transaction();
+        let mut tx = graph.start_transaction();
+        graph.remove_node(&mut tx, &nodes[1]).unwrap();
+
+        let dot = graph.export_dot(&reader, &DotOptions::new().versions(true)).unwrap();
+        assert!(dot.contains(&format!("created by {}\"", created)));
+        assert!(dot.contains(&format!("created by {}, expired by {}", created, tx.txid)));
+        assert!(!graph.export_dot(&reader, &DotOptions::new()).unwrap().contains("created by"));
+    }
+}
@@ -0,0 +1,288 @@
+//! GEXF 1.3, Gephi's own format. Properties become `<attvalue>`s of
+//! attributes declared per class, labels use the reserved node attribute
+//! `labels`, and the type of an edge is its `label`:
+//!
+//! ```xml
+//! <gexf xmlns="http://gexf.net/1.3" version="1.3">
+//!   <graph defaultedgetype="directed">
+//!     <attributes class="node">
+//!       <attribute id="labels" title="labels" type="string"/>
+//!       <attribute id="n0" title="name" type="string"/>
+//!     </attributes>
+//!     <nodes>
+//!       <node id="a" label="a">
+//!         <attvalues><attvalue for="labels" value=":Station"/><attvalue for="n0" value="Central"/></attvalues>
+//!       </node>
+//!       <node id="b" label="b"/>
+//!     </nodes>
+//!     <edges>
+//!       <edge id="e" source="a" target="b" label="red"/>
+//!     </edges>
+//!   </graph>
+//! </gexf>
+//! ```
+//!
+//! On import, an edge without a `label` takes its `kind`, or else the type
+//! `edge`. Node labels, colours, positions and dynamics are ignored.
+
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+
+use quick_xml::events::BytesStart;
+
+use super::xml::{self, XmlFormat, DEFAULT_EDGE_TYPE, LABELS};
+use super::{parse_value, GraphDocument, IdPolicy, Keys, Renamer};
+use crate::{BulkEdge, BulkItem, BulkNode, EdgeId, Graph, NodeId, TransactionId, TxError, TxResult};
+
+const NAME: &str = "GEXF";
+
+impl GraphDocument {
+    pub fn write_gexf(&self, mut writer: impl Write) -> TxResult<()> {
+        self.gexf(&mut writer).map_err(|error| TxError::database_failure("writing GEXF", error))
+    }
+
+    fn gexf(&self, w: &mut impl Write) -> std::io::Result<()> {
+        let keys = Keys::of(self);
+        writeln!(w, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(w, r#"<gexf xmlns="http://gexf.net/1.3" version="1.3">"#)?;
+        writeln!(w, r#"  <graph defaultedgetype="directed">"#)?;
+        writeln!(w, r#"    <attributes class="node">"#)?;
+        writeln!(w, r#"      <attribute id="{LABELS}" title="{LABELS}" type="string"/>"#)?;
+        for (name, (id, kind)) in &keys.node {
+            writeln!(w, r#"      <attribute id="{}" title="{}" type="{}"/>"#, id, xml::escape(name), kind)?;
+        }
+        writeln!(w, "    </attributes>")?;
+        writeln!(w, r#"    <attributes class="edge">"#)?;
+        for (name, (id, kind)) in &keys.edge {
+            writeln!(w, r#"      <attribute id="{}" title="{}" type="{}"/>"#, id, xml::escape(name), kind)?;
+        }
+        writeln!(w, "    </attributes>")?;
+
+        writeln!(w, "    <nodes>")?;
+        for node in &self.nodes {
+            let id = xml::escape(&node.id.to_string()).into_owned();
+            let mut values = Vec::new();
+            if !node.labels.is_empty() {
+                values.push((LABELS.to_string(), xml::join_labels(&node.labels)));
+            }
+            for (name, value) in &node.properties {
+                values.push((keys.node[name].0.clone(), value.to_string()));
+            }
+            write!(w, r#"      <node id="{}" label="{}""#, id, id)?;
+            attvalues(w, &values, "node")?;
+        }
+        writeln!(w, "    </nodes>")?;
+
+        writeln!(w, "    <edges>")?;
+        for edge in &self.edges {
+            let values: Vec<_> = edge.properties.iter().map(|(name, value)| (keys.edge[name].0.clone(), value.to_string())).collect();
+            write!(
+                w,
+                r#"      <edge id="{}" source="{}" target="{}" label="{}""#,
+                xml::escape(&edge.id.to_string()),
+                xml::escape(&edge.source.to_string()),
+                xml::escape(&edge.target.to_string()),
+                xml::escape(&edge.edge_type)
+            )?;
+            attvalues(w, &values, "edge")?;
+        }
+        writeln!(w, "    </edges>")?;
+
+        writeln!(w, "  </graph>")?;
+        writeln!(w, "</gexf>")
+    }
+}
+
+/// Closes the open `<node` or `<edge` tag, with its attribute values if
+/// there are any.
+fn attvalues(w: &mut impl Write, values: &[(String, String)], tag: &str) -> std::io::Result<()> {
+    if values.is_empty() {
+        return writeln!(w, "/>");
+    }
+    writeln!(w, ">")?;
+    writeln!(w, "        <attvalues>")?;
+    for (id, value) in values {
+        writeln!(w, r#"          <attvalue for="{}" value="{}"/>"#, id, xml::escape(value))?;
+    }
+    writeln!(w, "        </attvalues>")?;
+    writeln!(w, "      </{}>", tag)
+}
+
+impl Graph {
+    /// Writes the snapshot of `t` as GEXF.
+    pub fn export_gexf(&self, t: &TransactionId, writer: impl Write) -> TxResult<()> {
+        self.export_document(t).write_gexf(writer)
+    }
+
+    /// Builds a new graph from GEXF, parsing it as it is loaded.
+    pub fn import_gexf(reader: impl BufRead, ids: IdPolicy) -> TxResult<Graph> {
+        xml::import(reader, GexfFormat::new(ids))
+    }
+}
+
+/// A declared `<attribute>`: its property name and type.
+struct Attribute {
+    name: String,
+    kind: String,
+}
+
+struct GexfFormat {
+    renamer: Renamer,
+    // Attributes by class ("node" or "edge") and id
+    attributes: HashMap<(String, String), Attribute>,
+    class: String,
+    element: Option<BulkItem>,
+}
+
+impl GexfFormat {
+    fn new(ids: IdPolicy) -> Self {
+        GexfFormat {
+            renamer: Renamer::new(ids),
+            attributes: HashMap::new(),
+            class: String::new(),
+            element: None,
+        }
+    }
+
+    fn attvalue(&mut self, id: String, value: &str) -> TxResult<()> {
+        let class = match &self.element {
+            Some(BulkItem::Node(_)) => "node",
+            Some(BulkItem::Edge(_)) => "edge",
+            None => return Ok(()),
+        };
+        if let Some(BulkItem::Node(node)) = self.element.as_mut() {
+            if id == LABELS {
+                node.labels.extend(xml::split_labels(value));
+                return Ok(());
+            }
+        }
+        let declared = self.attributes.get(&(class.to_string(), id.clone()));
+        let declared = declared.ok_or_else(|| TxError::invalid_import(NAME, format!("undeclared {} attribute '{}'", class, id)))?;
+        let value = parse_value(NAME, &declared.name, &declared.kind, value)?;
+        match self.element.as_mut() {
+            Some(BulkItem::Node(node)) => node.properties.push((declared.name.clone(), value)),
+            Some(BulkItem::Edge(edge)) => edge.properties.push((declared.name.clone(), value)),
+            None => {}
+        }
+        Ok(())
+    }
+}
+
+impl XmlFormat for GexfFormat {
+    const NAME: &'static str = NAME;
+
+    fn start(&mut self, element: &BytesStart) -> TxResult<()> {
+        match element.local_name().as_ref() {
+            b"attributes" => self.class = xml::required(NAME, element, "class")?,
+            b"attribute" => {
+                let id = xml::required(NAME, element, "id")?;
+                let name = xml::attribute(NAME, element, "title")?.unwrap_or_else(|| id.clone());
+                let kind = xml::attribute(NAME, element, "type")?.unwrap_or_else(|| "string".to_string());
+                self.attributes.insert((self.class.clone(), id), Attribute { name, kind });
+            }
+            b"node" => {
+                let id = NodeId::String(xml::required(NAME, element, "id")?);
+                self.element = Some(BulkItem::Node(BulkNode::with_id(self.renamer.node(id)?)));
+            }
+            b"edge" => {
+                let source = self.renamer.endpoint(NodeId::String(xml::required(NAME, element, "source")?));
+                let target = self.renamer.endpoint(NodeId::String(xml::required(NAME, element, "target")?));
+                let edge_type = match xml::attribute(NAME, element, "label")? {
+                    Some(label) => label,
+                    None => xml::attribute(NAME, element, "kind")?.unwrap_or_else(|| DEFAULT_EDGE_TYPE.to_string()),
+                };
+                let mut edge = BulkEdge::new(source, target, &edge_type);
+                if let Some(id) = xml::attribute(NAME, element, "id")? {
+                    edge.id = self.renamer.edge(EdgeId::String(id));
+                }
+                self.element = Some(BulkItem::Edge(edge));
+            }
+            b"attvalue" => {
+                let id = xml::required(NAME, element, "for")?;
+                let value = xml::required(NAME, element, "value")?;
+                self.attvalue(id, &value)?;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn text(&mut self, _: &str) {}
+
+    fn end(&mut self, name: &[u8]) -> TxResult<Option<BulkItem>> {
+        match name {
+            b"node" | b"edge" => Ok(self.element.take()),
+            _ => Ok(None),
+        }
+    }
+
+    fn renamer(&self) -> &Renamer {
+        &self.renamer
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Unit Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ElementId;
+
+    #[test]
+    fn test_gexf_round_trip() {
+        let mut graph = Graph::new();
+        let mut tx = graph.start_transaction();
+        let a = graph.add_node(&mut tx);
+        let b = graph.add_node(&mut tx);
+        graph.add_label(&mut tx, &a, "Station".to_string()).unwrap();
+        let element = ElementId::Node(a.id().clone());
+        graph.set_property(&mut tx, element.clone(), "name".to_string(), " Central & \"Co\" ".into()).unwrap();
+        graph.set_property(&mut tx, element, "platforms".to_string(), 4i64.into()).unwrap();
+        graph.add_edge(&mut tx, &b, &a, "red".to_string()).unwrap();
+        let (_, edge) = graph.visible_edges(&tx, &a).remove(0);
+        graph.set_property(&mut tx, ElementId::Edge(edge.id().clone()), "open".to_string(), false.into()).unwrap();
+        graph.commit_transaction(&tx).unwrap();
+
+        let reader = graph.start_read_transaction();
+        let mut buffer = Vec::new();
+        graph.export_gexf(&reader, &mut buffer).unwrap();
+        let mut copy = Graph::import_gexf(buffer.as_slice(), IdPolicy::Preserve).unwrap();
+        let copied = copy.start_read_transaction();
+        assert_eq!(copy.export_document(&copied), graph.export_document(&reader));
+    }
+
+    #[test]
+    fn test_gephi_gexf_is_read() {
+        let file = r#"<?xml version="1.0" encoding="UTF-8"?>
+            <gexf xmlns="http://gexf.net/1.3" version="1.3">
+              <meta><creator>Gephi</creator></meta>
+              <graph defaultedgetype="undirected">
+                <attributes class="node"><attribute id="0" title="degree" type="integer"/></attributes>
+                <attributes class="edge"><attribute id="0" title="weight" type="float"/></attributes>
+                <nodes>
+                  <node id="0" label="Ann"><attvalues><attvalue for="0" value="1"/></attvalues></node>
+                  <node id="1" label="Bob"/>
+                </nodes>
+                <edges>
+                  <edge source="0" target="1"><attvalues><attvalue for="0" value="0.5"/></attvalues></edge>
+                </edges>
+              </graph>
+            </gexf>"#;
+        let mut graph = Graph::import_gexf(file.as_bytes(), IdPolicy::Preserve).unwrap();
+        let reader = graph.start_read_transaction();
+        let document = graph.export_document(&reader);
+        assert_eq!(document.nodes[0].properties["degree"], 1i64.into());
+        assert_eq!(document.edges[0].edge_type, DEFAULT_EDGE_TYPE);
+        assert_eq!(document.edges[0].properties["weight"], 0.5f64.into());
+        assert_eq!(document.edges[0].source, NodeId::String("0".to_string()));
+    }
+
+    #[test]
+    fn test_undeclared_attribute_is_reported() {
+        let file = r#"<gexf><graph><nodes><node id="0"><attvalues><attvalue for="7" value="x"/></attvalues></node></nodes></graph></gexf>"#;
+        assert_eq!(
+            Graph::import_gexf(file.as_bytes(), IdPolicy::Preserve).map(|_| ()),
+            Err(TxError::invalid_import(NAME, "undeclared node attribute '7'"))
+        );
+    }
+}
@@ -0,0 +1,287 @@
+//! GraphML, as read by yEd and Gephi. Properties become `<data>` under keys
+//! declared per property name and type; labels and edge types use the
+//! reserved keys `labels` and `type`:
+//!
+//! ```xml
+//! <graphml xmlns="http://graphml.graphdrawing.org/xmlns">
+//!   <key id="labels" for="node" attr.name="labels" attr.type="string"/>
+//!   <key id="n0" for="node" attr.name="name" attr.type="string"/>
+//!   <key id="type" for="edge" attr.name="type" attr.type="string"/>
+//!   <graph id="G" edgedefault="directed">
+//!     <node id="a"><data key="labels">:Station</data><data key="n0">Central</data></node>
+//!     <node id="b"/>
+//!     <edge id="e" source="a" target="b"><data key="type">red</data></edge>
+//!   </graph>
+//! </graphml>
+//! ```
+//!
+//! On import, edges without a `type` get the type `edge`, and edges without
+//! an id get a fresh one. Key defaults and nested graphs are ignored.
+
+use std::collections::HashMap;
+use std::io::{BufRead, Write};
+
+use quick_xml::events::BytesStart;
+
+use super::xml::{self, XmlFormat, DEFAULT_EDGE_TYPE, LABELS};
+use super::{parse_value, GraphDocument, IdPolicy, Keys, Renamer};
+use crate::{BulkEdge, BulkItem, BulkNode, EdgeId, Graph, NodeId, TransactionId, TxError, TxResult};
+
+const NAME: &str = "GraphML";
+const TYPE: &str = "type";
+
+impl GraphDocument {
+    pub fn write_graphml(&self, mut writer: impl Write) -> TxResult<()> {
+        self.graphml(&mut writer).map_err(|error| TxError::database_failure("writing GraphML", error))
+    }
+
+    fn graphml(&self, w: &mut impl Write) -> std::io::Result<()> {
+        let keys = Keys::of(self);
+        writeln!(w, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(w, r#"<graphml xmlns="http://graphml.graphdrawing.org/xmlns">"#)?;
+        writeln!(w, r#"  <key id="{LABELS}" for="node" attr.name="{LABELS}" attr.type="string"/>"#)?;
+        for (name, (id, kind)) in &keys.node {
+            writeln!(w, r#"  <key id="{}" for="node" attr.name="{}" attr.type="{}"/>"#, id, xml::escape(name), kind)?;
+        }
+        writeln!(w, r#"  <key id="{TYPE}" for="edge" attr.name="{TYPE}" attr.type="string"/>"#)?;
+        for (name, (id, kind)) in &keys.edge {
+            writeln!(w, r#"  <key id="{}" for="edge" attr.name="{}" attr.type="{}"/>"#, id, xml::escape(name), kind)?;
+        }
+        writeln!(w, r#"  <graph id="G" edgedefault="directed">"#)?;
+
+        for node in &self.nodes {
+            writeln!(w, r#"    <node id="{}">"#, xml::escape(&node.id.to_string()))?;
+            if !node.labels.is_empty() {
+                writeln!(w, r#"      <data key="{LABELS}">{}</data>"#, xml::escape(&xml::join_labels(&node.labels)))?;
+            }
+            for (name, value) in &node.properties {
+                let (id, _) = &keys.node[name];
+                writeln!(w, r#"      <data key="{}">{}</data>"#, id, xml::escape(&value.to_string()))?;
+            }
+            writeln!(w, "    </node>")?;
+        }
+        for edge in &self.edges {
+            writeln!(
+                w,
+                r#"    <edge id="{}" source="{}" target="{}">"#,
+                xml::escape(&edge.id.to_string()),
+                xml::escape(&edge.source.to_string()),
+                xml::escape(&edge.target.to_string())
+            )?;
+            writeln!(w, r#"      <data key="{TYPE}">{}</data>"#, xml::escape(&edge.edge_type))?;
+            for (name, value) in &edge.properties {
+                let (id, _) = &keys.edge[name];
+                writeln!(w, r#"      <data key="{}">{}</data>"#, id, xml::escape(&value.to_string()))?;
+            }
+            writeln!(w, "    </edge>")?;
+        }
+
+        writeln!(w, "  </graph>")?;
+        writeln!(w, "</graphml>")
+    }
+}
+
+impl Graph {
+    /// Writes the snapshot of `t` as GraphML.
+    pub fn export_graphml(&self, t: &TransactionId, writer: impl Write) -> TxResult<()> {
+        self.export_document(t).write_graphml(writer)
+    }
+
+    /// Builds a new graph from GraphML, parsing it as it is loaded.
+    pub fn import_graphml(reader: impl BufRead, ids: IdPolicy) -> TxResult<Graph> {
+        xml::import(reader, GraphmlFormat::new(ids))
+    }
+}
+
+/// A declared `<key>`: its property name and type.
+struct Key {
+    name: String,
+    kind: String,
+}
+
+struct GraphmlFormat {
+    renamer: Renamer,
+    keys: HashMap<String, Key>,
+    element: Option<BulkItem>,
+    // The key of the open <data> element and its text so far
+    data: Option<String>,
+    text: String,
+}
+
+impl GraphmlFormat {
+    fn new(ids: IdPolicy) -> Self {
+        GraphmlFormat {
+            renamer: Renamer::new(ids),
+            keys: HashMap::new(),
+            element: None,
+            data: None,
+            text: String::new(),
+        }
+    }
+
+    fn data(&mut self, key: &str) -> TxResult<()> {
+        let text = std::mem::take(&mut self.text);
+        match self.element.as_mut() {
+            Some(BulkItem::Node(node)) if key == LABELS => node.labels.extend(xml::split_labels(&text)),
+            Some(BulkItem::Edge(edge)) if key == TYPE => edge.edge_type = text.trim().to_string(),
+            Some(item) => {
+                let declared = self.keys.get(key);
+                let declared = declared.ok_or_else(|| TxError::invalid_import(NAME, format!("undeclared key '{}'", key)))?;
+                let value = parse_value(NAME, &declared.name, &declared.kind, &text)?;
+                match item {
+                    BulkItem::Node(node) => node.properties.push((declared.name.clone(), value)),
+                    BulkItem::Edge(edge) => edge.properties.push((declared.name.clone(), value)),
+                }
+            }
+            // Data of the graph itself
+            None => {}
+        }
+        Ok(())
+    }
+}
+
+impl XmlFormat for GraphmlFormat {
+    const NAME: &'static str = NAME;
+
+    fn start(&mut self, element: &BytesStart) -> TxResult<()> {
+        match element.local_name().as_ref() {
+            b"key" => {
+                let id = xml::required(NAME, element, "id")?;
+                let name = xml::attribute(NAME, element, "attr.name")?.unwrap_or_else(|| id.clone());
+                let kind = xml::attribute(NAME, element, "attr.type")?.unwrap_or_else(|| "string".to_string());
+                self.keys.insert(id, Key { name, kind });
+            }
+            b"node" => {
+                let id = NodeId::String(xml::required(NAME, element, "id")?);
+                self.element = Some(BulkItem::Node(BulkNode::with_id(self.renamer.node(id)?)));
+            }
+            b"edge" => {
+                let source = self.renamer.endpoint(NodeId::String(xml::required(NAME, element, "source")?));
+                let target = self.renamer.endpoint(NodeId::String(xml::required(NAME, element, "target")?));
+                let mut edge = BulkEdge::new(source, target, DEFAULT_EDGE_TYPE);
+                if let Some(id) = xml::attribute(NAME, element, "id")? {
+                    edge.id = self.renamer.edge(EdgeId::String(id));
+                }
+                self.element = Some(BulkItem::Edge(edge));
+            }
+            b"data" => {
+                self.data = Some(xml::required(NAME, element, "key")?);
+                self.text.clear();
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn text(&mut self, text: &str) {
+        if self.data.is_some() {
+            self.text.push_str(text);
+        }
+    }
+
+    fn end(&mut self, name: &[u8]) -> TxResult<Option<BulkItem>> {
+        match name {
+            b"data" => {
+                if let Some(key) = self.data.take() {
+                    self.data(&key)?;
+                }
+                Ok(None)
+            }
+            b"node" | b"edge" => Ok(self.element.take()),
+            _ => Ok(None),
+        }
+    }
+
+    fn renamer(&self) -> &Renamer {
+        &self.renamer
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Unit Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ElementId, ErrorKind};
+
+    fn sample() -> Graph {
+        let mut graph = Graph::new();
+        let mut tx = graph.start_transaction();
+        let a = graph.add_node(&mut tx);
+        let b = graph.add_node(&mut tx);
+        graph.add_label(&mut tx, &a, "Station".to_string()).unwrap();
+        graph.add_label(&mut tx, &a, "Stop".to_string()).unwrap();
+        let element = ElementId::Node(a.id().clone());
+        graph.set_property(&mut tx, element.clone(), "name".to_string(), "Café <Central> & \"Co\"".into()).unwrap();
+        graph.set_property(&mut tx, element.clone(), "platforms".to_string(), 4i64.into()).unwrap();
+        graph.set_property(&mut tx, ElementId::Node(b.id().clone()), "platforms".to_string(), 1.5f64.into()).unwrap();
+        graph.set_property(&mut tx, element, "open".to_string(), true.into()).unwrap();
+        graph.add_edge(&mut tx, &a, &b, "red".to_string()).unwrap();
+        let (_, edge) = graph.visible_edges(&tx, &a).remove(0);
+        graph.set_property(&mut tx, ElementId::Edge(edge.id().clone()), "minutes".to_string(), 2.5f64.into()).unwrap();
+        graph.commit_transaction(&tx).unwrap();
+        graph
+    }
+
+    #[test]
+    fn test_graphml_round_trip() {
+        let mut graph = sample();
+        let reader = graph.start_read_transaction();
+        let mut buffer = Vec::new();
+        graph.export_graphml(&reader, &mut buffer).unwrap();
+
+        let mut copy = Graph::import_graphml(buffer.as_slice(), IdPolicy::Preserve).unwrap();
+        let copied = copy.start_read_transaction();
+        let (original, imported) = (graph.export_document(&reader), copy.export_document(&copied));
+        assert_eq!(imported.edges, original.edges);
+        for (node, expected) in imported.nodes.iter().zip(&original.nodes) {
+            assert_eq!((&node.id, &node.labels), (&expected.id, &expected.labels));
+        }
+        // "platforms" holds an integer and a float, so it is declared a string
+        let platforms: Vec<_> = imported.nodes.iter().map(|node| node.properties["platforms"].to_string()).collect();
+        assert!(platforms.contains(&"4".to_string()) && platforms.contains(&"1.5".to_string()));
+        let named = imported.nodes.iter().find(|node| !node.labels.is_empty()).unwrap();
+        assert_eq!(named.properties["name"], "Café <Central> & \"Co\"".into());
+        assert_eq!(named.properties["open"], true.into());
+    }
+
+    #[test]
+    fn test_foreign_graphml_is_read() {
+        let file = r#"<?xml version="1.0" encoding="UTF-8"?>
+            <graphml xmlns="http://graphml.graphdrawing.org/xmlns">
+              <key id="d0" for="node" attr.name="weight" attr.type="int"/>
+              <key id="d1" for="edge" attr.name="cost" attr.type="float"/>
+              <graph edgedefault="undirected">
+                <edge source="x" target="y"><data key="d1">0.5</data></edge>
+                <node id="x"><data key="d0">3</data></node>
+                <node id="y"/>
+              </graph>
+            </graphml>"#;
+        let mut graph = Graph::import_graphml(file.as_bytes(), IdPolicy::Regenerate).unwrap();
+        let reader = graph.start_read_transaction();
+        let document = graph.export_document(&reader);
+        assert_eq!(document.nodes.len(), 2);
+        assert_eq!(document.edges[0].edge_type, DEFAULT_EDGE_TYPE);
+        assert_eq!(document.edges[0].properties["cost"], 0.5f64.into());
+        let weighted = document.nodes.iter().find(|node| node.id == document.edges[0].source).unwrap();
+        assert_eq!(weighted.properties["weight"], 3i64.into());
+    }
+
+    #[test]
+    fn test_bad_graphml_is_reported() {
+        let bad_value = r#"<graphml><key id="d0" for="node" attr.name="weight" attr.type="int"/>
+            <graph><node id="x"><data key="d0">heavy</data></node></graph></graphml>"#;
+        assert_eq!(
+            Graph::import_graphml(bad_value.as_bytes(), IdPolicy::Preserve).map(|_| ()),
+            Err(TxError::invalid_import(NAME, "value 'heavy' of 'weight' is not of type int"))
+        );
+
+        let dangling = r#"<graphml><graph><node id="x"/><edge source="x" target="y"/></graph></graphml>"#;
+        let error = Graph::import_graphml(dangling.as_bytes(), IdPolicy::Regenerate).unwrap_err();
+        assert_eq!(error, TxError::NodeNotFound(NodeId::String("y".to_string())));
+
+        let broken = "<graphml><graph><node id=\"x\"></graph></graphml>";
+        let error = Graph::import_graphml(broken.as_bytes(), IdPolicy::Preserve).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidImport);
+    }
+}
@@ -0,0 +1,202 @@
+//! JSON snapshots. A document is a single object:
+//!
+//! ```json
+//! {
+//!   "format": "graph_mvcc",
+//!   "version": 1,
+//!   "nodes": [
+//!     {"id": "a", "labels": ["Station"], "properties": {"name": "Central"}},
+//!     {"id": "b", "labels": [], "properties": {}}
+//!   ],
+//!   "edges": [
+//!     {"id": "e", "source": "a", "target": "b", "type": "red", "properties": {"minutes": 4}}
+//!   ]
+//! }
+//! ```
+//!
+//! Property values are bare JSON booleans, numbers and strings; a number
+//! without a fraction or exponent is read as an integer, and NaN and
+//! infinite floats cannot be exported. `labels` and `properties` may be
+//! left out.
+
+use std::io::{Read, Write};
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use super::{GraphDocument, IdPolicy};
+use crate::{Graph, PropertyValue, TransactionId, TxError, TxResult};
+
+const FORMAT: &str = "graph_mvcc";
+const VERSION: u32 = 1;
+
+#[derive(Serialize)]
+struct Header<'a> {
+    format: &'a str,
+    version: u32,
+    #[serde(flatten)]
+    document: &'a GraphDocument,
+}
+
+#[derive(Deserialize)]
+struct Parsed {
+    format: String,
+    version: u32,
+    #[serde(flatten)]
+    document: GraphDocument,
+}
+
+fn invalid(error: serde_json::Error) -> TxError {
+    TxError::InvalidImport {
+        format: "JSON".to_string(),
+        message: error.to_string(),
+        source: Some(Arc::new(error)),
+    }
+}
+
+impl GraphDocument {
+    /// The document as JSON. JSON has no NaN or infinity, so a document
+    /// holding one fails with `TxError::DatabaseFailure` rather than being
+    /// written with a `null` that cannot be read back.
+    pub fn to_json(&self) -> TxResult<String> {
+        self.check_finite()?;
+        let header = Header { format: FORMAT, version: VERSION, document: self };
+        serde_json::to_string_pretty(&header).map_err(|error| TxError::database_failure("writing JSON", error))
+    }
+
+    /// Like `to_json`, into `writer`.
+    pub fn write_json(&self, writer: impl Write) -> TxResult<()> {
+        self.check_finite()?;
+        let header = Header { format: FORMAT, version: VERSION, document: self };
+        serde_json::to_writer(writer, &header).map_err(|error| TxError::database_failure("writing JSON", error))
+    }
+
+    pub fn from_json(json: &str) -> TxResult<GraphDocument> {
+        Self::checked(serde_json::from_str(json).map_err(invalid)?)
+    }
+
+    pub fn read_json(reader: impl Read) -> TxResult<GraphDocument> {
+        Self::checked(serde_json::from_reader(reader).map_err(invalid)?)
+    }
+
+    fn check_finite(&self) -> TxResult<()> {
+        let nodes = self.nodes.iter().map(|node| ("node", node.id.to_string(), &node.properties));
+        let edges = self.edges.iter().map(|edge| ("edge", edge.id.to_string(), &edge.properties));
+        for (kind, id, properties) in nodes.chain(edges) {
+            for (key, value) in properties {
+                match value {
+                    PropertyValue::Float(float) if !float.is_finite() => {
+                        return Err(TxError::DatabaseFailure {
+                            message: format!("writing JSON: property '{}' of {} {} is {}", key, kind, id, float),
+                            source: None,
+                        });
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn checked(parsed: Parsed) -> TxResult<GraphDocument> {
+        if parsed.format != FORMAT {
+            return Err(TxError::invalid_import("JSON", format!("unknown format '{}'", parsed.format)));
+        }
+        if parsed.version != VERSION {
+            return Err(TxError::invalid_import("JSON", format!("unsupported version {}", parsed.version)));
+        }
+        Ok(parsed.document)
+    }
+}
+
+impl Graph {
+    /// The snapshot of `t` as a JSON document, see `GraphDocument::to_json`.
+    pub fn export_json(&self, t: &TransactionId) -> TxResult<String> {
+        self.export_document(t).to_json()
+    }
+
+    /// Builds a new graph from a JSON document written by `export_json`.
+    pub fn import_json(json: &str, ids: IdPolicy) -> TxResult<Graph> {
+        Graph::import_document(GraphDocument::from_json(json)?, ids)
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Unit Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ElementId, ErrorKind, NodeId};
+
+    #[test]
+    fn test_json_round_trip() {
+        let mut graph = Graph::new();
+        let mut tx = graph.start_transaction();
+        let a = graph.add_node(&mut tx);
+        let b = graph.add_node(&mut tx);
+        graph.add_label(&mut tx, &a, "Station".to_string()).unwrap();
+        let element = ElementId::Node(a.id().clone());
+        graph.set_property(&mut tx, element.clone(), "open".to_string(), true.into()).unwrap();
+        graph.set_property(&mut tx, element.clone(), "platforms".to_string(), 4i64.into()).unwrap();
+        graph.set_property(&mut tx, element.clone(), "load".to_string(), 1.0f64.into()).unwrap();
+        graph.set_property(&mut tx, element.clone(), "name".to_string(), "Central".into()).unwrap();
+        graph.add_edge(&mut tx, &b, &a, "red".to_string()).unwrap();
+        graph.commit_transaction(&tx).unwrap();
+
+        let reader = graph.start_read_transaction();
+        let json = graph.export_json(&reader).unwrap();
+        let mut copy = Graph::import_json(&json, IdPolicy::Preserve).unwrap();
+        let copied = copy.start_read_transaction();
+        assert_eq!(copy.export_document(&copied), graph.export_document(&reader));
+        assert_eq!(copy.export_json(&copied), Ok(json));
+        assert_eq!(copy.traversal(&copied).v(b.id()).out("red").to_list(), vec![a.id().clone()]);
+
+        let mut buffer = Vec::new();
+        graph.export_document(&reader).write_json(&mut buffer).unwrap();
+        assert_eq!(GraphDocument::read_json(buffer.as_slice()).unwrap(), graph.export_document(&reader));
+    }
+
+    #[test]
+    fn test_non_finite_floats_are_not_exported() {
+        let mut graph = Graph::new();
+        let mut tx = graph.start_transaction();
+        let a = graph.add_node(&mut tx);
+        graph.set_property(&mut tx, ElementId::Node(a.id().clone()), "load".to_string(), f64::NAN.into()).unwrap();
+        graph.commit_transaction(&tx).unwrap();
+
+        let reader = graph.start_read_transaction();
+        let error = graph.export_json(&reader).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::DatabaseFailure);
+        assert!(error.to_string().contains("'load'"));
+        assert!(graph.export_document(&reader).write_json(Vec::new()).is_err());
+    }
+
+    #[test]
+    fn test_documented_layout_is_accepted() {
+        let json = r#"{
+            "format": "graph_mvcc",
+            "version": 1,
+            "nodes": [{"id": "a", "labels": ["Station"]}, {"id": "b"}],
+            "edges": [{"id": "e", "source": "a", "target": "b", "type": "red", "properties": {"minutes": 4}}]
+        }"#;
+        let mut graph = Graph::import_json(json, IdPolicy::Preserve).unwrap();
+        let reader = graph.start_read_transaction();
+        let a = NodeId::String("a".to_string());
+        assert_eq!(graph.traversal(&reader).v(&a).out("red").to_list(), vec![NodeId::String("b".to_string())]);
+        let document = graph.export_document(&reader);
+        assert_eq!(document.edges[0].properties["minutes"], 4i64.into());
+    }
+
+    #[test]
+    fn test_malformed_json_is_reported() {
+        let error = Graph::import_json("{\"nodes\": [", IdPolicy::Preserve).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidImport);
+        assert!(std::error::Error::source(&error).is_some());
+
+        let future = r#"{"format": "graph_mvcc", "version": 2, "nodes": [], "edges": []}"#;
+        assert_eq!(
+            Graph::import_json(future, IdPolicy::Preserve).map(|_| ()),
+            Err(TxError::invalid_import("JSON", "unsupported version 2"))
+        );
+    }
+}
@@ -0,0 +1,363 @@
+//! # Import and export
+//!
+//! A `GraphDocument` is an owned, format-neutral copy of what a transaction
+//! sees: every visible node with its labels and properties, and every visible
+//! edge with its type, direction and properties. Exporters turn one into text,
+//! importers build one from text and load it into a new `Graph` through the
+//! bulk loader. The formats are JSON (`export_json`), GraphML
+//! (`export_graphml`) and GEXF (`export_gexf`), each with a matching import,
+//! Neo4j admin-import CSV (`export_neo4j_csv` and `Neo4jImport`), and DOT
+//! (`export_dot`) to look at a graph.
+//!
+//! Nodes and edges are listed in id order, so exporting the same snapshot
+//! twice gives the same output. On import, `IdPolicy` decides whether the
+//! ids of the document are kept or fresh ones are minted.
+//!
+//! ```
+//! use graph_mvcc::{Graph, IdPolicy};
+//!
+//! let mut graph = Graph::new();
+//! let mut tx = graph.start_transaction();
+//! let a = graph.add_node(&mut tx);
+//! let b = graph.add_node(&mut tx);
+//! graph.add_edge(&mut tx, &a, &b, "red".to_string()).unwrap();
+//! graph.commit_transaction(&tx).unwrap();
+//!
+//! let reader = graph.start_read_transaction();
+//! let document = graph.export_document(&reader);
+//! let mut copy = Graph::import_document(document.clone(), IdPolicy::Preserve).unwrap();
+//!
+//! let reader = copy.start_read_transaction();
+//! assert_eq!(copy.export_document(&reader), document);
+//! ```
+
+mod dot;
+mod gexf;
+mod graphml;
+mod json;
+mod neo4j;
+mod xml;
+
+pub use dot::DotOptions;
+pub use neo4j::{BadRow, CsvReport, Neo4jImport};
+
+use std::collections::{BTreeMap, HashMap};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    BulkEdge, BulkItem, BulkNode, EdgeId, ElementId, Graph, Node, NodeId, PropertyValue, TransactionId, TxError,
+    TxResult,
+};
+
+/// Everything a transaction sees, see the module documentation.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct GraphDocument {
+    pub nodes: Vec<DocumentNode>,
+    pub edges: Vec<DocumentEdge>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DocumentNode {
+    pub id: NodeId,
+    #[serde(default)]
+    pub labels: Vec<String>,
+    #[serde(default)]
+    pub properties: BTreeMap<String, PropertyValue>,
+}
+
+/// An edge, directed from `source` to `target`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DocumentEdge {
+    pub id: EdgeId,
+    pub source: NodeId,
+    pub target: NodeId,
+    #[serde(rename = "type")]
+    pub edge_type: String,
+    #[serde(default)]
+    pub properties: BTreeMap<String, PropertyValue>,
+}
+
+/// Which ids an imported graph uses.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Default)]
+pub enum IdPolicy {
+    /// The ids of the document.
+    #[default]
+    Preserve,
+    /// Freshly minted ids; edges are rewired to the new node ids.
+    Regenerate,
+}
+
+impl Graph {
+    /// Copies the nodes and edges visible to `t`, with their labels and
+    /// properties, into a `GraphDocument`.
+    pub fn export_document(&self, t: &TransactionId) -> GraphDocument {
+        let mut nodes = self.visible_nodes(t);
+        nodes.sort_by(|a, b| sort_key(a.id()).cmp(sort_key(b.id())));
+
+        let mut document = GraphDocument::default();
+        for node in &nodes {
+            document.nodes.push(DocumentNode {
+                id: node.id().clone(),
+                labels: self.get_labels(t, node),
+                properties: self.get_properties(t, &ElementId::Node(node.id().clone())),
+            });
+            for (_, edge) in self.visible_edges(t, node) {
+                // Every edge is listed at both endpoints, keep it once.
+                if edge.source() != node.id() {
+                    continue;
+                }
+                document.edges.push(DocumentEdge {
+                    id: edge.id().clone(),
+                    source: edge.source().clone(),
+                    target: edge.target().clone(),
+                    edge_type: edge.edgetype().to_string(),
+                    properties: self.get_properties(t, &ElementId::Edge(edge.id().clone())),
+                });
+            }
+        }
+        document.edges.sort_by(|a, b| {
+            let (EdgeId::String(a), EdgeId::String(b)) = (&a.id, &b.id);
+            a.cmp(b)
+        });
+        document
+    }
+
+    /// Builds a new graph holding `document`, committed in a single bulk load.
+    pub fn import_document(document: GraphDocument, ids: IdPolicy) -> TxResult<Graph> {
+        let mut renamer = Renamer::new(ids);
+        let mut items = Vec::with_capacity(document.nodes.len() + document.edges.len());
+        for node in document.nodes {
+            items.push(BulkItem::Node(BulkNode {
+                id: Some(renamer.node(node.id)?),
+                labels: node.labels,
+                properties: node.properties.into_iter().collect(),
+            }));
+        }
+        for edge in document.edges {
+            let mut bulk = BulkEdge::new(renamer.endpoint(edge.source), renamer.endpoint(edge.target), &edge.edge_type);
+            bulk.id = renamer.edge(edge.id);
+            bulk.properties = edge.properties.into_iter().collect();
+            items.push(BulkItem::Edge(bulk));
+        }
+        load(items.into_iter().map(Ok)).map_err(|error| renamer.explain(error))
+    }
+}
+
+/// Maps the ids of an import to the ids of the new graph under an `IdPolicy`.
+/// Edges may name their endpoints before the nodes are declared.
+pub(crate) struct Renamer {
+    ids: IdPolicy,
+    // Original id to new id, and whether the node was declared
+    renamed: HashMap<NodeId, (NodeId, bool)>,
+}
+
+impl Renamer {
+    pub(crate) fn new(ids: IdPolicy) -> Self {
+        Renamer { ids, renamed: HashMap::new() }
+    }
+
+    /// The new id of a declared node. Declaring an id twice is a collision,
+    /// caught here as the bulk loader would not see it once ids are renamed.
+    pub(crate) fn node(&mut self, id: NodeId) -> TxResult<NodeId> {
+        let ids = self.ids;
+        let (new, declared) = self.renamed.entry(id.clone()).or_insert_with(|| match ids {
+            IdPolicy::Preserve => (id.clone(), false),
+            IdPolicy::Regenerate => (Node::new().id, false),
+        });
+        if *declared {
+            return Err(TxError::Collision(format!("node {} already exists", id)));
+        }
+        *declared = true;
+        Ok(new.clone())
+    }
+
+    pub(crate) fn declared(&self, id: &NodeId) -> bool {
+        self.renamed.get(id).is_some_and(|(_, declared)| *declared)
+    }
+
+    /// The new id of a node an edge refers to.
+    pub(crate) fn endpoint(&mut self, id: NodeId) -> NodeId {
+        match self.ids {
+            IdPolicy::Preserve => id,
+            IdPolicy::Regenerate => self.renamed.entry(id).or_insert_with(|| (Node::new().id, false)).0.clone(),
+        }
+    }
+
+    /// The id for an edge, `None` to have one minted.
+    pub(crate) fn edge(&self, id: EdgeId) -> Option<EdgeId> {
+        match self.ids {
+            IdPolicy::Preserve => Some(id),
+            IdPolicy::Regenerate => None,
+        }
+    }
+
+    /// Names the node of a failed load by its id in the import.
+    pub(crate) fn explain(&self, error: TxError) -> TxError {
+        match error {
+            TxError::NodeNotFound(id) => {
+                let original = self.renamed.iter().find(|(_, (new, _))| *new == id).map(|(original, _)| original.clone());
+                TxError::NodeNotFound(original.unwrap_or(id))
+            }
+            error => error,
+        }
+    }
+}
+
+/// Bulk loads `items` into a new graph.
+pub(crate) fn load(items: impl IntoIterator<Item = TxResult<BulkItem>>) -> TxResult<Graph> {
+    let mut graph = Graph::new();
+    graph.bulk_loader().try_load(items)?;
+    Ok(graph)
+}
+
+/// The property keys of a document, by name, with their key id and type.
+/// A key whose values differ in type is declared as a string.
+pub(crate) struct Keys {
+    pub(crate) node: BTreeMap<String, (String, &'static str)>,
+    pub(crate) edge: BTreeMap<String, (String, &'static str)>,
+}
+
+impl Keys {
+    pub(crate) fn of(document: &GraphDocument) -> Keys {
+        let node = declare("n", document.nodes.iter().flat_map(|node| &node.properties));
+        let edge = declare("e", document.edges.iter().flat_map(|edge| &edge.properties));
+        Keys { node, edge }
+    }
+}
+
+fn declare<'a>(
+    prefix: &str,
+    properties: impl Iterator<Item = (&'a String, &'a PropertyValue)>,
+) -> BTreeMap<String, (String, &'static str)> {
+    let mut types: BTreeMap<String, &'static str> = BTreeMap::new();
+    for (name, value) in properties {
+        let kind = types.entry(name.clone()).or_insert(value_type(value));
+        if *kind != value_type(value) {
+            *kind = "string";
+        }
+    }
+    types
+        .into_iter()
+        .enumerate()
+        .map(|(i, (name, kind))| (name, (format!("{}{}", prefix, i), kind)))
+        .collect()
+}
+
+/// The name of the type of `value`, the same in GraphML, GEXF and Neo4j's
+/// CSV headers.
+fn value_type(value: &PropertyValue) -> &'static str {
+    match value {
+        PropertyValue::Bool(_) => "boolean",
+        PropertyValue::Int(_) => "long",
+        PropertyValue::Float(_) => "double",
+        PropertyValue::String(_) => "string",
+    }
+}
+
+/// Reads `text` as a value of the declared `kind`. Types without a
+/// counterpart, such as lists, are kept as strings.
+pub(crate) fn parse_value(format: &str, name: &str, kind: &str, text: &str) -> TxResult<PropertyValue> {
+    let invalid = || TxError::invalid_import(format, format!("value '{}' of '{}' is not of type {}", text, name, kind));
+    let trimmed = text.trim();
+    match kind {
+        "boolean" => match trimmed.to_ascii_lowercase().as_str() {
+            "true" | "1" => Ok(PropertyValue::Bool(true)),
+            "false" | "0" => Ok(PropertyValue::Bool(false)),
+            _ => Err(invalid()),
+        },
+        "int" | "integer" | "long" | "short" | "byte" => trimmed.parse().map(PropertyValue::Int).map_err(|_| invalid()),
+        "float" | "double" => trimmed.parse().map(PropertyValue::Float).map_err(|_| invalid()),
+        _ => Ok(PropertyValue::String(text.to_string())),
+    }
+}
+
+pub(crate) fn sort_key(id: &NodeId) -> &str {
+    let NodeId::String(id) = id;
+    id
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Unit Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    /// A station named Central with a red edge of 4 minutes to another node.
+    fn sample() -> Graph {
+        let (mut graph, nodes) = testing::graph(2, &[(0, 1, "red")]);
+        testing::name(&mut graph, &nodes, &["Central"]);
+        testing::set_edge_property(&mut graph, "minutes", &[("red", 4)]);
+        let mut tx = graph.start_transaction();
+        graph.add_label(&mut tx, &nodes[0], "Station".to_string()).unwrap();
+        graph.commit_transaction(&tx).unwrap();
+        graph
+    }
+
+    #[test]
+    fn test_export_lists_the_visible_snapshot() {
+        let mut graph = sample();
+        let reader = graph.start_read_transaction();
+        let mut tx = graph.start_transaction();
+        let extra = graph.add_node(&mut tx);
+
+        let document = graph.export_document(&reader);
+        assert_eq!(document.nodes.len(), 2);
+        assert!(document.nodes.iter().all(|node| &node.id != extra.id()));
+        assert_eq!(document.edges.len(), 1);
+        assert_eq!(document.edges[0].edge_type, "red");
+        assert_eq!(document.edges[0].properties["minutes"], 4i64.into());
+        let source = document.nodes.iter().find(|node| node.id == document.edges[0].source).unwrap();
+        assert_eq!(source.labels, vec!["Station".to_string()]);
+    }
+
+    #[test]
+    fn test_self_loops_are_exported_once() {
+        let mut graph = Graph::new();
+        let mut tx = graph.start_transaction();
+        let a = graph.add_node(&mut tx);
+        graph.add_edge(&mut tx, &a, &a, "loop".to_string()).unwrap();
+        graph.commit_transaction(&tx).unwrap();
+
+        let reader = graph.start_read_transaction();
+        let document = graph.export_document(&reader);
+        assert_eq!(document.edges.len(), 1);
+        let mut copy = Graph::import_document(document.clone(), IdPolicy::Preserve).unwrap();
+        let copied = copy.start_read_transaction();
+        assert_eq!(copy.export_document(&copied), document);
+        assert_eq!(copy.traversal(&copied).v(a.id()).out("loop").to_list(), vec![a.id().clone()]);
+    }
+
+    #[test]
+    fn test_import_can_regenerate_ids() {
+        let mut graph = sample();
+        let reader = graph.start_read_transaction();
+        let document = graph.export_document(&reader);
+
+        let mut copy = Graph::import_document(document.clone(), IdPolicy::Regenerate).unwrap();
+        let reader = copy.start_read_transaction();
+        let imported = copy.export_document(&reader);
+        assert_eq!(imported.nodes.len(), 2);
+        assert!(imported.nodes.iter().all(|node| document.nodes.iter().all(|original| original.id != node.id)));
+        let edge = &imported.edges[0];
+        let source = imported.nodes.iter().find(|node| node.id == edge.source).unwrap();
+        assert_eq!(source.properties["name"], "Central".into());
+        assert_eq!(edge.properties["minutes"], 4i64.into());
+    }
+
+    #[test]
+    fn test_import_rejects_dangling_edges() {
+        let mut document = GraphDocument::default();
+        document.edges.push(DocumentEdge {
+            id: EdgeId::String("e".to_string()),
+            source: NodeId::String("a".to_string()),
+            target: NodeId::String("b".to_string()),
+            edge_type: "red".to_string(),
+            properties: BTreeMap::new(),
+        });
+        let missing = Err(TxError::NodeNotFound(NodeId::String("a".to_string())));
+        assert_eq!(Graph::import_document(document.clone(), IdPolicy::Regenerate).map(|_| ()), missing);
+        assert_eq!(Graph::import_document(document, IdPolicy::Preserve).map(|_| ()), missing);
+    }
+}
@@ -0,0 +1,533 @@
+//! CSV in the layout of `neo4j-admin database import`: node files with an
+//! `:ID` column, an optional `:LABEL` column of `;`-separated labels and
+//! typed property columns, and relationship files with `:START_ID`,
+//! `:END_ID` and `:TYPE` columns:
+//!
+//! ```text
+//! stationId:ID,:LABEL,name:string,platforms:int
+//! a,Station;Stop,Central,4
+//! b,Station,North,
+//!
+//! :START_ID,:END_ID,:TYPE,minutes:double
+//! a,b,red,2.5
+//! ```
+//!
+//! Node files are read before relationship files, each as a stream into the
+//! bulk loader. A row that can't be read, names an unknown node, repeats an
+//! id or breaks the collision rule is a bad row: it is skipped and reported,
+//! until there are more than `bad_tolerance` of them and the import fails.
+//! That rule lets a node start an edge of a type only if its edges of that
+//! type, in either direction, all join it to the same node, so fan-out of
+//! one type is rejected: after `a,b,KNOWS`, the rows `a,c,KNOWS` and
+//! `b,c,KNOWS` are bad rows.
+//!
+//! Empty fields are missing values, ID spaces such as `:ID(Station)` are
+//! ignored, so ids must be unique across files, and array and temporal
+//! values are kept as strings. The export writes the same layout; it does
+//! not keep edge ids.
+//!
+//! ```
+//! use graph_mvcc::{IdPolicy, Neo4jImport};
+//!
+//! let nodes = "id:ID,:LABEL,name\na,Station,Central\nb,Station,North\nc,Station\n";
+//! let relationships = ":START_ID,:END_ID,:TYPE\na,b,red\nb,x,blue\n";
+//! let (graph, report) = Neo4jImport::new()
+//!     .nodes("stations.csv", nodes.as_bytes())
+//!     .relationships("lines.csv", relationships.as_bytes())
+//!     .ids(IdPolicy::Regenerate)
+//!     .bad_tolerance(10)
+//!     .run()
+//!     .unwrap();
+//!
+//! assert_eq!((report.nodes, report.relationships), (2, 1));
+//! assert_eq!(report.bad_rows[1].to_string(), "lines.csv line 3: unknown end node 'x'");
+//! ```
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt::{self, Display};
+use std::io::{Read, Write};
+
+use csv::{ReaderBuilder, StringRecord, Writer};
+
+use super::{load, parse_value, GraphDocument, IdPolicy, Keys, Renamer};
+use crate::{BulkEdge, BulkItem, BulkNode, Graph, NodeId, TransactionId, TxError, TxResult};
+
+const NAME: &str = "CSV";
+/// Property types of the header, next to the column keywords.
+const TYPES: [&str; 9] = ["int", "long", "short", "byte", "float", "double", "boolean", "string", "char"];
+/// Types without a `PropertyValue` counterpart.
+const STRING_TYPES: [&str; 7] = ["date", "time", "localtime", "datetime", "localdatetime", "duration", "point"];
+
+/// A row the import skipped.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct BadRow {
+    pub file: String,
+    pub line: u64,
+    pub message: String,
+}
+
+impl Display for BadRow {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(f, "{} line {}: {}", self.file, self.line, self.message)
+    }
+}
+
+/// What an import loaded and skipped.
+#[derive(Debug, Clone, Eq, PartialEq, Default)]
+pub struct CsvReport {
+    pub nodes: usize,
+    pub relationships: usize,
+    pub bad_rows: Vec<BadRow>,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum Kind {
+    Nodes,
+    Relationships,
+}
+
+struct Source<'a> {
+    name: String,
+    kind: Kind,
+    reader: Box<dyn Read + 'a>,
+}
+
+/// Reads Neo4j admin-import CSV files into a new graph, see the module
+/// documentation.
+pub struct Neo4jImport<'a> {
+    sources: Vec<Source<'a>>,
+    ids: IdPolicy,
+    bad_tolerance: usize,
+}
+
+impl Default for Neo4jImport<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> Neo4jImport<'a> {
+    pub fn new() -> Self {
+        Neo4jImport {
+            sources: Vec::new(),
+            ids: IdPolicy::Preserve,
+            bad_tolerance: 0,
+        }
+    }
+
+    /// Adds a node file; `name` is used in reports.
+    pub fn nodes(mut self, name: &str, reader: impl Read + 'a) -> Self {
+        self.sources.push(Source { name: name.to_string(), kind: Kind::Nodes, reader: Box::new(reader) });
+        self
+    }
+
+    /// Adds a relationship file; `name` is used in reports.
+    pub fn relationships(mut self, name: &str, reader: impl Read + 'a) -> Self {
+        self.sources.push(Source { name: name.to_string(), kind: Kind::Relationships, reader: Box::new(reader) });
+        self
+    }
+
+    pub fn ids(mut self, ids: IdPolicy) -> Self {
+        self.ids = ids;
+        self
+    }
+
+    /// How many bad rows are skipped before the import fails. By default the
+    /// first one fails it.
+    pub fn bad_tolerance(mut self, rows: usize) -> Self {
+        self.bad_tolerance = rows;
+        self
+    }
+
+    pub fn run(mut self) -> TxResult<(Graph, CsvReport)> {
+        // All nodes have to be known before the relationships are checked
+        self.sources.sort_by_key(|source| source.kind == Kind::Relationships);
+        let mut rows = Rows {
+            sources: self.sources.into(),
+            current: None,
+            renamer: Renamer::new(self.ids),
+            neighbors: Neighbors::new(),
+            bad_tolerance: self.bad_tolerance,
+            report: CsvReport::default(),
+            done: false,
+        };
+        let graph = load(&mut rows).map_err(|error| rows.renamer.explain(error))?;
+        Ok((graph, rows.report))
+    }
+}
+
+/// A column of a header.
+#[derive(Debug, Clone, PartialEq)]
+enum Column {
+    /// The id, also stored under the property name if there is one.
+    Id(Option<String>),
+    Label,
+    Type,
+    StartId,
+    EndId,
+    Ignore,
+    Property(String, String),
+}
+
+fn column(field: &str) -> Column {
+    let Some((name, suffix)) = field.rsplit_once(':') else {
+        return Column::Property(field.to_string(), "string".to_string());
+    };
+    // Drop the ID space of ":ID(Station)"
+    let keyword = suffix.split('(').next().unwrap_or(suffix);
+    match keyword {
+        "ID" => Column::Id(Some(name.to_string()).filter(|name| !name.is_empty())),
+        "LABEL" => Column::Label,
+        "TYPE" => Column::Type,
+        "START_ID" => Column::StartId,
+        "END_ID" => Column::EndId,
+        "IGNORE" => Column::Ignore,
+        kind if TYPES.contains(&kind) => Column::Property(name.to_string(), kind.to_string()),
+        // Arrays, dates, points and the like are kept as strings
+        kind if TYPES.contains(&kind.trim_end_matches("[]")) || STRING_TYPES.contains(&kind.trim_end_matches("[]")) => {
+            Column::Property(name.to_string(), "string".to_string())
+        }
+        // Not a type, so part of the name
+        _ => Column::Property(field.to_string(), "string".to_string()),
+    }
+}
+
+/// A file being read, with its parsed header.
+struct Open<'a> {
+    file: String,
+    kind: Kind,
+    reader: csv::Reader<Box<dyn Read + 'a>>,
+    columns: Vec<Column>,
+}
+
+/// The nodes each node is joined to by the relationships read so far, by
+/// relationship type.
+type Neighbors = HashMap<(NodeId, String), HashSet<NodeId>>;
+
+/// The bulk items of the files, read row by row as they are pulled.
+struct Rows<'a> {
+    sources: VecDeque<Source<'a>>,
+    current: Option<Open<'a>>,
+    renamer: Renamer,
+    neighbors: Neighbors,
+    bad_tolerance: usize,
+    report: CsvReport,
+    done: bool,
+}
+
+impl Rows<'_> {
+    fn step(&mut self) -> TxResult<Option<BulkItem>> {
+        let mut record = StringRecord::new();
+        loop {
+            let Some(Open { file, kind, reader, columns }) = self.current.as_mut() else {
+                let Some(source) = self.sources.pop_front() else {
+                    return Ok(None);
+                };
+                let mut reader = ReaderBuilder::new().from_reader(source.reader);
+                let header = reader.headers().map_err(|error| invalid(&source.name, error))?;
+                let columns: Vec<Column> = header.iter().map(column).collect();
+                check_header(&source.name, source.kind, &columns)?;
+                self.current = Some(Open { file: source.name, kind: source.kind, reader, columns });
+                continue;
+            };
+
+            let (file, kind) = (file.clone(), *kind);
+            let row = match reader.read_record(&mut record) {
+                Ok(false) => {
+                    self.current = None;
+                    continue;
+                }
+                Ok(true) => {
+                    let line = record.position().map_or(0, |position| position.line());
+                    match kind {
+                        Kind::Nodes => node(&mut self.renamer, columns, &record),
+                        Kind::Relationships => relationship(&mut self.renamer, &mut self.neighbors, columns, &record),
+                    }
+                    .map_err(|message| (line, message))
+                }
+                Err(error) if error.is_io_error() => return Err(invalid(&file, error)),
+                Err(error) => {
+                    let line = error.position().map_or(0, |position| position.line());
+                    Err((line, error.to_string()))
+                }
+            };
+
+            match row {
+                Ok(item) => {
+                    match item {
+                        BulkItem::Node(_) => self.report.nodes += 1,
+                        BulkItem::Edge(_) => self.report.relationships += 1,
+                    }
+                    return Ok(Some(item));
+                }
+                Err((line, message)) => {
+                    let bad = BadRow { file, line, message };
+                    if self.report.bad_rows.len() >= self.bad_tolerance {
+                        return Err(TxError::invalid_import(NAME, bad.to_string()));
+                    }
+                    self.report.bad_rows.push(bad);
+                }
+            }
+        }
+    }
+}
+
+impl Iterator for Rows<'_> {
+    type Item = TxResult<BulkItem>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let item = self.step().transpose();
+        self.done = !matches!(item, Some(Ok(_)));
+        item
+    }
+}
+
+fn invalid(file: &str, error: csv::Error) -> TxError {
+    TxError::InvalidImport {
+        format: NAME.to_string(),
+        message: format!("{}: {}", file, error),
+        source: Some(std::sync::Arc::new(error)),
+    }
+}
+
+fn check_header(file: &str, kind: Kind, columns: &[Column]) -> TxResult<()> {
+    let count = |wanted: &Column| columns.iter().filter(|column| std::mem::discriminant(*column) == std::mem::discriminant(wanted)).count();
+    let required: &[(Column, &str)] = match kind {
+        Kind::Nodes => &[(Column::Id(None), ":ID")],
+        Kind::Relationships => &[(Column::StartId, ":START_ID"), (Column::EndId, ":END_ID"), (Column::Type, ":TYPE")],
+    };
+    for (wanted, name) in required {
+        if count(wanted) != 1 {
+            return Err(TxError::invalid_import(NAME, format!("{}: the header needs one {} column", file, name)));
+        }
+    }
+    Ok(())
+}
+
+/// The message of a value that did not parse.
+fn message(error: TxError) -> String {
+    match error {
+        TxError::InvalidImport { message, .. } => message,
+        error => error.to_string(),
+    }
+}
+
+fn node(renamer: &mut Renamer, columns: &[Column], record: &StringRecord) -> Result<BulkItem, String> {
+    let mut node = BulkNode::new();
+    let mut id = None;
+    for (column, field) in columns.iter().zip(record.iter()) {
+        match column {
+            Column::Id(name) => {
+                if let Some(name) = name {
+                    node.properties.push((name.clone(), field.into()));
+                }
+                id = Some(NodeId::String(field.to_string())).filter(|_| !field.is_empty());
+            }
+            Column::Label => node.labels.extend(field.split(';').filter(|label| !label.is_empty()).map(str::to_string)),
+            Column::Property(name, kind) if !field.is_empty() => {
+                node.properties.push((name.clone(), parse_value(NAME, name, kind, field).map_err(message)?));
+            }
+            _ => {}
+        }
+    }
+    let id = id.ok_or_else(|| "missing id".to_string())?;
+    node.id = Some(renamer.node(id).map_err(message)?);
+    Ok(BulkItem::Node(node))
+}
+
+fn relationship(
+    renamer: &mut Renamer,
+    neighbors: &mut Neighbors,
+    columns: &[Column],
+    record: &StringRecord,
+) -> Result<BulkItem, String> {
+    let (mut start, mut end, mut edge_type) = ("", "", "");
+    let mut properties = Vec::new();
+    for (column, field) in columns.iter().zip(record.iter()) {
+        match column {
+            Column::StartId => start = field,
+            Column::EndId => end = field,
+            Column::Type => edge_type = field,
+            Column::Property(name, kind) if !field.is_empty() => {
+                properties.push((name.clone(), parse_value(NAME, name, kind, field).map_err(message)?));
+            }
+            _ => {}
+        }
+    }
+    if edge_type.is_empty() {
+        return Err("missing type".to_string());
+    }
+    for (end, id) in [("start", start), ("end", end)] {
+        if !renamer.declared(&NodeId::String(id.to_string())) {
+            return Err(format!("unknown {} node '{}'", end, id));
+        }
+    }
+    let source = renamer.endpoint(NodeId::String(start.to_string()));
+    let target = renamer.endpoint(NodeId::String(end.to_string()));
+    // The bulk loader checks collisions like `add_edge` does, only after
+    // all rows are in, where a collision fails the whole load
+    let key = (source.clone(), edge_type.to_string());
+    if neighbors.get(&key).is_some_and(|joined| joined.iter().any(|node| *node != target)) {
+        return Err(format!("node '{}' already has a {} relationship to another node", start, edge_type));
+    }
+    neighbors.entry(key).or_default().insert(target.clone());
+    neighbors.entry((target.clone(), edge_type.to_string())).or_default().insert(source.clone());
+    let mut edge = BulkEdge::new(source, target, edge_type);
+    edge.properties = properties;
+    Ok(BulkItem::Edge(edge))
+}
+
+impl GraphDocument {
+    /// Writes the nodes and the relationships of the document as two files
+    /// for `neo4j-admin database import`.
+    pub fn write_neo4j_csv(&self, nodes: impl Write, relationships: impl Write) -> TxResult<()> {
+        self.neo4j_csv(nodes, relationships).map_err(|error| TxError::database_failure("writing CSV", error))
+    }
+
+    fn neo4j_csv(&self, nodes: impl Write, relationships: impl Write) -> csv::Result<()> {
+        let keys = Keys::of(self);
+        let header = |fixed: &[&str], keys: &std::collections::BTreeMap<String, (String, &str)>| {
+            let mut header: Vec<String> = fixed.iter().map(|column| column.to_string()).collect();
+            header.extend(keys.iter().map(|(name, (_, kind))| format!("{}:{}", name, kind)));
+            header
+        };
+
+        let mut writer = Writer::from_writer(nodes);
+        writer.write_record(header(&[":ID", ":LABEL"], &keys.node))?;
+        for node in &self.nodes {
+            let mut row = vec![node.id.to_string(), node.labels.join(";")];
+            row.extend(keys.node.keys().map(|name| node.properties.get(name).map_or(String::new(), |value| value.to_string())));
+            writer.write_record(row)?;
+        }
+        writer.flush()?;
+
+        let mut writer = Writer::from_writer(relationships);
+        writer.write_record(header(&[":START_ID", ":END_ID", ":TYPE"], &keys.edge))?;
+        for edge in &self.edges {
+            let mut row = vec![edge.source.to_string(), edge.target.to_string(), edge.edge_type.clone()];
+            row.extend(keys.edge.keys().map(|name| edge.properties.get(name).map_or(String::new(), |value| value.to_string())));
+            writer.write_record(row)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+impl Graph {
+    /// Writes the snapshot of `t` as Neo4j admin-import node and
+    /// relationship files.
+    pub fn export_neo4j_csv(&self, t: &TransactionId, nodes: impl Write, relationships: impl Write) -> TxResult<()> {
+        self.export_document(t).write_neo4j_csv(nodes, relationships)
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Unit Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ElementId, ErrorKind};
+
+    #[test]
+    fn test_csv_round_trip() {
+        let mut graph = Graph::new();
+        let mut tx = graph.start_transaction();
+        let a = graph.add_node(&mut tx);
+        let b = graph.add_node(&mut tx);
+        graph.add_label(&mut tx, &a, "Station".to_string()).unwrap();
+        graph.add_label(&mut tx, &a, "Stop".to_string()).unwrap();
+        let element = ElementId::Node(a.id().clone());
+        graph.set_property(&mut tx, element.clone(), "name".to_string(), "Central, \"old\"".into()).unwrap();
+        graph.set_property(&mut tx, element, "platforms".to_string(), 4i64.into()).unwrap();
+        graph.add_edge(&mut tx, &a, &b, "red".to_string()).unwrap();
+        let (_, edge) = graph.visible_edges(&tx, &a).remove(0);
+        graph.set_property(&mut tx, ElementId::Edge(edge.id().clone()), "minutes".to_string(), 2.5f64.into()).unwrap();
+        graph.commit_transaction(&tx).unwrap();
+
+        let reader = graph.start_read_transaction();
+        let (mut nodes, mut relationships) = (Vec::new(), Vec::new());
+        graph.export_neo4j_csv(&reader, &mut nodes, &mut relationships).unwrap();
+        let (mut copy, report) = Neo4jImport::new()
+            .relationships("relationships.csv", relationships.as_slice())
+            .nodes("nodes.csv", nodes.as_slice())
+            .run()
+            .unwrap();
+        assert_eq!(report, CsvReport { nodes: 2, relationships: 1, bad_rows: Vec::new() });
+
+        let copied = copy.start_read_transaction();
+        let (original, imported) = (graph.export_document(&reader), copy.export_document(&copied));
+        assert_eq!(imported.nodes, original.nodes);
+        let strip = |document: &GraphDocument| document.edges.iter().map(|edge| (edge.source.clone(), edge.target.clone(), edge.edge_type.clone(), edge.properties.clone())).collect::<Vec<_>>();
+        assert_eq!(strip(&imported), strip(&original));
+    }
+
+    #[test]
+    fn test_bad_rows_are_reported() {
+        let nodes = "id:ID,:LABEL,age:int\na,Person,30\nb,Person,old\na,Person,31\n,Person,1\nc,Person,2,extra\nd,Person,\n";
+        let relationships = ":START_ID,:END_ID,:TYPE\na,d,KNOWS\na,zed,KNOWS\nd,a,\n";
+        let (mut graph, report) = Neo4jImport::new()
+            .nodes("people.csv", nodes.as_bytes())
+            .relationships("knows.csv", relationships.as_bytes())
+            .bad_tolerance(10)
+            .run()
+            .unwrap();
+
+        assert_eq!((report.nodes, report.relationships), (2, 1));
+        let bad: Vec<String> = report.bad_rows.iter().map(BadRow::to_string).collect();
+        assert_eq!(bad[0], "people.csv line 3: value 'old' of 'age' is not of type int");
+        assert_eq!(bad[1], "people.csv line 4: Collision: node a already exists");
+        assert_eq!(bad[2], "people.csv line 5: missing id");
+        assert!(bad[3].starts_with("people.csv line 6: "));
+        assert_eq!(bad[4], "knows.csv line 3: unknown end node 'zed'");
+        assert_eq!(bad[5], "knows.csv line 4: missing type");
+
+        let reader = graph.start_read_transaction();
+        let a = NodeId::String("a".to_string());
+        assert_eq!(graph.traversal(&reader).v(&a).out("KNOWS").to_list(), vec![NodeId::String("d".to_string())]);
+        let document = graph.export_document(&reader);
+        assert_eq!(document.nodes[0].properties["id"], "a".into());
+        assert_eq!(document.nodes[0].properties["age"], 30i64.into());
+    }
+
+    #[test]
+    fn test_fan_out_of_one_type_is_a_bad_row() {
+        let nodes = "id:ID\na\nb\nc\n";
+        let relationships = ":START_ID,:END_ID,:TYPE\na,b,KNOWS\na,c,KNOWS\nb,c,KNOWS\nb,c,LIKES\n";
+        for ids in [IdPolicy::Preserve, IdPolicy::Regenerate] {
+            let (_, report) = Neo4jImport::new()
+                .nodes("people.csv", nodes.as_bytes())
+                .relationships("knows.csv", relationships.as_bytes())
+                .ids(ids)
+                .bad_tolerance(2)
+                .run()
+                .unwrap();
+            assert_eq!(report.relationships, 2);
+            let bad: Vec<String> = report.bad_rows.iter().map(BadRow::to_string).collect();
+            assert_eq!(bad[0], "knows.csv line 3: node 'a' already has a KNOWS relationship to another node");
+            // Being the end node of a KNOWS relationship counts as well
+            assert_eq!(bad[1], "knows.csv line 4: node 'b' already has a KNOWS relationship to another node");
+        }
+
+        let error = Neo4jImport::new()
+            .nodes("people.csv", nodes.as_bytes())
+            .relationships("knows.csv", relationships.as_bytes())
+            .run()
+            .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidImport);
+    }
+
+    #[test]
+    fn test_intolerable_rows_fail_the_import() {
+        let nodes = "id:ID,age:int\na,1\nb,two\n";
+        let error = Neo4jImport::new().nodes("people.csv", nodes.as_bytes()).run().unwrap_err();
+        assert_eq!(error, TxError::invalid_import(NAME, "people.csv line 3: value 'two' of 'age' is not of type int"));
+
+        let relationships = ":START_ID,:TYPE\na,KNOWS\n";
+        let error = Neo4jImport::new().relationships("knows.csv", relationships.as_bytes()).run().unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidImport);
+        assert_eq!(error.to_string(), "Invalid CSV import: knows.csv: the header needs one :END_ID column");
+    }
+}
@@ -0,0 +1,127 @@
+//! What GraphML and GEXF share: labels kept under a reserved key, attribute
+//! helpers, and a pull parser that turns the file into bulk items one
+//! element at a time, so a file is never held in memory next to the graph
+//! built from it.
+
+use std::borrow::Cow;
+use std::io::BufRead;
+use std::sync::Arc;
+
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+
+use super::{load, Renamer};
+use crate::{BulkItem, Graph, TxError, TxResult};
+
+/// The key holding a node's labels, written `:A:B` as Neo4j's exports do.
+pub(crate) const LABELS: &str = "labels";
+/// The type of an edge the file gives none.
+pub(crate) const DEFAULT_EDGE_TYPE: &str = "edge";
+
+pub(crate) fn join_labels(labels: &[String]) -> String {
+    labels.iter().map(|label| format!(":{}", label)).collect()
+}
+
+pub(crate) fn split_labels(text: &str) -> Vec<String> {
+    text.split(':').map(str::trim).filter(|label| !label.is_empty()).map(str::to_string).collect()
+}
+
+pub(crate) fn escape(text: &str) -> Cow<'_, str> {
+    quick_xml::escape::escape(text)
+}
+
+fn invalid(format: &str, error: impl std::error::Error + Send + Sync + 'static) -> TxError {
+    TxError::InvalidImport {
+        format: format.to_string(),
+        message: error.to_string(),
+        source: Some(Arc::new(error)),
+    }
+}
+
+/// The unescaped value of the attribute `name`, if the element has it.
+pub(crate) fn attribute(format: &str, element: &BytesStart, name: &str) -> TxResult<Option<String>> {
+    match element.try_get_attribute(name).map_err(|error| invalid(format, error))? {
+        Some(attribute) => {
+            let value = attribute.unescape_value().map_err(|error| invalid(format, error))?;
+            Ok(Some(value.into_owned()))
+        }
+        None => Ok(None),
+    }
+}
+
+pub(crate) fn required(format: &str, element: &BytesStart, name: &str) -> TxResult<String> {
+    let tag = String::from_utf8_lossy(element.local_name().as_ref()).into_owned();
+    attribute(format, element, name)?
+        .ok_or_else(|| TxError::invalid_import(format, format!("<{}> without a '{}' attribute", tag, name)))
+}
+
+/// The element-by-element reading of one format.
+pub(crate) trait XmlFormat {
+    const NAME: &'static str;
+
+    fn start(&mut self, element: &BytesStart) -> TxResult<()>;
+    fn text(&mut self, text: &str);
+    /// Called with the local name of every closed element; returns the node
+    /// or edge it completes.
+    fn end(&mut self, name: &[u8]) -> TxResult<Option<BulkItem>>;
+    fn renamer(&self) -> &Renamer;
+}
+
+/// The bulk items of an XML file, parsed as they are pulled.
+struct XmlItems<R, F> {
+    reader: Reader<R>,
+    buffer: Vec<u8>,
+    format: F,
+    done: bool,
+}
+
+impl<R: BufRead, F: XmlFormat> XmlItems<R, F> {
+    fn step(&mut self) -> TxResult<Option<BulkItem>> {
+        loop {
+            self.buffer.clear();
+            let event = self.reader.read_event_into(&mut self.buffer).map_err(|error| invalid(F::NAME, error))?;
+            match event {
+                Event::Start(element) => self.format.start(&element)?,
+                Event::Empty(element) => {
+                    self.format.start(&element)?;
+                    if let Some(item) = self.format.end(element.local_name().as_ref())? {
+                        return Ok(Some(item));
+                    }
+                }
+                Event::Text(text) => self.format.text(&text.unescape().map_err(|error| invalid(F::NAME, error))?),
+                Event::CData(text) => self.format.text(&String::from_utf8_lossy(&text)),
+                Event::End(element) => {
+                    if let Some(item) = self.format.end(element.local_name().as_ref())? {
+                        return Ok(Some(item));
+                    }
+                }
+                Event::Eof => return Ok(None),
+                _ => {}
+            }
+        }
+    }
+}
+
+impl<R: BufRead, F: XmlFormat> Iterator for XmlItems<R, F> {
+    type Item = TxResult<BulkItem>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+        let item = self.step().transpose();
+        self.done = !matches!(item, Some(Ok(_)));
+        item
+    }
+}
+
+/// Parses `reader` with `format` straight into the bulk loader of a new graph.
+pub(crate) fn import<F: XmlFormat>(reader: impl BufRead, format: F) -> TxResult<Graph> {
+    let mut items = XmlItems {
+        reader: Reader::from_reader(reader),
+        buffer: Vec::new(),
+        format,
+        done: false,
+    };
+    load(&mut items).map_err(|error| items.format.renamer().explain(error))
+}
@@ -17,6 +17,8 @@
 //! edges within a transaction:
 //!
 //! ```
+//!     use graph_mvcc::Graph;
+//!
 //!     let mut graph = Graph::new();
 //!     let mut tx = graph.start_transaction();
 //!
@@ -42,48 +44,58 @@
 
 use std::fmt::{self, Display};
 
+use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 use std::hash::Hash;
+use std::cell::RefCell;
 use std::collections::BTreeSet;
 use std::collections::BTreeMap;
-use std::collections::VecDeque;
 use std::collections::{HashMap, HashSet};
 
-////////////////////////////////////////////////////////////////////////////////
-// Error handling for transactions
-pub type TxResult<T> = Result<T, TxError>;
-
-#[derive(Debug, PartialEq)]
-pub enum TxError {
-    Abort,
-    DatabaseFailure,
-    NodeNotFound,
-    ElementNotFound,
-    Collision(String),
-    InvalidRecord,
-    TransactionLocked,
-}
+pub mod analytics;
+pub mod bulk;
+pub mod changes;
+pub mod conductor;
+pub mod error;
+pub mod cypher;
+pub mod hooks;
+pub mod interchange;
+pub mod locking;
+pub mod predicates;
+pub mod readonly;
+pub mod routing;
+pub mod records;
+pub mod retry;
+pub mod savepoints;
+pub mod system;
+#[cfg(test)]
+mod testing;
+pub mod timeouts;
+pub mod traversal;
 
-impl Display for TxError {
-    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
-        match *self {
-            TxError::Abort => write!(f, "Transaction Aborted"),
-            TxError::DatabaseFailure => write!(f, "Database failure"),
-            TxError::NodeNotFound => write!(f, "Node not found"),
-            TxError::ElementNotFound => write!(f, "Element not found"),
-            TxError::Collision(ref msg) => write!(f, "Collision: {}", msg),
-            TxError::InvalidRecord => write!(f, "Invalid record"),
-            TxError::TransactionLocked => write!(f, "Transaction locked"),
-        }
-    }
-}
+pub use analytics::GraphSnapshot;
+pub use bulk::{BulkEdge, BulkItem, BulkLoader, BulkNode, BulkPhase, BulkProgress, BulkReport};
+pub use changes::{ChangeEvent, PropertyChange, RecordChange};
+pub use conductor::{Conductor, DeterministicConductor, HybridLogicalClock, LocalConductor};
+pub use error::{Conflict, ErrorKind, ErrorSource, Resource, TxError, TxResult};
+pub use hooks::HookId;
+pub use interchange::{BadRow, CsvReport, DocumentEdge, DocumentNode, DotOptions, GraphDocument, IdPolicy, Neo4jImport};
+pub use locking::{LockKey, LockManager, LockMode, LockWait, LockingMode, SharedGraph, NODE_LOCK};
+pub use predicates::{IsolationLevel, PredicateLock};
+pub use readonly::ReadTransaction;
+pub use records::{RecordHandle, RecordStore, RecordVersion};
+pub use retry::RetryPolicy;
+pub use routing::{Route, Router};
+pub use system::{SystemAction, SystemEvent};
+pub use timeouts::TRANSACTION_EXPIRATION_IN_SECONDS;
+pub use traversal::{Direction, TraversalSource, TypePath};
 
 ////////////////////////////////////////////////////////////////////////////////
-/// Possibility
-/// A `Possibility` is a data structure that holds a commit record in one
-/// of the three __states__ listed in Commit Record State: waiting, 
-/// complete, or aborted. The lifetime of the Possiblity is assigned
-/// upon it's creation.
+// Possibility
+// A `Possibility` is a data structure that holds a commit record in one
+// of the three __states__ listed in Commit Record State: waiting,
+// complete, or aborted. The lifetime of the Possiblity is assigned
+// upon it's creation.
 /*
 enum CRState {
     WAITING,
@@ -93,7 +105,7 @@ enum CRState {
 */
 
 ////////////////////////////////////////////////////////////////////////////////
-/// TODO:
+// TODO:
 /*
     1. Substitute unwrap with own ErrorType.
     2. Develop test case for 1 billion nodes.
@@ -101,7 +113,8 @@ enum CRState {
 
 ////////////////////////////////////////////////////////////////////////////////
 // Graph Related
-#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(untagged)]
 pub enum EdgeId {
     String(String),
 }
@@ -110,26 +123,58 @@ pub enum EdgeId {
 pub struct Edge {
     pub id: EdgeId,
     edgetype: String,
+    source: NodeId,
+    target: NodeId,
 }
 
 impl Edge {
-    fn new(typ: String) -> Self {
+    fn new(typ: String, source: NodeId, target: NodeId) -> Self {
         Edge {
             id: EdgeId::String(Uuid::new_v4().to_string().chars().take(8).collect()),
             edgetype: typ,
+            source,
+            target,
         }
     }
     pub fn id(&self) -> &EdgeId {
         &self.id
     
     }
+    pub fn edgetype(&self) -> &str {
+        &self.edgetype
+    }
+    /// The node the edge was drawn from. Edges are stored in the adjacency
+    /// list of both endpoints, so this is what tells the direction apart.
+    pub fn source(&self) -> &NodeId {
+        &self.source
+    }
+    pub fn target(&self) -> &NodeId {
+        &self.target
+    }
 }
 
-#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+#[derive(Debug, Clone, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(untagged)]
 pub enum NodeId {
     String(String),
 }
 
+impl Display for NodeId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match self {
+            NodeId::String(id) => write!(f, "{}", id),
+        }
+    }
+}
+
+impl Display for EdgeId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match self {
+            EdgeId::String(id) => write!(f, "{}", id),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub struct Node {
     id: NodeId,
@@ -148,11 +193,26 @@ impl Node {
 
 #[derive(Debug, Clone)]
 pub struct Graph {
-    nodes: HashMap<Node, HashSet<Edge>>,
+    nodes: HashMap<Node, Version>,
     adjacencylist: HashMap<Node, Vec<(Node, Edge)>>,
-    next_transaction_id: u32,
-    active_transactions: BTreeSet<u32>,
-    records: BTreeSet<BTreeMap<MVCC, u32>>,
+    edges: HashMap<EdgeId, (Edge, Version)>,
+    labels: HashMap<NodeId, Vec<(String, Version)>>,
+    properties: HashMap<ElementId, BTreeMap<String, Vec<(PropertyValue, Version)>>>,
+    conductor: Box<dyn Conductor>,
+    // Commit timestamp of every committed txid from `commit_horizon` on
+    commit_timestamps: BTreeMap<u64, u64>,
+    // Every txid below this has ended, see `prune_commit_timestamps`
+    commit_horizon: u64,
+    records: records::RecordTable,
+    store: BTreeMap<String, Vec<RecordVersion>>,
+    changes: changes::ChangeFeed,
+    writes: changes::WriteLog,
+    hooks: hooks::Hooks,
+    timeouts: timeouts::Timeouts,
+    locks: locking::Locks,
+    readers: readonly::Readers,
+    checkpoint: Option<system::Checkpoint>,
+    system_log: Vec<SystemEvent>,
 }
 
 
@@ -164,21 +224,45 @@ impl Default for Graph {
 }
 
 impl Graph {
-    pub fn txid_current(self) -> u32 {
-        self.next_transaction_id
+    pub fn txid_current(self) -> u64 {
+        self.conductor.last_issued()
     }
 
     pub fn new() -> Self {
+        Self::with_conductor(LocalConductor::new())
+    }
+
+    /// A graph whose txids and commit order are issued by `conductor`.
+    pub fn with_conductor(conductor: impl Conductor + 'static) -> Self {
         Self {
             nodes: HashMap::new(),
             adjacencylist: HashMap::new(),
-            next_transaction_id : 0,
-            active_transactions : BTreeSet::new(),
-            records : BTreeSet::new(),
+            edges: HashMap::new(),
+            labels: HashMap::new(),
+            properties: HashMap::new(),
+            conductor : Box::new(conductor),
+            commit_timestamps : BTreeMap::new(),
+            commit_horizon : system::FIRST_TXID,
+            records : records::RecordTable::default(),
+            store : BTreeMap::new(),
+            changes : changes::ChangeFeed::default(),
+            writes : changes::WriteLog::default(),
+            hooks : hooks::Hooks::default(),
+            timeouts : timeouts::Timeouts::default(),
+            locks : locking::Locks::default(),
+            readers : readonly::Readers::default(),
+            checkpoint : None,
+            system_log : Vec::new(),
         }
     }
 
+    /// A transaction that is no longer active gets a node that is never
+    /// written; its commit fails anyway.
     pub fn add_node(&mut self, t: &mut TransactionId) -> Node {
+        if self.check_active(t).is_err() {
+            return Node::new();
+        }
+
         // Ensure transaction has snapshot for isolation
         if t.snapshot.is_none() {
             t.snapshot = Some(self.create_snapshot(t));
@@ -186,7 +270,8 @@ impl Graph {
         
         let minted_node = Node::new();
         let node = minted_node.clone();
-        self.nodes.entry(minted_node).or_insert_with(HashSet::new);
+        self.nodes.insert(minted_node, Version::new(t.txid));
+        self.writes.push(t, UndoAction::CreateNode(node.clone()));
 
         // Create read lock for node creation
         t.read_locks.insert((node.id().clone(), "NODE_CREATION".to_string()));
@@ -195,6 +280,14 @@ impl Graph {
     }
     
     pub fn add_edge(&mut self, t: &mut TransactionId, from: &Node, to: &Node, edge_type: String) -> TxResult<()> {
+        self.add_edge_internal(t, from, to, edge_type).map(|_| ())
+    }
+
+    /// Same as `add_edge`, but hands back the minted edge so callers that
+    /// need to refer to it afterwards (e.g. the query executor) can do so.
+    pub(crate) fn add_edge_internal(&mut self, t: &mut TransactionId, from: &Node, to: &Node, edge_type: String) -> TxResult<Edge> {
+        self.check_active(t)?;
+
         // Ensure transaction has snapshot for isolation
         if t.snapshot.is_none() {
             t.snapshot = Some(self.create_snapshot(t));
@@ -202,19 +295,32 @@ impl Graph {
         
         // Check for collision: if an edge of this type already exists from this node
         // (but not to the same destination, since that would be a duplicate edge)
-        if self.has_collision_excluding_destination(from, to, &edge_type) {
+        if self.has_collision_excluding_destination(t, from, to, &edge_type) {
             return Err(TxError::Collision(format!("edge type '{}' already exists for source node", edge_type)));
         }
         
+        // In pessimistic mode both endpoints are locked for the edge type
+        self.lock_for_write(t, &Self::edge_lock_keys(from, to, &edge_type))?;
+
         // Create read locks for both nodes and the specific edge type
         t.read_locks.insert((from.id().clone(), edge_type.clone()));
         t.read_locks.insert((to.id().clone(), edge_type.clone()));
         
-        let minted_edge = Edge::new(edge_type);
-        self.set_directed_edge(from, to, minted_edge.clone());
-        self.set_directed_edge(to, from, minted_edge);
+        let minted_edge = Edge::new(edge_type, from.id().clone(), to.id().clone());
+        self.edges.insert(minted_edge.id().clone(), (minted_edge.clone(), Version::new(t.txid)));
+        self.list_edge(from, to, minted_edge.clone());
+        self.writes.push(t, UndoAction::CreateEdge(minted_edge.id().clone()));
         
-        Ok(())
+        Ok(minted_edge)
+    }
+
+    /// Lists `edge` in the adjacency lists of both its endpoints, and a
+    /// self-loop once in that of its only one.
+    pub(crate) fn list_edge(&mut self, from: &Node, to: &Node, edge: Edge) {
+        if from != to {
+            self.set_directed_edge(from, to, edge.clone());
+        }
+        self.set_directed_edge(to, from, edge);
     }
 
     pub fn set_directed_edge(&mut self, from: &Node, to: &Node, edge: Edge) {
@@ -239,17 +345,23 @@ impl Graph {
         self.traverse_with_snapshot(t, origin, search_path)
     }
     
-    fn traverse_with_snapshot(&self, t: &TransactionId, origin: &Node, search_path: Vec<String>) -> Vec<Node> {
-        // For now, use the existing traversal mechanism
-        // In a full implementation, this would filter the adjacency list based on the snapshot
-        let type_path = TypePath { 
-            graph: self, 
-            current_node: Some(origin.clone()),
-            type_list: search_path,
-            path_list: VecDeque::new(),
-        };
-        
-        type_path.into_iter().collect()
+    fn traverse_with_snapshot(&self, t: &TransactionId, origin: &Node, mut search_path: Vec<String>) -> Vec<Node> {
+        // The edge types are taken from the back of the search path. At
+        // every node only the first visible edge is looked at, and the walk
+        // stops where its type is not the one asked for.
+        let mut found = Vec::new();
+        let mut current = origin.clone();
+        while let Some(edge_type) = search_path.pop() {
+            t.register_predicate(PredicateLock::new(current.id().clone(), Some(edge_type.clone()), Direction::Both));
+            match self.visible_edges(t, &current).into_iter().next() {
+                Some((next, edge)) if edge.edgetype == edge_type => {
+                    found.push(next.clone());
+                    current = next;
+                }
+                _ => break,
+            }
+        }
+        found
     }
     
 }
@@ -279,72 +391,75 @@ pub enum MVCC {
 /// to prevent transaction IDs from being reused.
 #[derive(Debug, Clone)]
 pub struct TransactionId {
-    pub txid: u32,
-    pub rollback_actions: BTreeSet<BTreeMap<MVCC, u32>>,
+    pub txid: u64,
+    /// Writes committed before this point in time are visible.
+    pub snapshot_ts: u64,
+    /// Transactions that were active when this one started. Their writes
+    /// stay invisible even once they commit.
+    pub in_flight: BTreeSet<u64>,
+    /// Undo log: one entry per write, in the order the writes happened.
+    pub rollback_actions: Vec<UndoAction>,
+    /// Named positions in the undo log, see `savepoint`.
+    pub savepoints: Vec<(String, usize)>,
     pub read_locks: HashSet<(NodeId, String)>, // (node_id, edge_type)
-    pub snapshot: Option<BTreeSet<BTreeMap<MVCC, u32>>>, // Cached snapshot for this transaction
+    pub isolation: IsolationLevel,
+    /// Neighborhoods scanned by traversals, see `PredicateLock`.
+    predicate_locks: RefCell<HashSet<PredicateLock>>,
+    pub snapshot: Option<BTreeSet<BTreeMap<MVCC, u64>>>, // Cached snapshot for this transaction
 }
 impl TransactionId {
-    pub fn new(txid: u32) -> Self {
+    pub fn new(txid: u64) -> Self {
         TransactionId {
             txid,
-            rollback_actions: BTreeSet::new(),
+            snapshot_ts: txid,
+            in_flight: BTreeSet::new(),
+            rollback_actions: Vec::new(),
+            savepoints: Vec::new(),
             read_locks: HashSet::new(),
+            isolation: IsolationLevel::Snapshot,
+            predicate_locks: RefCell::new(HashSet::new()),
             snapshot: None,
         }
     }  
 }
 
+/// A single write of a transaction, recorded so that it can be undone. Graph
+/// elements are identified by what the transaction wrote; label and property
+/// entries refer to the latest version `txid` created or expired under that
+/// key.
+#[derive(Debug, Clone, PartialEq)]
+pub enum UndoAction {
+    CreateNode(Node),
+    ExpireNode(Node),
+    CreateEdge(EdgeId),
+    ExpireEdge(EdgeId),
+    AddLabel(NodeId, String),
+    ExpireLabel(NodeId, String),
+    SetProperty(ElementId, String),
+    ExpireProperty(ElementId, String),
+    CreateRecord(RecordHandle),
+    ExpireRecord(RecordHandle),
+    PutRecord(String),
+    RemoveRecord(String),
+}
+
 /* impl Drop for TransactionId {
 
 } */
 
 /* pub trait Transactable {
-    transaction_creation_id: u32,
-    transaction_expiration_id: u32,
+    transaction_creation_id: u64,
+    transaction_expiration_id: u64,
 }
  */
-pub struct TypePath<'graph> {
-    /// TypePath represents a traversal of the graph based on the sequence of types 
-    /// leading from a starting node, through all adjancent nodes connected via edges
-    /// matching the sequence of types in the type path.
-    // TODO: write an example of using TypePath.
-    graph: &'graph Graph,
-    current_node: Option<Node>,
-    // TODO: Improve naming of type_list and path_list variables.
-    type_list: Vec<String>,
-    path_list: VecDeque<Node>,
-}
-impl<'graph> Iterator for TypePath<'graph> {
-    type Item = Node;
-
-    fn next(&mut self) -> Option<Node> {
-        if let Some(node) = self.current_node.take() {
-            let edge_list = self.graph.adjacencylist.get(&node)?;
-            
-            if let Some(current_type) = self.type_list.pop() {
-                if let Some((node,edge)) = edge_list.iter().next() {
-                    if edge.edgetype == current_type {
-                        self.path_list.push_back(node.clone());
-                        self.current_node = Some(node.clone());
-                        return Some(node.clone());
-                    }
-                }
-            }
-        }
-        
-        self.current_node = None;
-        None
-    }
-}
-
 ////////////////////////////////////////////////////////////////////////////////
 // MCC Related
 impl Graph {
     /// A new transaction is initialized with a unique identifier, `txid`, that
     /// is issued by the graph's `Conductor` trait.
     /// 
-    /// The txid is a number between 3 and u32/2. The first three txid numbers
+    /// The txid is a 64-bit number starting at 3, so it does not wrap around
+    /// within the lifetime of a deployment. The first three txid numbers
     /// are reserved for system signaling of particular actions:  
     /// txid.0 == reset  
     /// txid.1 == reboot  
@@ -358,18 +473,73 @@ impl Graph {
     /// transaction was created. 
     // pub fn start_transaction(&mut self) -> Result<TransactionId, MVCCError::TransactionInitializationFailed> {        
     pub fn start_transaction(&mut self) -> TransactionId {        
-        // The Conductor increments its atomic counter by one and issues the
-        // next number to the transaction.
-        self.next_transaction_id += 1;
-        // The new transaction is tracked as being `alive` by adding its
-        // txid to the Conductor's list of active transactions.
-        self.active_transactions.insert(self.next_transaction_id);
+        // Transactions that ran out of time no longer count as active.
+        self.reap_expired_transactions();
+
+        // The Conductor issues the next number to the transaction and tracks
+        // it as being `alive` in its list of active transactions.
+        let in_flight = self.conductor.active().clone();
+        let txid = self.conductor.begin();
+        self.timeouts.start(txid);
         
         // A new transaction is spawned and provided its own unique txid that
-        // will be assigned to it during its entire lifecycle.
-        TransactionId::new(self.next_transaction_id)
+        // will be assigned to it during its entire lifecycle. Txids and
+        // commit timestamps come from the same clock, so the txid doubles
+        // as the snapshot timestamp.
+        let mut t = TransactionId::new(txid);
+        t.in_flight = in_flight;
+        t
     }
 
+    /// The commit timestamp of `txid`, if it has committed and a transaction
+    /// older than it is still active, see `prune_commit_timestamps`.
+    pub fn commit_timestamp(&self, txid: u64) -> Option<u64> {
+        self.commit_timestamps.get(&txid).copied()
+    }
+
+    /// Whether the writes of `txid` belong to the snapshot of `t`: `txid`
+    /// committed before `t` started and was not in flight at the time.
+    fn committed_before(&self, t: &TransactionId, txid: u64) -> bool {
+        if t.in_flight.contains(&txid) {
+            return false;
+        }
+        match self.commit_timestamps.get(&txid) {
+            Some(ts) => *ts < t.snapshot_ts,
+            // Ended and pruned: it committed before `t` started unless it
+            // started after `t`
+            None => self.ended_before_horizon(txid) && txid < t.snapshot_ts,
+        }
+    }
+
+    /// Whether `txid` committed after `t` started, i.e. it wrote something
+    /// `t` cannot see.
+    fn committed_after(&self, t: &TransactionId, txid: u64) -> bool {
+        txid != t.txid
+            && (self.commit_timestamps.contains_key(&txid) || self.ended_before_horizon(txid))
+            && !self.committed_before(t, txid)
+    }
+
+    fn ended_before_horizon(&self, txid: u64) -> bool {
+        (system::FIRST_TXID..self.commit_horizon).contains(&txid)
+    }
+
+    /// Drops the commit timestamps of the txids below the oldest active one.
+    /// Those transactions have all ended, and aborted ones leave no versions
+    /// behind, so a txid below the horizon found on a version committed. Its
+    /// timestamp is not needed either: a transaction that was in flight when
+    /// `t` started is in `t.in_flight`, and any other one committed before
+    /// `t` started if and only if its txid is below `t`'s snapshot, as txids
+    /// and commit timestamps come from the same clock.
+    fn prune_commit_timestamps(&mut self) {
+        let horizon = match self.conductor.active().first() {
+            Some(&oldest) => oldest,
+            None => self.conductor.last_issued().saturating_add(1),
+        };
+        if horizon > self.commit_horizon {
+            self.commit_horizon = horizon;
+            self.commit_timestamps = self.commit_timestamps.split_off(&horizon);
+        }
+    }
 
     /// Transactions need to have an expiration date - a hard time cap after
     /// which filed commitments from the transaction are no longer accepted
@@ -378,21 +548,12 @@ impl Graph {
     /// the Conductor:
     /// TRANSACTION_EXPIRATION_IN_SECONDS 900
     ///
-    // TODO: Describe exactly how set_transaction_expiration works.
-    pub fn set_transaction_expiration(&mut self, pos: u32, n:u32) {
-        let mut i:u32 = 0;
-        
-        for item in &self.records {
-            if i == pos {
-                let mut updated_item = item.clone();
-                updated_item.insert(MVCC::TransactionExpired, n);
-                // Note: This is a simplified approach - proper implementation would need
-                // to replace the item in the BTreeSet
-                break;
-                // break;
-            } else {
-                i+=1;
-            }
+    /// The cap itself is enforced by the reaper, see `set_transaction_timeout`.
+    /// This stamps `MVCC::TransactionExpired` with `n` on the record version
+    /// behind `handle`.
+    pub fn set_transaction_expiration(&mut self, handle: RecordHandle, n:u64) {
+        if let Some(record) = self.records.get_mut(handle) {
+            record.insert(MVCC::TransactionExpired, n);
         }
     }
 
@@ -401,7 +562,8 @@ impl Graph {
     /// an atomic unit of data. Most importantly here is that a record cannot 
     /// be simultaneously modified by two separate clients/transactions. 
     /// If you have a complex data structure you will want to make sure what you 
-    /// define as a record does not encapsulate too much.
+    /// define as a record does not encapsulate too much. Records with a typed
+    /// payload are kept in the record store, see `record_store`.
     /// A Universally Unique Identifier (UUID).
     ///
     /// # Example
@@ -458,47 +620,53 @@ impl Graph {
     /// # ABI
     ///
     /// The `Uuid` type is always guaranteed to be have the same ABI as [`Bytes`].
-    pub fn add_record(&mut self, t: &mut TransactionId, record: &mut BTreeMap<MVCC, u32>) {
+    ///
+    /// A transaction that is no longer active gets a handle to nothing; its
+    /// commit fails anyway.
+    pub fn add_record(&mut self, t: &mut TransactionId, record: &mut BTreeMap<MVCC, u64>) -> RecordHandle {
+        if self.check_active(t).is_err() {
+            return self.records.reserve();
+        }
         record.insert(MVCC::TransactionCreationId, t.txid);
         record.insert(MVCC::TransactionExpirationId, 0);
 
-        let mut action:BTreeMap<MVCC, u32> = BTreeMap::new();
-        action.insert(MVCC::DeleteElementFromTransaction, self.records.len() as u32);
-        t.rollback_actions.insert(action);
-        
-        self.records.insert(record.clone());
+        let handle = self.records.insert(record.clone());
+        self.writes.push(t, UndoAction::CreateRecord(handle));
+        handle
+    }
+
+    /// The record version behind `handle`.
+    pub fn record(&self, handle: RecordHandle) -> Option<&BTreeMap<MVCC, u64>> {
+        self.records.get(handle)
     }
 
     /// If expired_xid is true (does not have the value 0) then that means the
     /// record is an element of a transaction that is active.
     /// If the expired_xid is false (has the value 0)
-    pub fn delete_record(&mut self, t: &mut TransactionId, id: u32) -> TxResult<()> {
+    pub fn delete_record(&mut self, t: &mut TransactionId, id: u64) -> TxResult<()> {
+        self.check_active(t)?;
         let mut records_to_update = Vec::new();
         
-        for (i, record) in self.records.iter().enumerate() {
-            if let Some(element_id) = record.get(&MVCC::ElementId) {
-                if self.record_is_visible(t, &record) && element_id == &id {
-                    if self.row_is_locked(&record) {
-                        return Err(TxError::TransactionLocked);
-                    } else {
-                        records_to_update.push((i, record.clone()));
-                    }
+        // Only the version chain of `id` needs looking at
+        for (handle, record) in self.records.chain(id) {
+            if self.record_is_visible(t, record) {
+                if let Some(holder) = self.row_locked_by(t, record) {
+                    return Err(TxError::TransactionLocked(Conflict::new(t.txid).with(holder).on(Resource::Record(id))));
+                } else {
+                    records_to_update.push(handle);
                 }
             }
         }
         
         if records_to_update.is_empty() {
-            return Err(TxError::ElementNotFound);
+            return Err(TxError::ElementNotFound(Resource::Record(id)));
         }
         
-        for (i, mut record) in records_to_update {
-            record.insert(MVCC::TransactionExpirationId, t.txid);
-            
-            let mut new_rec: BTreeMap<MVCC,u32> = BTreeMap::new();
-            new_rec.insert(MVCC::AddElementToTransaction, i as u32);
-            t.rollback_actions.insert(new_rec);
-            
-            self.records.replace(record);
+        for handle in records_to_update {
+            if let Some(record) = self.records.get_mut(handle) {
+                record.insert(MVCC::TransactionExpirationId, t.txid);
+                self.writes.push(t, UndoAction::ExpireRecord(handle));
+            }
         }
         
         Ok(())
@@ -507,45 +675,61 @@ impl Graph {
     /// The visibility of a record depends on who is looking at it.
     /// We have to test each record that a particular transaction wants to modify, 
     /// to check if the transaction can see it.
-    fn record_is_visible(&self, t: &TransactionId, record: &BTreeMap<MVCC, u32>) -> bool {
-        if let Some(creation_id) = record.get(&MVCC::TransactionCreationId) {
-            if self.active_transactions.contains(creation_id) && creation_id != &t.txid {
+    /// A record is visible when it was created by `t` or by a transaction
+    /// that committed before `t` started, and has not been expired by either.
+    fn record_is_visible(&self, t: &TransactionId, record: &BTreeMap<MVCC, u64>) -> bool {
+        if let Some(&creation_id) = record.get(&MVCC::TransactionCreationId) {
+            if creation_id != t.txid && !self.committed_before(t, creation_id) {
                 return false;
             }
         }
     
-        if let Some(expiration_id) = record.get(&MVCC::TransactionExpirationId) {
-            if expiration_id != &0 {
-                if !self.active_transactions.contains(expiration_id) || 
-                   record.get(&MVCC::TransactionCreationId) == Some(&t.txid) {
-                    return false;
-                }
+        if let Some(&expiration_id) = record.get(&MVCC::TransactionExpirationId) {
+            if expiration_id != 0 && (expiration_id == t.txid || self.committed_before(t, expiration_id)) {
+                return false;
             }
         }
         
         true
     }
     
-    fn row_is_locked(&self, record: &BTreeMap<MVCC, u32>) -> bool {
-        if let Some(expiration_id) = record.get(&MVCC::TransactionExpirationId) {
-            expiration_id != &0 && self.active_transactions.contains(expiration_id)
-        } else {
-            false
-        }
+    /// Another transaction is expiring the record, or already expired it
+    /// after `t` started.
+    fn row_is_locked(&self, t: &TransactionId, record: &BTreeMap<MVCC, u64>) -> bool {
+        self.row_locked_by(t, record).is_some()
+    }
+
+    /// The transaction that holds `record` locked against `t`.
+    fn row_locked_by(&self, t: &TransactionId, record: &BTreeMap<MVCC, u64>) -> Option<u64> {
+        record.get(&MVCC::TransactionExpirationId).copied().filter(|&expiration_id| {
+            expiration_id != 0 && expiration_id != t.txid
+                && (self.conductor.active().contains(&expiration_id) || self.committed_after(t, expiration_id))
+        })
     }
 
-    pub fn update_record(&mut self, t: &mut TransactionId, id:u32, _num:String) -> TxResult<()> {
+    /// Replaces the visible version of record `id` with a new one holding
+    /// `value`, see `record_value`. Nothing is written if the record cannot
+    /// be expired.
+    pub fn update_record(&mut self, t: &mut TransactionId, id:u64, value:String) -> TxResult<()> {
         self.delete_record(t, id)?;
-        let mut new_modification_version: BTreeMap<MVCC,u32> = BTreeMap::new();
+        let mut new_modification_version: BTreeMap<MVCC,u64> = BTreeMap::new();
         new_modification_version.insert(MVCC::ElementId, id);
-        self.add_record(t, &mut new_modification_version);
+        let handle = self.add_record(t, &mut new_modification_version);
+        self.records.set_value(handle, value);
         Ok(())
     }
 
-    fn create_snapshot(&self, t: &TransactionId) -> BTreeSet<BTreeMap<MVCC, u32>> {
+    /// The value `update_record` gave the version of record `id` visible to
+    /// `t`, if it was written by an update.
+    pub fn record_value(&self, t: &TransactionId, id: u64) -> Option<String> {
+        let (handle, _) = self.records.chain(id).filter(|(_, record)| self.record_is_visible(t, record)).last()?;
+        self.records.value(handle).map(str::to_string)
+    }
+
+    fn create_snapshot(&self, t: &TransactionId) -> BTreeSet<BTreeMap<MVCC, u64>> {
         let mut visible_modifications = BTreeSet::new();
         
-        for records in self.records.iter() {
+        for (_, records) in self.records.iter() {
             if self.record_is_visible(t, records) {
                 visible_modifications.insert(records.clone());
             }
@@ -555,14 +739,49 @@ impl Graph {
     }
 
     pub fn commit_transaction(&mut self, t: &TransactionId) -> TxResult<()> {
+        // Commitments filed after the deadline are no longer accepted
+        self.reap_expired_transactions();
+        self.check_active(t)?;
+        
         // Check for conflicts on read locks
-        if self.has_read_lock_conflicts(t) {
+        if let Some(conflict) = self.read_lock_conflict(t) {
             let _ = self.rollback_transaction(t);
-            return Err(TxError::Abort);
+            return Err(TxError::Abort(conflict));
+        }
+
+        // Serializable transactions also fail on phantoms in the
+        // neighborhoods they scanned
+        if t.isolation == IsolationLevel::Serializable {
+            if let Some(conflict) = self.find_phantom(t) {
+                let _ = self.rollback_transaction(t);
+                return Err(TxError::Abort(conflict));
+            }
         }
         
-        // Commit successful - remove from active transactions
-        self.active_transactions.remove(&t.txid);
+        // Pre-commit hooks still see the transaction as active and may veto.
+        // Without subscribers or hooks there is no one to build the event for.
+        let changes = if self.wants_changes() {
+            self.collect_changes(t)
+        } else {
+            ChangeEvent { txid: t.txid, ..ChangeEvent::default() }
+        };
+        if let Err(veto) = self.run_pre_commit_hooks(t, &changes) {
+            let _ = self.rollback_transaction(t);
+            return Err(veto);
+        }
+        
+        // Commit successful - the Conductor removes it from the active
+        // transactions and orders it among the other commits
+        let commit_ts = self.conductor.commit(t.txid);
+        self.commit_timestamps.insert(t.txid, commit_ts);
+        self.prune_commit_timestamps();
+        self.writes.finish(t.txid);
+        self.timeouts.finish(t.txid);
+        self.locks.manager.release_all(t.txid);
+        // Let change data capture subscribers and post-commit hooks know
+        // what the commit changed
+        let changes = self.publish_changes(ChangeEvent { commit_ts, ..changes });
+        self.run_post_commit_hooks(&changes);
         Ok(())
     }
     
@@ -570,21 +789,23 @@ impl Graph {
         self.rollback_transaction(t)
     }
 
-    fn has_read_lock_conflicts(&self, t: &TransactionId) -> bool {
+    fn read_lock_conflict(&self, t: &TransactionId) -> Option<Conflict> {
         // Check if any read locks have been violated by other committed transactions
         for (node_id, edge_type) in &t.read_locks {
             // Check if any other committed transaction has modified this node+edge_type combination
             // since this transaction started
-            if self.has_conflicting_write(t, node_id, edge_type) {
-                return true;
+            if let Some(writer) = self.conflicting_write(t, node_id, edge_type) {
+                let key = (node_id.clone(), edge_type.clone());
+                return Some(Conflict::new(t.txid).with(writer).on(Resource::Lock(key)));
             }
         }
-        false
+        None
     }
     
-    fn has_conflicting_write(&self, t: &TransactionId, node_id: &NodeId, edge_type: &str) -> bool {
+    /// The txid of a transaction that committed a conflicting write.
+    fn conflicting_write(&self, t: &TransactionId, node_id: &NodeId, edge_type: &str) -> Option<u64> {
         // Check if any transaction with id > t.txid has committed changes to this node+edge_type
-        for record in &self.records {
+        for (_, record) in self.records.iter() {
             if let (Some(&creation_id), Some(&expiration_id)) = 
                 (record.get(&MVCC::TransactionCreationId), record.get(&MVCC::TransactionExpirationId)) {
                 
@@ -595,40 +816,447 @@ impl Graph {
                 
                 // Check if this record represents a write to our read-locked resource
                 // and was committed after our transaction started
-                if creation_id > t.txid && expiration_id == 0 {
+                if self.committed_after(t, creation_id) && expiration_id == 0 {
                     // This is a committed write that happened after our transaction started
                     // For now, we'll assume conflict - in a full implementation, we'd need
                     // to check if this record actually affects the specific node+edge_type
-                    return true;
+                    return Some(creation_id);
                 }
             }
         }
-        false
+        None
     }
 
     fn rollback_transaction(&mut self, t: &TransactionId) -> TxResult<()> {
-        // FIX: it's hardly efficient to iterate twice over rollback_actions.
+        // Walk the undo log backwards, newest write first.
         for action in t.rollback_actions.iter().rev() {
-            let mut map = action.iter();
-            if let Some((action_type, action_position)) = map.next() {
-                // TODO: check if it's possible to get out of this clone()
-                let pos:u32 = *action_position;
-                
-                match action_type {
-                    &MVCC::AddElementToTransaction =>                
-                        self.set_transaction_expiration(pos, 0),
-                    &MVCC::DeleteElementFromTransaction => 
-                            self.set_transaction_expiration(pos, t.txid),
-                    _ => return Err(TxError::InvalidRecord)
-                }
-            }
-        } 
+            self.undo(t.txid, action);
+        }
         
-        self.active_transactions.remove(&t.txid);
+        // Element writes made through a copy of `t` (e.g. via `IGraph`) are
+        // not in its undo log, so sweep for anything left by the txid.
+        self.rollback_graph_elements(t.txid);
+        self.conductor.abort(t.txid);
+        self.writes.finish(t.txid);
+        self.timeouts.finish(t.txid);
+        self.locks.manager.release_all(t.txid);
         Ok(())
     }
 }
 
+////////////////////////////////////////////////////////////////////////////////
+// Versioned Graph Elements
+
+/// Every node, edge, label and property value carries the txid of the
+/// transaction that created it and the txid of the transaction that expired
+/// it, exactly like the `TransactionCreationId`/`TransactionExpirationId`
+/// pair stored on records. An `expiration_id` of 0 means the element is live.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash, Default)]
+pub struct Version {
+    pub creation_id: u64,
+    pub expiration_id: u64,
+}
+
+impl Version {
+    fn new(txid: u64) -> Self {
+        Version {
+            creation_id: txid,
+            expiration_id: 0,
+        }
+    }
+}
+
+/// A value stored under a property key on a node or an edge. It is
+/// serialized as the bare JSON value.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum PropertyValue {
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    String(String),
+}
+
+impl Display for PropertyValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match self {
+            PropertyValue::Bool(b) => write!(f, "{}", b),
+            PropertyValue::Int(i) => write!(f, "{}", i),
+            PropertyValue::Float(x) => write!(f, "{}", x),
+            PropertyValue::String(s) => write!(f, "{}", s),
+        }
+    }
+}
+
+impl From<bool> for PropertyValue {
+    fn from(b: bool) -> Self {
+        PropertyValue::Bool(b)
+    }
+}
+
+impl From<i64> for PropertyValue {
+    fn from(i: i64) -> Self {
+        PropertyValue::Int(i)
+    }
+}
+
+impl From<f64> for PropertyValue {
+    fn from(x: f64) -> Self {
+        PropertyValue::Float(x)
+    }
+}
+
+impl From<&str> for PropertyValue {
+    fn from(s: &str) -> Self {
+        PropertyValue::String(s.to_string())
+    }
+}
+
+impl From<String> for PropertyValue {
+    fn from(s: String) -> Self {
+        PropertyValue::String(s)
+    }
+}
+
+/// Identifies the owner of a property: either a node or an edge.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub enum ElementId {
+    Node(NodeId),
+    Edge(EdgeId),
+}
+
+impl Graph {
+    /// An element version is visible to `t` when it was created by `t` itself
+    /// or by a transaction that committed before `t` started, and it has not
+    /// been expired by either.
+    fn version_is_visible(&self, t: &TransactionId, version: &Version) -> bool {
+        let in_snapshot = |txid: u64| txid == t.txid || self.committed_before(t, txid);
+        let created = in_snapshot(version.creation_id);
+        let expired = version.expiration_id != 0 && in_snapshot(version.expiration_id);
+        created && !expired
+    }
+
+    /// The element version equivalent of `row_is_locked`: another transaction
+    /// that is still active, or that committed after `t` started, has either
+    /// created or expired this version.
+    fn version_is_locked(&self, t: &TransactionId, version: &Version) -> bool {
+        self.version_locked_by(t, version).is_some()
+    }
+
+    /// The transaction that holds `version` locked against `t`.
+    fn version_locked_by(&self, t: &TransactionId, version: &Version) -> Option<u64> {
+        let foreign = |txid: &u64| *txid != 0 && *txid != t.txid
+            && (self.conductor.active().contains(txid) || self.committed_after(t, *txid));
+        [version.creation_id, version.expiration_id].into_iter().find(foreign)
+    }
+
+    /// The first of `versions` locked against `t`, as the conflict it causes.
+    fn locked_version<'v>(&self, t: &TransactionId, mut versions: impl Iterator<Item = &'v Version>, resource: Resource) -> TxResult<()> {
+        match versions.find_map(|version| self.version_locked_by(t, version)) {
+            Some(holder) => Err(TxError::TransactionLocked(Conflict::new(t.txid).with(holder).on(resource))),
+            None => Ok(()),
+        }
+    }
+
+    fn edge_is_removed_for(&self, t: &TransactionId, edge: &Edge) -> bool {
+        match self.edges.get(edge.id()) {
+            Some((_, version)) => version.expiration_id != 0
+                && (version.expiration_id == t.txid || self.committed_before(t, version.expiration_id)),
+            None => true,
+        }
+    }
+
+    pub fn node_is_visible(&self, t: &TransactionId, node: &Node) -> bool {
+        self.nodes.get(node).is_some_and(|version| self.version_is_visible(t, version))
+    }
+
+    pub fn edge_is_visible(&self, t: &TransactionId, edge: &Edge) -> bool {
+        self.edges.get(edge.id()).is_some_and(|(_, version)| self.version_is_visible(t, version))
+    }
+
+    /// Looks up an edge by id as seen by `t`.
+    pub fn visible_edge(&self, t: &TransactionId, edge_id: &EdgeId) -> Option<Edge> {
+        match self.edges.get(edge_id) {
+            Some((edge, version)) if self.version_is_visible(t, version) => Some(edge.clone()),
+            _ => None,
+        }
+    }
+
+    /// Looks up a node by id as seen by `t`.
+    pub fn visible_node(&self, t: &TransactionId, node_id: &NodeId) -> Option<Node> {
+        let node = Node { id: node_id.clone() };
+        if self.node_is_visible(t, &node) {
+            Some(node)
+        } else {
+            None
+        }
+    }
+
+    /// All nodes visible to `t`, in no particular order.
+    pub fn visible_nodes(&self, t: &TransactionId) -> Vec<Node> {
+        self.nodes
+            .iter()
+            .filter(|(_, version)| self.version_is_visible(t, version))
+            .map(|(node, _)| node.clone())
+            .collect()
+    }
+
+    /// The edges incident to `node` that are visible to `t`, paired with the
+    /// node at the other end. Use `Edge::source` to tell the direction.
+    pub fn visible_edges(&self, t: &TransactionId, node: &Node) -> Vec<(Node, Edge)> {
+        match self.adjacencylist.get(node) {
+            Some(edges) => edges
+                .iter()
+                .filter(|(other, edge)| self.edge_is_visible(t, edge) && self.node_is_visible(t, other))
+                .cloned()
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Expires `node` together with every edge attached to it.
+    pub fn remove_node(&mut self, t: &mut TransactionId, node: &Node) -> TxResult<()> {
+        self.check_active(t)?;
+        let version = *self.nodes.get(node).ok_or_else(|| TxError::NodeNotFound(node.id().clone()))?;
+        if !self.version_is_visible(t, &version) {
+            return Err(TxError::NodeNotFound(node.id().clone()));
+        }
+        self.locked_version(t, [&version].into_iter(), Resource::Node(node.id().clone()))?;
+        self.lock_for_write(t, &[(node.id().clone(), "NODE_DELETION".to_string())])?;
+
+        for (_, edge) in self.visible_edges(t, node) {
+            self.remove_edge(t, &edge)?;
+        }
+
+        t.read_locks.insert((node.id().clone(), "NODE_DELETION".to_string()));
+        if let Some(version) = self.nodes.get_mut(node) {
+            version.expiration_id = t.txid;
+            self.writes.push(t, UndoAction::ExpireNode(node.clone()));
+        }
+        Ok(())
+    }
+
+    pub fn remove_edge(&mut self, t: &mut TransactionId, edge: &Edge) -> TxResult<()> {
+        self.check_active(t)?;
+        let not_found = || TxError::ElementNotFound(Resource::Edge(edge.id().clone()));
+        let version = self.edges.get(edge.id()).ok_or_else(not_found)?.1;
+        if !self.version_is_visible(t, &version) {
+            return Err(not_found());
+        }
+        self.locked_version(t, [&version].into_iter(), Resource::Edge(edge.id().clone()))?;
+        self.lock_for_write(t, &[
+            (edge.source().clone(), edge.edgetype.clone()),
+            (edge.target().clone(), edge.edgetype.clone()),
+        ])?;
+
+        t.read_locks.insert((edge.source().clone(), edge.edgetype.clone()));
+        t.read_locks.insert((edge.target().clone(), edge.edgetype.clone()));
+        if let Some((_, version)) = self.edges.get_mut(edge.id()) {
+            version.expiration_id = t.txid;
+            self.writes.push(t, UndoAction::ExpireEdge(edge.id().clone()));
+        }
+        Ok(())
+    }
+
+    pub fn add_label(&mut self, t: &mut TransactionId, node: &Node, label: String) -> TxResult<()> {
+        self.check_active(t)?;
+        if !self.node_is_visible(t, node) {
+            return Err(TxError::NodeNotFound(node.id().clone()));
+        }
+        if self.get_labels(t, node).contains(&label) {
+            return Ok(());
+        }
+        self.lock_for_write(t, &[(node.id().clone(), "NODE_LABELS".to_string())])?;
+
+        t.read_locks.insert((node.id().clone(), "NODE_LABELS".to_string()));
+        self.labels
+            .entry(node.id().clone())
+            .or_default()
+            .push((label.clone(), Version::new(t.txid)));
+        self.writes.push(t, UndoAction::AddLabel(node.id().clone(), label));
+        Ok(())
+    }
+
+    pub fn remove_label(&mut self, t: &mut TransactionId, node: &Node, label: &str) -> TxResult<()> {
+        self.check_active(t)?;
+        if !self.node_is_visible(t, node) {
+            return Err(TxError::NodeNotFound(node.id().clone()));
+        }
+        let versions = match self.labels.get(node.id()) {
+            Some(versions) => versions,
+            None => return Ok(()),
+        };
+        let labelled = versions.iter().filter(|(l, _)| l == label).map(|(_, version)| version);
+        self.locked_version(t, labelled, Resource::Label(node.id().clone(), label.to_string()))?;
+        let expire: Vec<usize> = versions
+            .iter()
+            .enumerate()
+            .filter(|(_, (l, version))| l == label && self.version_is_visible(t, version))
+            .map(|(i, _)| i)
+            .collect();
+        self.lock_for_write(t, &[(node.id().clone(), "NODE_LABELS".to_string())])?;
+
+        t.read_locks.insert((node.id().clone(), "NODE_LABELS".to_string()));
+        if let Some(versions) = self.labels.get_mut(node.id()) {
+            for i in expire {
+                versions[i].1.expiration_id = t.txid;
+                self.writes.push(t, UndoAction::ExpireLabel(node.id().clone(), label.to_string()));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn get_labels(&self, t: &TransactionId, node: &Node) -> Vec<String> {
+        match self.labels.get(node.id()) {
+            Some(versions) => versions
+                .iter()
+                .filter(|(_, version)| self.version_is_visible(t, version))
+                .map(|(label, _)| label.clone())
+                .collect(),
+            None => Vec::new(),
+        }
+    }
+
+    fn element_is_visible(&self, t: &TransactionId, element: &ElementId) -> bool {
+        match element {
+            ElementId::Node(id) => self.nodes.get(&Node { id: id.clone() })
+                .is_some_and(|version| self.version_is_visible(t, version)),
+            ElementId::Edge(id) => self.edges.get(id)
+                .is_some_and(|(_, version)| self.version_is_visible(t, version)),
+        }
+    }
+
+    /// Node properties are guarded by a dedicated lock key, edge properties
+    /// by the same (source, edge type) lock that `add_edge` takes.
+    fn property_lock(&self, element: &ElementId) -> Option<(NodeId, String)> {
+        match element {
+            ElementId::Node(id) => Some((id.clone(), "NODE_PROPERTIES".to_string())),
+            ElementId::Edge(id) => self.edges.get(id)
+                .map(|(edge, _)| (edge.source().clone(), edge.edgetype.clone())),
+        }
+    }
+
+    /// Writes a new version of `key` on `element`, expiring the version
+    /// that `t` could see until now.
+    pub fn set_property(&mut self, t: &mut TransactionId, element: ElementId, key: String, value: PropertyValue) -> TxResult<()> {
+        self.expire_property(t, &element, &key)?;
+        self.properties
+            .entry(element.clone())
+            .or_default()
+            .entry(key.clone())
+            .or_default()
+            .push((value, Version::new(t.txid)));
+        self.writes.push(t, UndoAction::SetProperty(element, key));
+        Ok(())
+    }
+
+    pub fn remove_property(&mut self, t: &mut TransactionId, element: &ElementId, key: &str) -> TxResult<()> {
+        self.expire_property(t, element, key)
+    }
+
+    fn expire_property(&mut self, t: &mut TransactionId, element: &ElementId, key: &str) -> TxResult<()> {
+        self.check_active(t)?;
+        if !self.element_is_visible(t, element) {
+            return Err(match element {
+                ElementId::Node(id) => TxError::NodeNotFound(id.clone()),
+                ElementId::Edge(id) => TxError::ElementNotFound(Resource::Edge(id.clone())),
+            });
+        }
+
+        if let Some(lock) = self.property_lock(element) {
+            self.lock_for_write(t, &[lock])?;
+        }
+
+        let expire: Vec<usize> = match self.properties.get(element).and_then(|keys| keys.get(key)) {
+            Some(versions) => {
+                let resource = Resource::Property(element.clone(), key.to_string());
+                self.locked_version(t, versions.iter().map(|(_, version)| version), resource)?;
+                versions
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, (_, version))| self.version_is_visible(t, version))
+                    .map(|(i, _)| i)
+                    .collect()
+            }
+            None => Vec::new(),
+        };
+
+        if let Some(lock) = self.property_lock(element) {
+            t.read_locks.insert(lock);
+        }
+        if let Some(versions) = self.properties.get_mut(element).and_then(|keys| keys.get_mut(key)) {
+            for i in expire {
+                versions[i].1.expiration_id = t.txid;
+                self.writes.push(t, UndoAction::ExpireProperty(element.clone(), key.to_string()));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn get_property(&self, t: &TransactionId, element: &ElementId, key: &str) -> Option<PropertyValue> {
+        self.properties
+            .get(element)?
+            .get(key)?
+            .iter()
+            .rev()
+            .find(|(_, version)| self.version_is_visible(t, version))
+            .map(|(value, _)| value.clone())
+    }
+
+    pub fn get_properties(&self, t: &TransactionId, element: &ElementId) -> BTreeMap<String, PropertyValue> {
+        let mut visible = BTreeMap::new();
+        if let Some(keys) = self.properties.get(element) {
+            for (key, versions) in keys {
+                if let Some((value, _)) = versions.iter().rev().find(|(_, version)| self.version_is_visible(t, version)) {
+                    visible.insert(key.clone(), value.clone());
+                }
+            }
+        }
+        visible
+    }
+
+    /// Undoes every element write made by `txid`: versions it created are
+    /// dropped and versions it expired become live again.
+    fn rollback_graph_elements(&mut self, txid: u64) {
+        let restore = |version: &mut Version| {
+            if version.expiration_id == txid {
+                version.expiration_id = 0;
+            }
+        };
+
+        self.nodes.retain(|_, version| version.creation_id != txid);
+        self.nodes.values_mut().for_each(restore);
+
+        self.edges.retain(|_, (_, version)| version.creation_id != txid);
+        self.edges.values_mut().for_each(|(_, version)| restore(version));
+
+        let nodes = &self.nodes;
+        let edges = &self.edges;
+        self.adjacencylist.retain(|node, _| nodes.contains_key(node));
+        for list in self.adjacencylist.values_mut() {
+            list.retain(|(_, edge)| edges.contains_key(edge.id()));
+        }
+
+        for versions in self.labels.values_mut() {
+            versions.retain(|(_, version)| version.creation_id != txid);
+            versions.iter_mut().for_each(|(_, version)| restore(version));
+        }
+
+        for keys in self.properties.values_mut() {
+            for versions in keys.values_mut() {
+                versions.retain(|(_, version)| version.creation_id != txid);
+                versions.iter_mut().for_each(|(_, version)| restore(version));
+            }
+        }
+
+        for versions in self.store.values_mut() {
+            versions.retain(|record| record.version.creation_id != txid);
+            versions.iter_mut().for_each(|record| restore(&mut record.version));
+        }
+        self.store.retain(|_, versions| !versions.is_empty());
+    }
+}
+
 ////////////////////////////////////////////////////////////////////////////////
 // API Interface matching PRD specification
 
@@ -673,8 +1301,8 @@ impl IGraph for Graph {
     
     fn add_edge(&mut self, transaction_id: Option<TransactionId>, src: NodeId, dst: NodeId, edge_type: String) -> TxResult<()> {
         // First find the actual Node objects from NodeIds
-        let src_node = self.find_node_by_id(&src).ok_or(TxError::NodeNotFound)?;
-        let dst_node = self.find_node_by_id(&dst).ok_or(TxError::NodeNotFound)?;
+        let src_node = self.find_node_by_id(&src).ok_or_else(|| TxError::NodeNotFound(src.clone()))?;
+        let dst_node = self.find_node_by_id(&dst).ok_or_else(|| TxError::NodeNotFound(dst.clone()))?;
         
         match transaction_id {
             Some(mut txid) => {
@@ -691,7 +1319,7 @@ impl IGraph for Graph {
     
     fn get_nodes(&mut self, transaction_id: Option<TransactionId>, origin: NodeId, search_path: Vec<String>) -> TxResult<Vec<NodeId>> {
         // First find the actual Node object from NodeId
-        let origin_node = self.find_node_by_id(&origin).ok_or(TxError::NodeNotFound)?;
+        let origin_node = self.find_node_by_id(&origin).ok_or_else(|| TxError::NodeNotFound(origin.clone()))?;
         
         match transaction_id {
             Some(mut txid) => {
@@ -699,12 +1327,10 @@ impl IGraph for Graph {
                 Ok(nodes.into_iter().map(|node| node.id().clone()).collect())
             },
             None => {
-                // Create temporary transaction for single operation
-                let mut temp_txid = self.start_transaction();
-                let nodes = self.get_nodes_internal(&mut temp_txid, &origin_node, search_path);
-                let node_ids: Vec<NodeId> = nodes.into_iter().map(|node| node.id().clone()).collect();
-                self.commit_transaction(&temp_txid)?;
-                Ok(node_ids)
+                // A single read needs no more than a read-only snapshot
+                let reader = self.start_read_transaction();
+                let nodes = self.read_nodes(&reader, &origin_node, search_path);
+                Ok(nodes.into_iter().map(|node| node.id().clone()).collect())
             }
         }
     }
@@ -763,10 +1389,12 @@ impl Graph {
     
     /// Check for collision but exclude the specific destination we're trying to connect to
     /// This allows the same edge type to go to different destinations
-    fn has_collision_excluding_destination(&self, from: &Node, to: &Node, edge_type: &str) -> bool {
+    fn has_collision_excluding_destination(&self, t: &TransactionId, from: &Node, to: &Node, edge_type: &str) -> bool {
         if let Some(edges) = self.adjacencylist.get(from) {
-            // Check if there's an edge of this type to a different destination
-            edges.iter().any(|(dest, edge)| dest != to && edge.edgetype == edge_type)
+            // Check if there's an edge of this type to a different destination,
+            // ignoring edges this transaction (or a committed one) has removed
+            edges.iter().any(|(dest, edge)| dest != to && edge.edgetype == edge_type
+                && !self.edge_is_removed_for(t, edge))
         } else {
             false
         }
@@ -864,7 +1492,7 @@ mod tests {
         // Both should succeed
         assert!(result1.is_ok());
         assert!(result2.is_ok());
-        assert!(tx.read_locks.len() > 0);
+        assert!(!tx.read_locks.is_empty());
     }
 
     #[test]
@@ -874,7 +1502,7 @@ mod tests {
         let tx_id = tx.txid;
         
         // Transaction should be active
-        assert!(graph.active_transactions.contains(&tx_id));
+        assert!(graph.conductor.active().contains(&tx_id));
         
         let result = graph.commit_transaction(&tx);
         
@@ -882,7 +1510,7 @@ mod tests {
         assert!(result.is_ok());
         
         // Transaction should no longer be active
-        assert!(!graph.active_transactions.contains(&tx_id));
+        assert!(!graph.conductor.active().contains(&tx_id));
     }
 
     #[test]
@@ -892,12 +1520,12 @@ mod tests {
         let tx_id = tx.txid;
         
         // Transaction should be active
-        assert!(graph.active_transactions.contains(&tx_id));
+        assert!(graph.conductor.active().contains(&tx_id));
         
         let _ = graph.abort_transaction(&tx);
         
         // Transaction should no longer be active
-        assert!(!graph.active_transactions.contains(&tx_id));
+        assert!(!graph.conductor.active().contains(&tx_id));
     }
 
     #[test]
@@ -931,4 +1559,134 @@ mod tests {
         let _node2 = graph.add_node(&mut tx2);
         assert!(tx2.snapshot.is_some());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_commit_timestamps_are_pruned_below_the_oldest_active_txid() {
+        let mut graph = Graph::new();
+        let oldest = graph.start_transaction();
+        let early = graph.start_read_transaction();
+        let mut writer = graph.start_transaction();
+        let node = graph.add_node(&mut writer);
+        let during = graph.start_read_transaction();
+        graph.commit_transaction(&writer).unwrap();
+        assert!(graph.commit_timestamp(writer.txid).is_some());
+
+        graph.commit_transaction(&oldest).unwrap();
+        assert!(graph.commit_timestamps.is_empty());
+        // Without the timestamps each reader still orders the commit right.
+        assert!(!graph.node_is_visible(&early, &node));
+        assert!(!graph.node_is_visible(&during, &node));
+        let after = graph.start_read_transaction();
+        assert!(graph.node_is_visible(&after, &node));
+    }
+
+    #[test]
+    fn test_updated_record_value_is_readable() {
+        let mut graph = Graph::new();
+        let mut setup = graph.start_transaction();
+        let mut record = BTreeMap::new();
+        record.insert(MVCC::ElementId, 1);
+        graph.add_record(&mut setup, &mut record);
+        graph.commit_transaction(&setup).unwrap();
+
+        let before = graph.start_transaction();
+        let mut writer = graph.start_transaction();
+        graph.update_record(&mut writer, 1, "42".to_string()).unwrap();
+        assert_eq!(graph.record_value(&writer, 1), Some("42".to_string()));
+        // The value is not a record store key
+        assert!(graph.record_store(&mut writer).keys().is_empty());
+        graph.commit_transaction(&writer).unwrap();
+
+        let mut after = graph.start_transaction();
+        assert_eq!(graph.record_value(&after, 1), Some("42".to_string()));
+        assert_eq!(graph.record_value(&before, 1), None);
+
+        // An update that cannot expire the record writes nothing
+        let mut blocked = graph.start_transaction();
+        graph.delete_record(&mut after, 1).unwrap();
+        assert!(graph.update_record(&mut blocked, 1, "43".to_string()).is_err());
+        assert!(blocked.rollback_actions.is_empty());
+        assert_eq!(graph.record_value(&after, 1), None);
+    }
+
+    #[test]
+    fn test_repeatable_reads() {
+        let mut graph = Graph::new();
+        let mut setup = graph.start_transaction();
+        let node = graph.add_node(&mut setup);
+        let name = ElementId::Node(node.id().clone());
+        graph.set_property(&mut setup, name.clone(), "name".to_string(), "a".into()).unwrap();
+        let mut record = BTreeMap::new();
+        record.insert(MVCC::ElementId, 1);
+        graph.add_record(&mut setup, &mut record);
+        graph.commit_transaction(&setup).unwrap();
+
+        let mut reader = graph.start_transaction();
+        let mut writer = graph.start_transaction();
+        let added = graph.add_node(&mut writer);
+        graph.set_property(&mut writer, name.clone(), "name".to_string(), "b".into()).unwrap();
+        graph.delete_record(&mut writer, 1).unwrap();
+        graph.commit_transaction(&writer).unwrap();
+        assert!(graph.commit_timestamp(writer.txid).unwrap() > reader.txid);
+
+        // The reader keeps seeing the graph as it was when it started.
+        assert!(!graph.node_is_visible(&reader, &added));
+        assert_eq!(graph.get_property(&reader, &name, "name"), Some("a".into()));
+        assert!(graph.record_is_visible(&reader, &record));
+
+        // Writing over a version it cannot see the latest state of conflicts.
+        assert_eq!(
+            graph.set_property(&mut reader, name.clone(), "name".to_string(), "c".into()),
+            Err(TxError::TransactionLocked(Conflict::new(reader.txid).with(writer.txid).on(Resource::Property(name.clone(), "name".to_string()))))
+        );
+        assert_eq!(graph.delete_record(&mut reader, 1).unwrap_err().kind(), ErrorKind::TransactionLocked);
+
+        let fresh = graph.start_transaction();
+        assert!(graph.node_is_visible(&fresh, &added));
+        assert_eq!(graph.get_property(&fresh, &name, "name"), Some("b".into()));
+    }
+
+    #[test]
+    fn test_get_nodes_walks_the_search_path_from_the_back() {
+        let mut graph = Graph::new();
+        let a = IGraph::add_node(&mut graph, None).unwrap();
+        let b = IGraph::add_node(&mut graph, None).unwrap();
+        IGraph::add_edge(&mut graph, None, a.clone(), b.clone(), "red".to_string()).unwrap();
+
+        let path = vec!["blue".to_string(), "red".to_string()];
+        assert_eq!(IGraph::get_nodes(&mut graph, None, a.clone(), path), Ok(vec![b]));
+        let path = vec!["red".to_string(), "blue".to_string()];
+        assert_eq!(IGraph::get_nodes(&mut graph, None, a, path), Ok(vec![]));
+    }
+
+    #[test]
+    fn test_txids_past_u32_range() {
+        // Pretend four billion transactions have already run.
+        let mut graph = Graph::with_conductor(DeterministicConductor::starting_at(u32::MAX as u64 - 1));
+        let _changes = graph.subscribe(None);
+
+        let mut before = graph.start_transaction();
+        let node = graph.add_node(&mut before);
+        let mut record = BTreeMap::new();
+        record.insert(MVCC::ElementId, 1);
+        graph.add_record(&mut before, &mut record);
+        graph.commit_transaction(&before).unwrap();
+
+        let mut reader = graph.start_transaction();
+        graph.get_nodes_internal(&mut reader, &node, vec!["red".to_string()]);
+        let mut after = graph.start_transaction();
+        assert!(after.txid > u32::MAX as u64);
+        assert!(graph.node_is_visible(&after, &node));
+
+        // A commit past the old wrap point is still ordered after the reader.
+        let mut other = BTreeMap::new();
+        other.insert(MVCC::ElementId, 2);
+        graph.add_record(&mut after, &mut other);
+        graph.commit_transaction(&after).unwrap();
+        let aborted = graph.commit_transaction(&reader).unwrap_err();
+        assert_eq!(aborted.conflict().and_then(|conflict| conflict.with), Some(after.txid));
+
+        let txids: Vec<u64> = graph.changes_since(1).iter().map(|event| event.txid).collect();
+        assert_eq!(txids, vec![after.txid]);
+    }
+}
@@ -0,0 +1,664 @@
+//! # Pessimistic locking
+//!
+//! By default transactions run optimistically: writes go ahead and conflicts
+//! surface at commit. In `LockingMode::Pessimistic` the graph instead takes an
+//! exclusive lock on every `(NodeId, edge type)` key a write touches, the same
+//! keys the optimistic mode records as read locks: both endpoints of a new
+//! edge, "NODE_PROPERTIES" for node properties and the edge's `(source, edge
+//! type)` for edge properties. Locks are held until the transaction commits or
+//! rolls back.
+//!
+//! A `Graph` is used through `&mut self`, so it cannot wait for a lock itself;
+//! a write that finds its key locked fails with `TxError::TransactionLocked`.
+//! Waiting happens in the `LockManager`, which is shared between threads:
+//! `acquire` blocks and `acquire_async` waits asynchronously until the lock is
+//! free. While waiting a transaction is recorded in the wait-for graph; when a
+//! wait would close a cycle the youngest transaction in it is chosen as the
+//! victim and its wait ends with `TxError::Deadlock`.
+//!
+//! Independent of the mode, `lock_for_update` lets a transaction lock nodes
+//! or `(NodeId, edge type)` pairs it means to write, shared or exclusive, with
+//! `NOWAIT` and `SKIP LOCKED` behaviour. Writes of other transactions to a
+//! locked key fail with `TxError::TransactionLocked`.
+//!
+//! `SharedGraph` puts both together: it acquires the locks of a write
+//! through the lock manager before taking the graph, and rolls a deadlock
+//! victim back.
+//!
+//! ```
+//! use graph_mvcc::{ElementId, Graph, LockingMode, SharedGraph};
+//!
+//! let mut graph = Graph::new();
+//! let mut setup = graph.start_transaction();
+//! let node = graph.add_node(&mut setup);
+//! graph.commit_transaction(&setup).unwrap();
+//!
+//! let shared = SharedGraph::new(graph);
+//! let mut tx = shared.start_transaction();
+//! let element = ElementId::Node(node.id().clone());
+//! shared.set_property(&mut tx, element, "name".to_string(), "hub".into()).unwrap();
+//! shared.commit_transaction(&tx).unwrap();
+//! assert_eq!(shared.read(|graph| graph.locking_mode()), LockingMode::Pessimistic);
+//! ```
+
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::pin::pin;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use tokio::sync::Notify;
+
+use crate::{Conflict, ElementId, ErrorKind, Graph, Node, NodeId, PropertyValue, Resource, TransactionId, TxError, TxResult};
+
+/// The key a lock is taken on.
+pub type LockKey = (NodeId, String);
+
+/// The edge type of the key that locks a node as a whole. It conflicts with
+/// every other key on the same node.
+pub const NODE_LOCK: &str = "NODE";
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Default)]
+pub enum LockingMode {
+    /// Writes proceed and conflicts are detected at commit.
+    #[default]
+    Optimistic,
+    /// Writes take exclusive locks on the keys they touch.
+    Pessimistic,
+}
+
+/// What `lock_for_update` does about keys another transaction has locked.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Default)]
+pub enum LockWait {
+    /// Wait until the lock is released.
+    #[default]
+    Wait,
+    /// Fail with `TxError::TransactionLocked`, like `NOWAIT`.
+    NoWait,
+    /// Leave the key out, like `SKIP LOCKED`.
+    SkipLocked,
+}
+
+/// Shared locks are compatible with each other, an exclusive lock with none.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd)]
+pub enum LockMode {
+    Shared,
+    Exclusive,
+}
+
+#[derive(Debug)]
+struct Lock {
+    mode: LockMode,
+    owners: BTreeSet<u64>,
+}
+
+#[derive(Debug, Default)]
+struct LockTable {
+    holders: HashMap<LockKey, Lock>,
+    held: HashMap<u64, HashSet<LockKey>>,
+    // The transactions each waiting transaction waits for
+    waits_for: HashMap<u64, BTreeSet<u64>>,
+    victims: HashSet<u64>,
+}
+
+/// What to do after looking at a lock.
+enum Attempt {
+    Granted,
+    /// Wait for the holders to release it. `victim_chosen` is set when the
+    /// wait closed a cycle and another transaction has to be woken up to
+    /// learn it is the deadlock victim.
+    Wait { victim_chosen: bool },
+}
+
+fn locked(txid: u64, holder: u64, key: &LockKey) -> TxError {
+    TxError::TransactionLocked(Conflict::new(txid).with(holder).on(Resource::Lock(key.clone())))
+}
+
+/// Two keys overlap when they are the same, or when they are on the same
+/// node and one of them locks the whole node.
+fn overlaps(a: &LockKey, b: &LockKey) -> bool {
+    a.0 == b.0 && (a.1 == b.1 || a.1 == NODE_LOCK || b.1 == NODE_LOCK)
+}
+
+impl LockTable {
+    fn grant(&mut self, txid: u64, key: &LockKey, mode: LockMode) {
+        let lock = self.holders.entry(key.clone()).or_insert(Lock { mode, owners: BTreeSet::new() });
+        lock.mode = lock.mode.max(mode);
+        lock.owners.insert(txid);
+        self.held.entry(txid).or_default().insert(key.clone());
+        self.waits_for.remove(&txid);
+    }
+
+    /// The other transactions whose locks keep `txid` from locking `key` in
+    /// `mode`.
+    fn blockers(&self, txid: u64, key: &LockKey, mode: LockMode) -> BTreeSet<u64> {
+        self.holders
+            .iter()
+            .filter(|(held, lock)| overlaps(held, key) && (mode == LockMode::Exclusive || lock.mode == LockMode::Exclusive))
+            .flat_map(|(_, lock)| lock.owners.iter().copied())
+            .filter(|owner| *owner != txid)
+            .collect()
+    }
+
+    /// The transactions on a path of waits from `from` to `target`.
+    fn path_to(&self, from: u64, target: u64, seen: &mut HashSet<u64>) -> Option<Vec<u64>> {
+        for &next in self.waits_for.get(&from).into_iter().flatten() {
+            if next == target {
+                return Some(vec![from]);
+            }
+            if seen.insert(next) {
+                if let Some(mut path) = self.path_to(next, target, seen) {
+                    path.push(from);
+                    return Some(path);
+                }
+            }
+        }
+        None
+    }
+
+    /// Grants `key` to `txid` if no other transaction holds a conflicting
+    /// lock, or records that `txid` waits for them and runs deadlock detection.
+    fn attempt(&mut self, txid: u64, key: &LockKey, mode: LockMode) -> TxResult<Attempt> {
+        let deadlock = |with: Option<u64>| TxError::Deadlock(Conflict::new(txid).with(with).on(Resource::Lock(key.clone())));
+        if self.victims.remove(&txid) {
+            let with = self.waits_for.remove(&txid).and_then(|blockers| blockers.first().copied());
+            return Err(deadlock(with));
+        }
+        let blockers = self.blockers(txid, key, mode);
+        if blockers.is_empty() {
+            self.grant(txid, key, mode);
+            return Ok(Attempt::Granted);
+        }
+
+        let with = blockers.first().copied();
+        self.waits_for.insert(txid, blockers);
+        if let Some(cycle) = self.path_to(txid, txid, &mut HashSet::new()) {
+            let victim = cycle.into_iter().max().unwrap_or(txid);
+            if victim == txid {
+                self.waits_for.remove(&txid);
+                return Err(deadlock(with));
+            }
+            return Ok(Attempt::Wait { victim_chosen: self.victims.insert(victim) });
+        }
+        Ok(Attempt::Wait { victim_chosen: false })
+    }
+}
+
+#[derive(Debug, Default)]
+struct Shared {
+    table: Mutex<LockTable>,
+    released: Condvar,
+    released_async: Notify,
+}
+
+/// Shared and exclusive locks on `LockKey`s with a wait-for graph. Clones
+/// share the same lock table.
+#[derive(Debug, Clone, Default)]
+pub struct LockManager {
+    shared: Arc<Shared>,
+}
+
+impl LockManager {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn table(&self) -> MutexGuard<'_, LockTable> {
+        self.shared.table.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn wake_waiters(&self) {
+        self.shared.released.notify_all();
+        self.shared.released_async.notify_waiters();
+    }
+
+    /// Takes the lock on `key` for `txid` unless another transaction holds a
+    /// conflicting one, in which case it fails with `TxError::TransactionLocked`.
+    pub fn try_acquire(&self, txid: u64, key: &LockKey, mode: LockMode) -> TxResult<()> {
+        let mut table = self.table();
+        if let Some(&holder) = table.blockers(txid, key, mode).first() {
+            return Err(locked(txid, holder, key));
+        }
+        table.grant(txid, key, mode);
+        Ok(())
+    }
+
+    /// Fails with `TxError::TransactionLocked` if another transaction holds a
+    /// lock that conflicts with writing `key`, without taking a lock.
+    pub fn check_writable(&self, txid: u64, key: &LockKey) -> TxResult<()> {
+        match self.table().blockers(txid, key, LockMode::Exclusive).first() {
+            Some(&holder) => Err(locked(txid, holder, key)),
+            None => Ok(()),
+        }
+    }
+
+    /// Blocks until `txid` holds the lock on `key`. Gives up with
+    /// `TxError::TransactionLocked` once `timeout` has passed, and with
+    /// `TxError::Deadlock` if `txid` is chosen as a deadlock victim.
+    pub fn acquire(&self, txid: u64, key: &LockKey, mode: LockMode, timeout: Option<Duration>) -> TxResult<()> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        let mut table = self.table();
+        loop {
+            match table.attempt(txid, key, mode)? {
+                Attempt::Granted => return Ok(()),
+                Attempt::Wait { victim_chosen } => {
+                    if victim_chosen {
+                        self.wake_waiters();
+                    }
+                }
+            }
+            table = match deadline {
+                Some(deadline) => {
+                    let left = deadline.saturating_duration_since(Instant::now());
+                    if left.is_zero() {
+                        let blockers = table.waits_for.remove(&txid).unwrap_or_default();
+                        let conflict = Conflict::new(txid).with(blockers.first().copied()).on(Resource::Lock(key.clone()));
+                        return Err(TxError::TransactionLocked(conflict));
+                    }
+                    self.shared
+                        .released
+                        .wait_timeout(table, left)
+                        .unwrap_or_else(|poisoned| poisoned.into_inner())
+                        .0
+                }
+                None => self.shared.released.wait(table).unwrap_or_else(|poisoned| poisoned.into_inner()),
+            };
+        }
+    }
+
+    /// Waits asynchronously until `txid` holds the lock on `key`. Fails with
+    /// `TxError::Deadlock` if `txid` is chosen as a deadlock victim.
+    pub async fn acquire_async(&self, txid: u64, key: &LockKey, mode: LockMode) -> TxResult<()> {
+        loop {
+            // Register for the wake-up before looking at the table, so a
+            // release in between is not missed.
+            let mut released = pin!(self.shared.released_async.notified());
+            released.as_mut().enable();
+            let attempt = self.table().attempt(txid, key, mode)?;
+            match attempt {
+                Attempt::Granted => return Ok(()),
+                Attempt::Wait { victim_chosen } => {
+                    if victim_chosen {
+                        self.wake_waiters();
+                    }
+                }
+            }
+            released.await;
+        }
+    }
+
+    /// The transactions holding a lock on `key` and the mode they hold it in.
+    pub fn holders(&self, key: &LockKey) -> Option<(LockMode, BTreeSet<u64>)> {
+        self.table().holders.get(key).map(|lock| (lock.mode, lock.owners.clone()))
+    }
+
+    /// Releases every lock of `txid` and wakes the transactions waiting.
+    pub fn release_all(&self, txid: u64) {
+        let mut table = self.table();
+        if let Some(keys) = table.held.remove(&txid) {
+            for key in keys {
+                if let Some(lock) = table.holders.get_mut(&key) {
+                    lock.owners.remove(&txid);
+                    if lock.owners.is_empty() {
+                        table.holders.remove(&key);
+                    }
+                }
+            }
+        }
+        table.waits_for.remove(&txid);
+        table.victims.remove(&txid);
+        drop(table);
+        self.wake_waiters();
+    }
+}
+
+/// The graph's lock manager. A cloned graph starts with no locks held.
+#[derive(Debug, Default)]
+pub(crate) struct Locks {
+    pub(crate) mode: LockingMode,
+    pub(crate) manager: LockManager,
+}
+
+impl Clone for Locks {
+    fn clone(&self) -> Self {
+        Locks {
+            mode: self.mode,
+            manager: LockManager::new(),
+        }
+    }
+}
+
+impl Graph {
+    pub fn set_locking_mode(&mut self, mode: LockingMode) {
+        self.locks.mode = mode;
+    }
+
+    pub fn locking_mode(&self) -> LockingMode {
+        self.locks.mode
+    }
+
+    /// The lock manager of this graph, to wait for locks from other threads.
+    pub fn lock_manager(&self) -> LockManager {
+        self.locks.manager.clone()
+    }
+
+    /// The keys a write of `add_edge` locks in pessimistic mode.
+    pub(crate) fn edge_lock_keys(from: &Node, to: &Node, edge_type: &str) -> Vec<LockKey> {
+        vec![(from.id().clone(), edge_type.to_string()), (to.id().clone(), edge_type.to_string())]
+    }
+
+    /// The key that locks `node` as a whole.
+    pub fn node_lock_key(node: &Node) -> LockKey {
+        (node.id().clone(), NODE_LOCK.to_string())
+    }
+
+    /// Makes sure `t` may write `keys`. In pessimistic mode it takes
+    /// exclusive locks on them; in optimistic mode it only checks that no
+    /// other transaction has locked them for update. Fails with
+    /// `TxError::TransactionLocked` otherwise.
+    pub(crate) fn lock_for_write(&self, t: &TransactionId, keys: &[LockKey]) -> TxResult<()> {
+        for key in keys {
+            match self.locks.mode {
+                LockingMode::Optimistic => self.locks.manager.check_writable(t.txid, key)?,
+                LockingMode::Pessimistic => self.locks.manager.try_acquire(t.txid, key, LockMode::Exclusive)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// Locks `keys` until `t` ends, like `SELECT ... FOR UPDATE` (exclusive)
+    /// and `SELECT ... FOR SHARE` (shared). Other transactions can't write
+    /// what is locked, in either locking mode, nor lock it in a conflicting
+    /// mode. Locking `node_lock_key` covers every key on the node.
+    ///
+    /// With `LockWait::NoWait` a conflicting lock fails with
+    /// `TxError::TransactionLocked`, leaving the keys before it locked. With
+    /// `LockWait::SkipLocked` conflicting keys are left out. A `Graph` cannot
+    /// block, so `LockWait::Wait` behaves like `NoWait` here; use
+    /// `SharedGraph::lock_for_update` to wait. Returns the keys now locked.
+    pub fn lock_for_update(&mut self, t: &mut TransactionId, keys: &[LockKey], mode: LockMode, wait: LockWait) -> TxResult<Vec<LockKey>> {
+        if !self.conductor.active().contains(&t.txid) {
+            return Err(TxError::Abort(Conflict::new(t.txid)));
+        }
+        if t.snapshot.is_none() {
+            t.snapshot = Some(self.create_snapshot(t));
+        }
+        if let Some((id, _)) = keys.iter().find(|(id, _)| !self.node_is_visible(t, &Node { id: id.clone() })) {
+            return Err(TxError::NodeNotFound(id.clone()));
+        }
+
+        let mut locked = Vec::new();
+        for key in keys {
+            match self.locks.manager.try_acquire(t.txid, key, mode) {
+                Ok(()) => locked.push(key.clone()),
+                Err(_) if wait == LockWait::SkipLocked => continue,
+                Err(error) => return Err(error),
+            }
+        }
+        Ok(locked)
+    }
+}
+
+/// A graph shared between threads, running in pessimistic mode. Writes wait
+/// for their locks before they take the graph.
+#[derive(Debug, Clone)]
+pub struct SharedGraph {
+    graph: Arc<Mutex<Graph>>,
+    locks: LockManager,
+    lock_timeout: Option<Duration>,
+}
+
+impl SharedGraph {
+    pub fn new(mut graph: Graph) -> Self {
+        graph.set_locking_mode(LockingMode::Pessimistic);
+        let locks = graph.lock_manager();
+        SharedGraph {
+            graph: Arc::new(Mutex::new(graph)),
+            locks,
+            lock_timeout: None,
+        }
+    }
+
+    /// How long a blocking write waits for a lock; `None` waits forever.
+    pub fn lock_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.lock_timeout = timeout;
+        self
+    }
+
+    fn graph(&self) -> MutexGuard<'_, Graph> {
+        self.graph.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    pub fn read<R>(&self, f: impl FnOnce(&Graph) -> R) -> R {
+        f(&self.graph())
+    }
+
+    pub fn write<R>(&self, f: impl FnOnce(&mut Graph) -> R) -> R {
+        f(&mut self.graph())
+    }
+
+    pub fn start_transaction(&self) -> TransactionId {
+        self.graph().start_transaction()
+    }
+
+    pub fn commit_transaction(&self, t: &TransactionId) -> TxResult<()> {
+        self.graph().commit_transaction(t)
+    }
+
+    pub fn abort_transaction(&self, t: &TransactionId) -> TxResult<()> {
+        self.graph().abort_transaction(t)
+    }
+
+    /// Waits for `keys`, rolling `t` back if it becomes a deadlock victim.
+    fn lock_all(&self, t: &TransactionId, keys: &[LockKey], mode: LockMode) -> TxResult<()> {
+        for key in keys {
+            if let Err(error) = self.locks.acquire(t.txid, key, mode, self.lock_timeout) {
+                if error.kind() == ErrorKind::Deadlock {
+                    let _ = self.abort_transaction(t);
+                }
+                return Err(error);
+            }
+        }
+        Ok(())
+    }
+
+    async fn lock_all_async(&self, t: &TransactionId, keys: &[LockKey]) -> TxResult<()> {
+        for key in keys {
+            if let Err(error) = self.locks.acquire_async(t.txid, key, LockMode::Exclusive).await {
+                if error.kind() == ErrorKind::Deadlock {
+                    let _ = self.abort_transaction(t);
+                }
+                return Err(error);
+            }
+        }
+        Ok(())
+    }
+
+    /// Like `Graph::lock_for_update`, except that `LockWait::Wait` blocks
+    /// until the keys are free.
+    pub fn lock_for_update(&self, t: &mut TransactionId, keys: &[LockKey], mode: LockMode, wait: LockWait) -> TxResult<Vec<LockKey>> {
+        if wait == LockWait::Wait {
+            self.lock_all(t, keys, mode)?;
+        }
+        self.graph().lock_for_update(t, keys, mode, wait)
+    }
+
+    fn property_keys(&self, element: &ElementId) -> Vec<LockKey> {
+        self.graph().property_lock(element).into_iter().collect()
+    }
+
+    pub fn add_edge(&self, t: &mut TransactionId, from: &Node, to: &Node, edge_type: String) -> TxResult<()> {
+        self.lock_all(t, &Graph::edge_lock_keys(from, to, &edge_type), LockMode::Exclusive)?;
+        self.graph().add_edge(t, from, to, edge_type)
+    }
+
+    pub async fn add_edge_async(&self, t: &mut TransactionId, from: &Node, to: &Node, edge_type: String) -> TxResult<()> {
+        self.lock_all_async(t, &Graph::edge_lock_keys(from, to, &edge_type)).await?;
+        self.graph().add_edge(t, from, to, edge_type)
+    }
+
+    pub fn set_property(&self, t: &mut TransactionId, element: ElementId, key: String, value: PropertyValue) -> TxResult<()> {
+        self.lock_all(t, &self.property_keys(&element), LockMode::Exclusive)?;
+        self.graph().set_property(t, element, key, value)
+    }
+
+    pub async fn set_property_async(&self, t: &mut TransactionId, element: ElementId, key: String, value: PropertyValue) -> TxResult<()> {
+        let keys = self.property_keys(&element);
+        self.lock_all_async(t, &keys).await?;
+        self.graph().set_property(t, element, key, value)
+    }
+
+    pub fn remove_property(&self, t: &mut TransactionId, element: &ElementId, key: &str) -> TxResult<()> {
+        self.lock_all(t, &self.property_keys(element), LockMode::Exclusive)?;
+        self.graph().remove_property(t, element, key)
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Unit Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+    use std::thread;
+    use crate::testing;
+
+    #[test]
+    fn test_pessimistic_writes_lock_until_commit() {
+        let (mut graph, nodes) = testing::graph(2, &[]);
+        graph.set_locking_mode(LockingMode::Pessimistic);
+        let element = ElementId::Node(nodes[0].id().clone());
+
+        let mut first = graph.start_transaction();
+        let mut second = graph.start_transaction();
+        graph.add_edge(&mut first, &nodes[0], &nodes[1], "red".to_string()).unwrap();
+        let conflict = Conflict::new(second.txid).with(first.txid).on(Resource::Lock((nodes[1].id().clone(), "red".to_string())));
+        assert_eq!(graph.add_edge(&mut second, &nodes[1], &nodes[0], "red".to_string()), Err(TxError::TransactionLocked(conflict)));
+
+        graph.set_property(&mut first, element.clone(), "name".to_string(), "a".into()).unwrap();
+        let key = (nodes[0].id().clone(), "NODE_PROPERTIES".to_string());
+        assert_eq!(graph.lock_manager().holders(&key), Some((LockMode::Exclusive, BTreeSet::from([first.txid]))));
+        graph.commit_transaction(&first).unwrap();
+        assert_eq!(graph.lock_manager().holders(&key), None);
+
+        let mut third = graph.start_transaction();
+        graph.set_property(&mut third, element, "name".to_string(), "b".into()).unwrap();
+    }
+
+    #[test]
+    fn test_blocked_writer_waits_for_commit() {
+        let (graph, nodes) = testing::graph(1, &[]);
+        let shared = SharedGraph::new(graph);
+        let element = ElementId::Node(nodes[0].id().clone());
+
+        let mut first = shared.start_transaction();
+        shared.set_property(&mut first, element.clone(), "name".to_string(), "first".into()).unwrap();
+
+        let (done, finished) = mpsc::channel();
+        let waiter = {
+            let shared = shared.clone();
+            let element = element.clone();
+            thread::spawn(move || {
+                let mut second = shared.start_transaction();
+                shared.set_property(&mut second, element, "role".to_string(), "second".into()).unwrap();
+                shared.commit_transaction(&second).unwrap();
+                done.send(()).unwrap();
+            })
+        };
+
+        assert!(finished.recv_timeout(Duration::from_millis(100)).is_err());
+        shared.commit_transaction(&first).unwrap();
+        waiter.join().unwrap();
+        finished.recv().unwrap();
+
+        let reader = shared.start_transaction();
+        assert_eq!(shared.read(|graph| graph.get_property(&reader, &element, "name")), Some("first".into()));
+        assert_eq!(shared.read(|graph| graph.get_property(&reader, &element, "role")), Some("second".into()));
+    }
+
+    #[test]
+    fn test_deadlock_aborts_the_younger_transaction() {
+        let (graph, nodes) = testing::graph(2, &[]);
+        let shared = SharedGraph::new(graph);
+        let a = ElementId::Node(nodes[0].id().clone());
+        let b = ElementId::Node(nodes[1].id().clone());
+
+        let mut older = shared.start_transaction();
+        let mut younger = shared.start_transaction();
+        shared.set_property(&mut older, a.clone(), "x".to_string(), 1i64.into()).unwrap();
+        shared.set_property(&mut younger, b.clone(), "x".to_string(), 2i64.into()).unwrap();
+
+        let victim = {
+            let shared = shared.clone();
+            let a = a.clone();
+            thread::spawn(move || shared.set_property(&mut younger, a, "x".to_string(), 3i64.into()))
+        };
+        // Once the younger transaction waits, this wait closes the cycle.
+        while shared.locks.table().waits_for.is_empty() {
+            thread::yield_now();
+        }
+        shared.set_property(&mut older, b.clone(), "x".to_string(), 4i64.into()).unwrap();
+        let deadlock = victim.join().unwrap().unwrap_err();
+        assert_eq!(deadlock.kind(), ErrorKind::Deadlock);
+        assert_eq!(deadlock.resource(), Some(Resource::Lock((nodes[0].id().clone(), "NODE_PROPERTIES".to_string()))));
+        shared.commit_transaction(&older).unwrap();
+
+        let reader = shared.start_transaction();
+        assert_eq!(shared.read(|graph| graph.get_property(&reader, &b, "x")), Some(4i64.into()));
+    }
+
+    #[test]
+    fn test_lock_for_update() {
+        let (mut graph, nodes) = testing::graph(2, &[]);
+        let mut reader = graph.start_transaction();
+        let mut other = graph.start_transaction();
+        let mut writer = graph.start_transaction();
+        let node = Graph::node_lock_key(&nodes[0]);
+        let red = (nodes[1].id().clone(), "red".to_string());
+
+        // Shared locks are compatible, an exclusive one is not
+        graph.lock_for_update(&mut reader, std::slice::from_ref(&node), LockMode::Shared, LockWait::NoWait).unwrap();
+        graph.lock_for_update(&mut other, std::slice::from_ref(&node), LockMode::Shared, LockWait::NoWait).unwrap();
+        assert_eq!(
+            graph.lock_for_update(&mut writer, std::slice::from_ref(&node), LockMode::Exclusive, LockWait::NoWait).unwrap_err().kind(),
+            ErrorKind::TransactionLocked
+        );
+        assert_eq!(
+            graph.lock_for_update(&mut writer, &[node.clone(), red.clone()], LockMode::Exclusive, LockWait::SkipLocked),
+            Ok(vec![red.clone()])
+        );
+
+        // The node lock keeps writers off every key of the node, even in
+        // optimistic mode
+        let element = ElementId::Node(nodes[0].id().clone());
+        let locked = graph.set_property(&mut writer, element.clone(), "x".to_string(), 1i64.into()).unwrap_err();
+        assert_eq!(locked.resource(), Some(Resource::Lock((nodes[0].id().clone(), "NODE_PROPERTIES".to_string()))));
+        let locked = graph.add_edge(&mut reader, &nodes[0], &nodes[1], "red".to_string()).unwrap_err();
+        assert_eq!(locked.conflict().and_then(|conflict| conflict.with), Some(other.txid));
+
+        graph.commit_transaction(&reader).unwrap();
+        graph.abort_transaction(&other).unwrap();
+        graph.set_property(&mut writer, element, "x".to_string(), 1i64.into()).unwrap();
+        graph.commit_transaction(&writer).unwrap();
+    }
+
+    #[test]
+    fn test_async_waiters() {
+        let (graph, nodes) = testing::graph(2, &[]);
+        let shared = SharedGraph::new(graph);
+        let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+
+        let mut first = shared.start_transaction();
+        shared.add_edge(&mut first, &nodes[0], &nodes[1], "red".to_string()).unwrap();
+        runtime.block_on(async {
+            let mut second = shared.start_transaction();
+            let waiting = shared.add_edge_async(&mut second, &nodes[0], &nodes[1], "red".to_string());
+            let release = async {
+                tokio::task::yield_now().await;
+                shared.abort_transaction(&first).unwrap();
+            };
+            let (result, _) = tokio::join!(waiting, release);
+            result.unwrap();
+            shared.commit_transaction(&second).unwrap();
+        });
+    }
+}
@@ -0,0 +1,172 @@
+//! # Predicate locks
+//!
+//! Snapshot isolation keeps a transaction from seeing what others commit
+//! after it started, but not from acting on a neighborhood that has changed
+//! underneath it: a transaction that read the red edges at a node can commit
+//! while another one has committed a new red edge there, a phantom.
+//!
+//! Every traversal step, query hop, route expansion and `get_nodes_internal`
+//! call therefore registers a `PredicateLock` on the `(node, edge type,
+//! direction)` neighborhood it scanned. A transaction started with
+//! `start_serializable_transaction` has them validated on commit: if a
+//! concurrent transaction committed an edge into or out of one of those
+//! neighborhoods, or expired one, the commit is rolled back with
+//! `TxError::Abort`.
+//!
+//! ```
+//! use graph_mvcc::{ErrorKind, Graph};
+//!
+//! let mut graph = Graph::new();
+//! let mut setup = graph.start_transaction();
+//! let hub = graph.add_node(&mut setup);
+//! let spoke = graph.add_node(&mut setup);
+//! graph.commit_transaction(&setup).unwrap();
+//!
+//! let mut reader = graph.start_serializable_transaction();
+//! assert!(graph.get_nodes_internal(&mut reader, &hub, vec!["red".to_string()]).is_empty());
+//!
+//! let mut writer = graph.start_transaction();
+//! graph.add_edge(&mut writer, &hub, &spoke, "red".to_string()).unwrap();
+//! graph.commit_transaction(&writer).unwrap();
+//!
+//! assert_eq!(graph.commit_transaction(&reader).unwrap_err().kind(), ErrorKind::Abort);
+//! ```
+
+use crate::traversal::Direction;
+use crate::{Conflict, Edge, Graph, Node, NodeId, Resource, TransactionId};
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Default)]
+pub enum IsolationLevel {
+    /// Reads see the snapshot the transaction started with.
+    #[default]
+    Snapshot,
+    /// Snapshot reads, and commits fail if a concurrent commit changed a
+    /// neighborhood the transaction scanned.
+    Serializable,
+}
+
+/// The edges of `edge_type` (of any type if `None`) at `node` in `direction`.
+#[derive(Debug, Clone, Eq, PartialEq, Hash)]
+pub struct PredicateLock {
+    pub node: NodeId,
+    pub edge_type: Option<String>,
+    pub direction: Direction,
+}
+
+impl PredicateLock {
+    pub fn new(node: NodeId, edge_type: Option<String>, direction: Direction) -> Self {
+        PredicateLock { node, edge_type, direction }
+    }
+
+    /// Whether `edge` belongs to the neighborhood.
+    pub fn covers(&self, edge: &Edge) -> bool {
+        self.edge_type.as_ref().is_none_or(|edge_type| edge.edgetype() == edge_type)
+            && match self.direction {
+                Direction::Out => edge.source() == &self.node,
+                Direction::In => edge.target() == &self.node,
+                Direction::Both => edge.source() == &self.node || edge.target() == &self.node,
+            }
+    }
+}
+
+impl TransactionId {
+    /// Records that `t` scanned the neighborhood of `lock`.
+    pub(crate) fn register_predicate(&self, lock: PredicateLock) {
+        self.predicate_locks.borrow_mut().insert(lock);
+    }
+
+    /// The neighborhoods this transaction has scanned.
+    pub fn predicate_locks(&self) -> Vec<PredicateLock> {
+        self.predicate_locks.borrow().iter().cloned().collect()
+    }
+}
+
+impl Graph {
+    pub fn start_serializable_transaction(&mut self) -> TransactionId {
+        let mut t = self.start_transaction();
+        t.isolation = IsolationLevel::Serializable;
+        t
+    }
+
+    /// An edge in a neighborhood `t` scanned that a transaction which
+    /// committed after `t` started created or expired.
+    pub(crate) fn find_phantom(&self, t: &TransactionId) -> Option<Conflict> {
+        let concurrent = |txid: &u64| *txid != 0 && self.committed_after(t, *txid);
+        t.predicate_locks.borrow().iter().find_map(|lock| {
+            let node = Node { id: lock.node.clone() };
+            self.adjacencylist.get(&node).into_iter().flatten().find_map(|(_, edge)| {
+                let (_, version) = self.edges.get(edge.id()).filter(|_| lock.covers(edge))?;
+                let writer = [version.creation_id, version.expiration_id].into_iter().find(concurrent)?;
+                Some(Conflict::new(t.txid).with(writer).on(Resource::Edge(edge.id().clone())))
+            })
+        })
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Unit Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{testing, TxError};
+
+    /// Three nodes, the first joined to the second by a red edge.
+    const STAR: [(usize, usize, &str); 1] = [(0, 1, "red")];
+
+    #[test]
+    fn test_traversals_register_predicate_locks() {
+        let (mut graph, nodes) = testing::graph(3, &STAR);
+        let reader = graph.start_transaction();
+        let found = graph.traversal(&reader).v(nodes[0].id()).out("red").to_list();
+        assert_eq!(found, vec![nodes[1].id().clone()]);
+        assert_eq!(
+            reader.predicate_locks(),
+            vec![PredicateLock::new(nodes[0].id().clone(), Some("red".to_string()), Direction::Out)]
+        );
+    }
+
+    #[test]
+    fn test_phantom_insert_and_delete_abort_serializable_commit() {
+        let (mut graph, nodes) = testing::graph(3, &STAR);
+
+        let inserted = graph.start_serializable_transaction();
+        graph.traversal(&inserted).v(nodes[0].id()).out("red").to_list();
+        let mut deleted = graph.start_serializable_transaction();
+        graph.traversal(&deleted).v(nodes[1].id()).in_("red").to_list();
+        graph.add_label(&mut deleted, &nodes[1], "seen".to_string()).unwrap();
+
+        let mut writer = graph.start_transaction();
+        let (_, edge) = graph.visible_edges(&writer, &nodes[1]).remove(0);
+        graph.remove_edge(&mut writer, &edge).unwrap();
+        graph.add_edge(&mut writer, &nodes[0], &nodes[2], "red".to_string()).unwrap();
+        graph.commit_transaction(&writer).unwrap();
+
+        let aborted = graph.commit_transaction(&inserted).unwrap_err();
+        assert_eq!(aborted.conflict().and_then(|conflict| conflict.with), Some(writer.txid));
+        let deleted_edge = Conflict::new(deleted.txid).with(writer.txid).on(Resource::Edge(edge.id().clone()));
+        assert_eq!(graph.commit_transaction(&deleted), Err(TxError::Abort(deleted_edge)));
+        let reader = graph.start_transaction();
+        assert!(graph.get_labels(&reader, &nodes[1]).is_empty());
+    }
+
+    #[test]
+    fn test_unrelated_and_snapshot_commits_pass() {
+        let (mut graph, nodes) = testing::graph(3, &STAR);
+
+        let mut serializable = graph.start_serializable_transaction();
+        graph.get_nodes_internal(&mut serializable, &nodes[0], vec!["red".to_string()]);
+        let mut snapshot = graph.start_transaction();
+        graph.get_nodes_internal(&mut snapshot, &nodes[0], vec!["red".to_string()]);
+
+        // Another edge type at the same node is outside the neighborhood
+        let mut writer = graph.start_transaction();
+        graph.add_edge(&mut writer, &nodes[0], &nodes[2], "blue".to_string()).unwrap();
+        graph.commit_transaction(&writer).unwrap();
+        assert!(graph.commit_transaction(&serializable).is_ok());
+
+        let mut writer = graph.start_transaction();
+        graph.add_edge(&mut writer, &nodes[2], &nodes[0], "red".to_string()).unwrap();
+        graph.commit_transaction(&writer).unwrap();
+        assert!(graph.commit_transaction(&snapshot).is_ok());
+    }
+}
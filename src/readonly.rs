@@ -0,0 +1,146 @@
+//! # Read-only transactions
+//!
+//! A `ReadTransaction` reads a snapshot and nothing else. It is issued a txid
+//! like any transaction, but leaves the conductor's active set right away: it
+//! takes no read locks, has no deadline, and there is nothing to commit, so
+//! it can't abort. It dereferences to `TransactionId`, which lets it be passed
+//! to everything that reads, while the writes, which need a
+//! `&mut TransactionId`, don't accept it:
+//!
+//! ```compile_fail
+//! use graph_mvcc::Graph;
+//!
+//! let mut graph = Graph::new();
+//! let mut reader = graph.start_read_transaction();
+//! graph.add_node(&mut reader);
+//! ```
+//!
+//! While a read transaction is alive `reboot`, `reset` and `revert` refuse to
+//! run, since they would drop versions it can still see. Dropping it is all
+//! it takes to end it.
+//!
+//! ```
+//! use graph_mvcc::Graph;
+//!
+//! let mut graph = Graph::new();
+//! let mut tx = graph.start_transaction();
+//! let node = graph.add_node(&mut tx);
+//! graph.commit_transaction(&tx).unwrap();
+//!
+//! let reader = graph.start_read_transaction();
+//! assert!(graph.node_is_visible(&reader, &node));
+//! assert!(graph.read_nodes(&reader, &node, vec!["red".to_string()]).is_empty());
+//! ```
+
+use std::ops::Deref;
+use std::sync::Arc;
+
+use crate::{Graph, Node, TransactionId};
+
+/// A transaction that can only read. See the module documentation.
+#[derive(Debug)]
+pub struct ReadTransaction {
+    t: TransactionId,
+    // Keeps the graph's count of live readers up
+    _reader: Arc<()>,
+}
+
+impl Deref for ReadTransaction {
+    type Target = TransactionId;
+
+    fn deref(&self) -> &TransactionId {
+        &self.t
+    }
+}
+
+/// Counts the read transactions alive. A cloned graph starts with none.
+#[derive(Debug, Default)]
+pub(crate) struct Readers(Arc<()>);
+
+impl Clone for Readers {
+    fn clone(&self) -> Self {
+        Readers::default()
+    }
+}
+
+impl Readers {
+    pub(crate) fn alive(&self) -> usize {
+        Arc::strong_count(&self.0) - 1
+    }
+}
+
+impl Graph {
+    /// Starts a read-only transaction on the committed state of the graph.
+    pub fn start_read_transaction(&mut self) -> ReadTransaction {
+        self.reap_expired_transactions();
+        let in_flight = self.conductor.active().clone();
+        // The txid is only needed to place the snapshot, so the transaction
+        // leaves the active set as soon as it has one.
+        let txid = self.conductor.begin();
+        self.conductor.abort(txid);
+
+        let mut t = TransactionId::new(txid);
+        t.in_flight = in_flight;
+        ReadTransaction {
+            t,
+            _reader: Arc::clone(&self.readers.0),
+        }
+    }
+
+    /// `get_nodes_internal` for a read-only transaction, without read locks.
+    pub fn read_nodes(&self, t: &ReadTransaction, origin: &Node, search_path: Vec<String>) -> Vec<Node> {
+        self.traverse_with_snapshot(t, origin, search_path)
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Unit Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ElementId, IGraph, TxError};
+
+    #[test]
+    fn test_read_transaction_sees_its_snapshot() {
+        let mut graph = Graph::new();
+        let mut tx = graph.start_transaction();
+        let node = graph.add_node(&mut tx);
+        let element = ElementId::Node(node.id().clone());
+        graph.set_property(&mut tx, element.clone(), "name".to_string(), "before".into()).unwrap();
+        graph.commit_transaction(&tx).unwrap();
+
+        let reader = graph.start_read_transaction();
+        assert!(graph.conductor.active().is_empty());
+
+        let mut writer = graph.start_transaction();
+        graph.set_property(&mut writer, element.clone(), "name".to_string(), "after".into()).unwrap();
+        assert_eq!(graph.get_property(&reader, &element, "name"), Some("before".into()));
+        graph.commit_transaction(&writer).unwrap();
+
+        assert_eq!(graph.get_property(&reader, &element, "name"), Some("before".into()));
+        assert_eq!(graph.traversal(&reader).v(node.id()).count(), 1);
+        assert!(reader.read_locks.is_empty());
+    }
+
+    #[test]
+    fn test_igraph_reads_leave_no_transaction_behind() {
+        let mut graph = Graph::new();
+        let a = IGraph::add_node(&mut graph, None).unwrap();
+        let b = IGraph::add_node(&mut graph, None).unwrap();
+        IGraph::add_edge(&mut graph, None, a.clone(), b.clone(), "red".to_string()).unwrap();
+        let timestamps = graph.commit_timestamps.len();
+
+        assert_eq!(IGraph::get_nodes(&mut graph, None, a, vec!["red".to_string()]), Ok(vec![b]));
+        assert!(graph.conductor.active().is_empty());
+        assert_eq!(graph.commit_timestamps.len(), timestamps);
+    }
+
+    #[test]
+    fn test_read_transactions_hold_off_system_operations() {
+        let mut graph = Graph::new();
+        let reader = graph.start_read_transaction();
+        assert_eq!(graph.reboot(), Err(TxError::TransactionsActive(1)));
+        drop(reader);
+        assert!(graph.reboot().is_ok());
+    }
+}
@@ -0,0 +1,366 @@
+//! # Record store
+//!
+//! A versioned key-value store that lives next to the graph and takes part in
+//! the same transactions. Every key holds a chain of versions; each version
+//! carries a header (key, per-key revision and the creating and expiring
+//! txids) and a payload, which can be any value serde can serialize.
+//!
+//! ```
+//! use graph_mvcc::Graph;
+//!
+//! let mut graph = Graph::new();
+//! let mut tx = graph.start_transaction();
+//! let station = graph.add_node(&mut tx);
+//! graph.record_store(&mut tx).put("config", &vec![1, 2, 3]).unwrap();
+//! graph.commit_transaction(&tx).unwrap();
+//!
+//! let mut reader = graph.start_transaction();
+//! let config: Option<Vec<i32>> = graph.record_store(&mut reader).get("config").unwrap();
+//! assert_eq!(config, Some(vec![1, 2, 3]));
+//! assert!(graph.node_is_visible(&reader, &station));
+//! ```
+
+use std::collections::BTreeMap;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::{Graph, Resource, TransactionId, TxError, TxResult, UndoAction, Version, MVCC};
+
+/// One version of a record.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordVersion {
+    pub key: String,
+    /// Position of the version in the key's history, starting at 1.
+    pub revision: u64,
+    pub version: Version,
+    pub payload: Value,
+}
+
+/// The record store as seen by transaction `t`.
+#[derive(Debug)]
+pub struct RecordStore<'graph> {
+    graph: &'graph mut Graph,
+    t: &'graph mut TransactionId,
+}
+
+impl Graph {
+    pub fn record_store<'graph>(&'graph mut self, t: &'graph mut TransactionId) -> RecordStore<'graph> {
+        RecordStore { graph: self, t }
+    }
+
+    /// The version of `key` visible to `t`.
+    pub(crate) fn visible_record(&self, t: &TransactionId, key: &str) -> Option<&RecordVersion> {
+        self.store
+            .get(key)?
+            .iter()
+            .rev()
+            .find(|record| self.version_is_visible(t, &record.version))
+    }
+}
+
+impl RecordStore<'_> {
+    /// Writes `payload` as the new version of `key` and returns its revision.
+    pub fn put<T: Serialize + ?Sized>(&mut self, key: &str, payload: &T) -> TxResult<u64> {
+        let payload = serde_json::to_value(payload).map_err(|error| TxError::invalid_record(key, error))?;
+        self.expire(key)?;
+
+        let versions = self.graph.store.entry(key.to_string()).or_default();
+        let revision = versions.last().map_or(1, |record| record.revision + 1);
+        versions.push(RecordVersion {
+            key: key.to_string(),
+            revision,
+            version: Version::new(self.t.txid),
+            payload,
+        });
+        self.graph.writes.push(self.t, UndoAction::PutRecord(key.to_string()));
+        Ok(revision)
+    }
+
+    /// The payload of `key`, or `None` if the key has no visible version.
+    pub fn get<T: DeserializeOwned>(&self, key: &str) -> TxResult<Option<T>> {
+        match self.graph.visible_record(self.t, key) {
+            Some(record) => serde_json::from_value(record.payload.clone())
+                .map(Some)
+                .map_err(|error| TxError::invalid_record(key, error)),
+            None => Ok(None),
+        }
+    }
+
+    /// The visible version of `key` including its header.
+    pub fn version(&self, key: &str) -> Option<RecordVersion> {
+        self.graph.visible_record(self.t, key).cloned()
+    }
+
+    /// Like `put`, but fails with `TxError::ElementNotFound` if the key has
+    /// no visible version.
+    pub fn update<T: Serialize + ?Sized>(&mut self, key: &str, payload: &T) -> TxResult<u64> {
+        if self.graph.visible_record(self.t, key).is_none() {
+            return Err(TxError::ElementNotFound(Resource::StoreKey(key.to_string())));
+        }
+        self.put(key, payload)
+    }
+
+    pub fn delete(&mut self, key: &str) -> TxResult<()> {
+        if self.graph.visible_record(self.t, key).is_none() {
+            return Err(TxError::ElementNotFound(Resource::StoreKey(key.to_string())));
+        }
+        self.expire(key)
+    }
+
+    /// Every version of `key` that belongs to the view of `t`, including
+    /// the ones that have since been replaced or deleted, oldest first.
+    pub fn history(&self, key: &str) -> Vec<RecordVersion> {
+        let t = &*self.t;
+        let created = |version: &Version| version.creation_id == t.txid || self.graph.committed_before(t, version.creation_id);
+        match self.graph.store.get(key) {
+            Some(versions) => versions.iter().filter(|record| created(&record.version)).cloned().collect(),
+            None => Vec::new(),
+        }
+    }
+
+    /// The keys with a visible version, in order.
+    pub fn keys(&self) -> Vec<String> {
+        self.graph
+            .store
+            .keys()
+            .filter(|key| self.graph.visible_record(self.t, key).is_some())
+            .cloned()
+            .collect()
+    }
+
+    fn expire(&mut self, key: &str) -> TxResult<()> {
+        self.graph.check_active(self.t)?;
+        let graph = &*self.graph;
+        let t = &*self.t;
+        let expire: Vec<usize> = match graph.store.get(key) {
+            Some(versions) => {
+                let resource = Resource::StoreKey(key.to_string());
+                graph.locked_version(t, versions.iter().map(|record| &record.version), resource)?;
+                versions
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, record)| graph.version_is_visible(t, &record.version))
+                    .map(|(i, _)| i)
+                    .collect()
+            }
+            None => Vec::new(),
+        };
+
+        if let Some(versions) = self.graph.store.get_mut(key) {
+            for i in expire {
+                versions[i].version.expiration_id = self.t.txid;
+                self.graph.writes.push(self.t, UndoAction::RemoveRecord(key.to_string()));
+            }
+        }
+        Ok(())
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Record Table
+
+/// Stable reference to one version of a `BTreeMap<MVCC, u64>` record. A
+/// handle keeps pointing at the same version no matter what happens to the
+/// other records.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Hash)]
+pub struct RecordHandle(u64);
+
+/// The records added with `Graph::add_record`, keyed by handle, plus the
+/// version chain of every `MVCC::ElementId` so a record's versions are found
+/// without scanning. Versions written by `Graph::update_record` also carry
+/// the value they were given.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct RecordTable {
+    next_handle: u64,
+    rows: BTreeMap<RecordHandle, BTreeMap<MVCC, u64>>,
+    chains: BTreeMap<u64, Vec<RecordHandle>>,
+    values: BTreeMap<RecordHandle, String>,
+}
+
+impl RecordTable {
+    pub(crate) fn insert(&mut self, record: BTreeMap<MVCC, u64>) -> RecordHandle {
+        self.next_handle += 1;
+        let handle = RecordHandle(self.next_handle);
+        if let Some(&element_id) = record.get(&MVCC::ElementId) {
+            self.chains.entry(element_id).or_default().push(handle);
+        }
+        self.rows.insert(handle, record);
+        handle
+    }
+
+    /// A handle that no record will ever be stored under.
+    pub(crate) fn reserve(&mut self) -> RecordHandle {
+        self.next_handle += 1;
+        RecordHandle(self.next_handle)
+    }
+
+    pub(crate) fn remove(&mut self, handle: RecordHandle) -> Option<BTreeMap<MVCC, u64>> {
+        let record = self.rows.remove(&handle)?;
+        self.values.remove(&handle);
+        if let Some(element_id) = record.get(&MVCC::ElementId) {
+            if let Some(chain) = self.chains.get_mut(element_id) {
+                chain.retain(|listed| *listed != handle);
+                if chain.is_empty() {
+                    self.chains.remove(element_id);
+                }
+            }
+        }
+        Some(record)
+    }
+
+    pub(crate) fn get(&self, handle: RecordHandle) -> Option<&BTreeMap<MVCC, u64>> {
+        self.rows.get(&handle)
+    }
+
+    /// Mutable access to a record. The `MVCC::ElementId` must not change.
+    pub(crate) fn get_mut(&mut self, handle: RecordHandle) -> Option<&mut BTreeMap<MVCC, u64>> {
+        self.rows.get_mut(&handle)
+    }
+
+    pub(crate) fn set_value(&mut self, handle: RecordHandle, value: String) {
+        if self.rows.contains_key(&handle) {
+            self.values.insert(handle, value);
+        }
+    }
+
+    pub(crate) fn value(&self, handle: RecordHandle) -> Option<&str> {
+        self.values.get(&handle).map(String::as_str)
+    }
+
+    /// The versions of `element_id`, oldest first.
+    pub(crate) fn chain(&self, element_id: u64) -> impl Iterator<Item = (RecordHandle, &BTreeMap<MVCC, u64>)> {
+        self.chains
+            .get(&element_id)
+            .into_iter()
+            .flatten()
+            .filter_map(|handle| self.rows.get(handle).map(|record| (*handle, record)))
+    }
+
+    pub(crate) fn iter(&self) -> impl Iterator<Item = (RecordHandle, &BTreeMap<MVCC, u64>)> {
+        self.rows.iter().map(|(handle, record)| (*handle, record))
+    }
+
+    pub(crate) fn retain(&mut self, mut keep: impl FnMut(&BTreeMap<MVCC, u64>) -> bool) {
+        let dropped: Vec<RecordHandle> = self.iter().filter(|(_, record)| !keep(record)).map(|(handle, _)| handle).collect();
+        for handle in dropped {
+            self.remove(handle);
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.rows.len()
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Unit Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Conflict, ErrorKind};
+    use serde::Deserialize;
+
+    #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+    struct Timetable {
+        line: String,
+        departures: Vec<u32>,
+    }
+
+    #[test]
+    fn test_put_get_update_delete() {
+        let mut graph = Graph::new();
+        let mut tx = graph.start_transaction();
+        let timetable = Timetable { line: "red".to_string(), departures: vec![600, 630] };
+        let mut store = graph.record_store(&mut tx);
+        assert_eq!(store.put("red", &timetable).unwrap(), 1);
+        assert_eq!(store.get::<Timetable>("red").unwrap(), Some(timetable.clone()));
+        assert_eq!(store.update("blue", &timetable), Err(TxError::ElementNotFound(Resource::StoreKey("blue".to_string()))));
+        assert_eq!(store.get::<u32>("red").unwrap_err().kind(), ErrorKind::InvalidRecord);
+        graph.commit_transaction(&tx).unwrap();
+
+        let mut tx = graph.start_transaction();
+        let mut store = graph.record_store(&mut tx);
+        let later = Timetable { departures: vec![700], ..timetable.clone() };
+        assert_eq!(store.update("red", &later).unwrap(), 2);
+        store.put("blue", "unused").unwrap();
+        store.delete("blue").unwrap();
+        assert_eq!(store.keys(), vec!["red".to_string()]);
+        assert_eq!(store.version("red").unwrap().version.creation_id, tx.txid);
+        graph.commit_transaction(&tx).unwrap();
+
+        let mut reader = graph.start_transaction();
+        let history = graph.record_store(&mut reader).history("red");
+        let revisions: Vec<u64> = history.iter().map(|record| record.revision).collect();
+        assert_eq!(revisions, vec![1, 2]);
+        assert_eq!(history[0].payload["departures"], serde_json::json!([600, 630]));
+    }
+
+    #[test]
+    fn test_records_follow_the_transaction() {
+        let mut graph = Graph::new();
+        let mut tx = graph.start_transaction();
+        graph.record_store(&mut tx).put("counter", &1).unwrap();
+        graph.commit_transaction(&tx).unwrap();
+
+        let mut reader = graph.start_transaction();
+        let mut writer = graph.start_transaction();
+        graph.record_store(&mut writer).put("counter", &2).unwrap();
+        let locked = graph.record_store(&mut reader).put("counter", &3).unwrap_err();
+        assert_eq!(locked, TxError::TransactionLocked(Conflict::new(reader.txid).with(writer.txid).on(Resource::StoreKey("counter".to_string()))));
+        graph.commit_transaction(&writer).unwrap();
+        assert_eq!(graph.record_store(&mut reader).get::<i32>("counter").unwrap(), Some(1));
+
+        let mut aborted = graph.start_transaction();
+        graph.record_store(&mut aborted).put("counter", &4).unwrap();
+        aborted.savepoint("before_delete");
+        graph.record_store(&mut aborted).delete("counter").unwrap();
+        graph.rollback_to(&mut aborted, "before_delete").unwrap();
+        assert_eq!(graph.record_store(&mut aborted).get::<i32>("counter").unwrap(), Some(4));
+        graph.abort_transaction(&aborted).unwrap();
+
+        let mut fresh = graph.start_transaction();
+        assert_eq!(graph.record_store(&mut fresh).get::<i32>("counter").unwrap(), Some(2));
+    }
+
+    #[test]
+    fn test_record_table_handles_and_chains() {
+        let mut graph = Graph::new();
+        let mut tx = graph.start_transaction();
+        let mut handles = Vec::new();
+        for id in [5, 6, 5] {
+            let mut record = BTreeMap::new();
+            record.insert(MVCC::ElementId, id);
+            handles.push(graph.add_record(&mut tx, &mut record));
+        }
+        assert_eq!(graph.records.chain(5).map(|(handle, _)| handle).collect::<Vec<_>>(), vec![handles[0], handles[2]]);
+
+        // Removing a version leaves every other handle pointing where it did.
+        graph.records.remove(handles[0]);
+        assert_eq!(graph.record(handles[1]).unwrap()[&MVCC::ElementId], 6);
+        assert_eq!(graph.records.chain(5).count(), 1);
+
+        graph.delete_record(&mut tx, 5).unwrap();
+        graph.set_transaction_expiration(handles[2], 900);
+        let record = graph.record(handles[2]).unwrap();
+        assert_eq!(record[&MVCC::TransactionExpirationId], tx.txid);
+        assert_eq!(record[&MVCC::TransactionExpired], 900);
+
+        graph.abort_transaction(&tx).unwrap();
+        assert_eq!(graph.records.len(), 0);
+    }
+
+    #[test]
+    fn test_record_changes_are_published() {
+        let mut graph = Graph::new();
+        let changes = graph.subscribe(None);
+        let mut tx = graph.start_transaction();
+        graph.record_store(&mut tx).put("a", &true).unwrap();
+        graph.commit_transaction(&tx).unwrap();
+
+        let event = changes.try_recv().unwrap();
+        assert_eq!(event.record_changes[0].key, "a");
+        assert_eq!(event.record_changes[0].payload, Some(Value::Bool(true)));
+    }
+}
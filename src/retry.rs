@@ -0,0 +1,212 @@
+//! # Retrying transactions
+//!
+//! `transact` runs a closure inside a fresh transaction and commits it. When
+//! the closure or the commit fails with an error that another attempt can
+//! get past (see `TxError::is_retryable`), the transaction is rolled back
+//! and the closure run again in a new one, after a backoff. Other errors are
+//! returned straight away. `RetryPolicy` sets the number of attempts and the
+//! backoff.
+//!
+//! ```
+//! use graph_mvcc::{ElementId, Graph};
+//!
+//! let mut graph = Graph::new();
+//! let node = graph.transact(|graph, tx| Ok(graph.add_node(tx))).unwrap();
+//! graph
+//!     .transact(|graph, tx| {
+//!         let element = ElementId::Node(node.id().clone());
+//!         graph.set_property(tx, element, "visits".to_string(), 1i64.into())
+//!     })
+//!     .unwrap();
+//! ```
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::thread;
+use std::time::Duration;
+
+use crate::{Graph, TransactionId, TxResult};
+
+/// How often and how patiently `transact_with` retries.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RetryPolicy {
+    max_attempts: usize,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    multiplier: f64,
+    jitter: f64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 5,
+            initial_backoff: Duration::from_millis(10),
+            max_backoff: Duration::from_secs(1),
+            multiplier: 2.0,
+            jitter: 0.5,
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Runs in total, the first one included. At least one run is made.
+    pub fn max_attempts(mut self, n: usize) -> Self {
+        self.max_attempts = n.max(1);
+        self
+    }
+
+    /// The wait after the first failed run, multiplied by `multiplier` after
+    /// each further one, up to `max`.
+    pub fn backoff(mut self, initial: Duration, multiplier: f64, max: Duration) -> Self {
+        self.initial_backoff = initial;
+        self.multiplier = multiplier.max(1.0);
+        self.max_backoff = max;
+        self
+    }
+
+    /// Shortens each wait by a random part of up to `fraction` of it, so that
+    /// transactions that conflicted don't retry in lockstep.
+    pub fn jitter(mut self, fraction: f64) -> Self {
+        self.jitter = fraction.clamp(0.0, 1.0);
+        self
+    }
+
+    /// The wait after the `attempt`th failed run, counting from 1.
+    fn delay(&self, attempt: usize) -> Duration {
+        let exponent = i32::try_from(attempt - 1).unwrap_or(i32::MAX);
+        let backoff = self
+            .initial_backoff
+            .mul_f64(self.multiplier.powi(exponent).min(u32::MAX as f64))
+            .min(self.max_backoff);
+        backoff.mul_f64(1.0 - self.jitter * random())
+    }
+}
+
+/// A number in `[0, 1)` from a splitmix64 sequence that every retry draws
+/// from, so retries running at the same time wait for different lengths.
+fn random() -> f64 {
+    const GAMMA: u64 = 0x9e37_79b9_7f4a_7c15;
+    static STATE: AtomicU64 = AtomicU64::new(0);
+    let mut z = STATE.fetch_add(GAMMA, Ordering::Relaxed).wrapping_add(GAMMA);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^= z >> 31;
+    (z >> 11) as f64 / (1u64 << 53) as f64
+}
+
+impl Graph {
+    /// Runs `f` in a transaction with the default `RetryPolicy`.
+    pub fn transact<R>(&mut self, f: impl FnMut(&mut Graph, &mut TransactionId) -> TxResult<R>) -> TxResult<R> {
+        self.transact_with(&RetryPolicy::default(), f)
+    }
+
+    /// Runs `f` in a new transaction and commits it, starting over as long
+    /// as it fails with a retryable error and `policy` allows another attempt.
+    pub fn transact_with<R>(
+        &mut self,
+        policy: &RetryPolicy,
+        mut f: impl FnMut(&mut Graph, &mut TransactionId) -> TxResult<R>,
+    ) -> TxResult<R> {
+        let mut attempt = 1;
+        loop {
+            let mut t = self.start_transaction();
+            let error = match f(self, &mut t) {
+                Ok(result) => match self.commit_transaction(&t) {
+                    Ok(()) => return Ok(result),
+                    // A failed commit has already rolled back
+                    Err(error) => error,
+                },
+                Err(error) => {
+                    // The closure's error is what the caller needs to see,
+                    // not a failure to clean up after it
+                    if self.conductor.active().contains(&t.txid) {
+                        let _ = self.abort_transaction(&t);
+                    }
+                    error
+                }
+            };
+
+            if !error.is_retryable() || attempt >= policy.max_attempts {
+                return Err(error);
+            }
+            thread::sleep(policy.delay(attempt));
+            attempt += 1;
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Unit Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Conflict, ElementId, ErrorKind, Node, TxError};
+
+    fn no_wait() -> RetryPolicy {
+        RetryPolicy::new().backoff(Duration::ZERO, 1.0, Duration::ZERO)
+    }
+
+    #[test]
+    fn test_conflicting_transaction_is_rerun() {
+        let mut graph = Graph::new();
+        let node = graph.transact(|graph, tx| Ok(graph.add_node(tx))).unwrap();
+        let element = ElementId::Node(node.id().clone());
+
+        let mut attempts = 0;
+        let result = graph.transact_with(&no_wait(), |graph, tx| {
+            attempts += 1;
+            if attempts == 1 {
+                // Someone else writes the property first
+                let mut other = graph.start_transaction();
+                graph.set_property(&mut other, element.clone(), "owner".to_string(), "other".into())?;
+                graph.commit_transaction(&other)?;
+            }
+            graph.set_property(tx, element.clone(), "owner".to_string(), "me".into())
+        });
+
+        assert_eq!(result, Ok(()));
+        assert_eq!(attempts, 2);
+        let reader = graph.start_transaction();
+        assert_eq!(graph.get_property(&reader, &element, "owner"), Some("me".into()));
+    }
+
+    #[test]
+    fn test_permanent_errors_are_not_retried() {
+        let mut graph = Graph::new();
+        let missing = Node::new();
+        let mut attempts = 0;
+        let result = graph.transact_with(&no_wait(), |graph, tx| {
+            attempts += 1;
+            graph.add_node(tx);
+            graph.remove_node(tx, &missing)
+        });
+
+        assert_eq!(result, Err(TxError::NodeNotFound(missing.id().clone())));
+        assert_eq!(attempts, 1);
+        let reader = graph.start_transaction();
+        assert!(graph.visible_nodes(&reader).is_empty());
+    }
+
+    #[test]
+    fn test_attempts_and_backoff_are_bounded() {
+        let mut graph = Graph::new();
+        let mut attempts = 0;
+        let result: TxResult<()> = graph.transact_with(&no_wait().max_attempts(3), |_, _| {
+            attempts += 1;
+            Err(TxError::Deadlock(Conflict::new(0)))
+        });
+        assert_eq!(result.unwrap_err().kind(), ErrorKind::Deadlock);
+        assert_eq!(attempts, 3);
+
+        let policy = RetryPolicy::new().backoff(Duration::from_millis(10), 2.0, Duration::from_millis(50)).jitter(0.5);
+        assert!(policy.delay(1) <= Duration::from_millis(10) && policy.delay(1) >= Duration::from_millis(5));
+        assert!(policy.delay(10) <= Duration::from_millis(50) && policy.delay(10) >= Duration::from_millis(25));
+        let draws: Vec<f64> = (0..100).map(|_| random()).collect();
+        assert!(draws.iter().all(|draw| (0.0..1.0).contains(draw)));
+        assert!(draws.windows(2).any(|pair| pair[0] != pair[1]));
+    }
+}
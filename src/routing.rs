@@ -0,0 +1,322 @@
+//! # Routing
+//!
+//! Shortest paths over a transaction's view of the graph: breadth-first
+//! search by hop count, and Dijkstra or A* over a numeric edge property.
+//! Routes only follow edges of the allowed types, and since every lookup goes
+//! through the transaction, the route is consistent with what the rest of the
+//! transaction reads.
+//!
+//! ```
+//! use graph_mvcc::{ElementId, Graph};
+//!
+//! let mut graph = Graph::new();
+//! let mut tx = graph.start_transaction();
+//! let a = graph.add_node(&mut tx);
+//! let b = graph.add_node(&mut tx);
+//! graph.add_edge(&mut tx, &a, &b, "Rail".to_string()).unwrap();
+//! let (_, rail) = graph.visible_edges(&tx, &a).remove(0);
+//! graph.set_property(&mut tx, ElementId::Edge(rail.id().clone()), "minutes".to_string(), 42i64.into()).unwrap();
+//!
+//! let route = graph.router(&tx).edge_types(&["Rail"]).dijkstra(a.id(), b.id(), "minutes").unwrap().unwrap();
+//! assert_eq!(route.cost, 42.0);
+//! ```
+
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap, VecDeque};
+
+use crate::traversal::Direction;
+use crate::{EdgeId, ElementId, Graph, Node, NodeId, PredicateLock, PropertyValue, TransactionId, TxError, TxResult};
+
+/// A path found by the router. `nodes` starts with the origin and ends with
+/// the destination, `edges[i]` connects `nodes[i]` and `nodes[i + 1]`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Route {
+    pub nodes: Vec<NodeId>,
+    pub edges: Vec<EdgeId>,
+    /// Number of hops for `shortest_path`, summed weights otherwise.
+    pub cost: f64,
+}
+
+/// Route planner over the view of transaction `t`.
+#[derive(Debug, Clone)]
+pub struct Router<'graph> {
+    graph: &'graph Graph,
+    t: &'graph TransactionId,
+    edge_types: Vec<String>,
+    directed: bool,
+}
+
+impl Graph {
+    pub fn router<'graph>(&'graph self, t: &'graph TransactionId) -> Router<'graph> {
+        Router {
+            graph: self,
+            t,
+            edge_types: Vec::new(),
+            directed: false,
+        }
+    }
+}
+
+/// Priority queue entry ordered so that `BinaryHeap` pops the lowest
+/// estimate first.
+struct Candidate {
+    estimate: f64,
+    cost: f64,
+    node: NodeId,
+}
+
+impl PartialEq for Candidate {
+    fn eq(&self, other: &Self) -> bool {
+        self.estimate.total_cmp(&other.estimate) == Ordering::Equal
+    }
+}
+
+impl Eq for Candidate {}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.estimate.total_cmp(&self.estimate)
+    }
+}
+
+impl<'graph> Router<'graph> {
+    /// Restricts routes to edges of these types. By default every type is allowed.
+    pub fn edge_types(mut self, edge_types: &[&str]) -> Self {
+        self.edge_types = edge_types.iter().map(|edge_type| edge_type.to_string()).collect();
+        self
+    }
+
+    /// Only follow edges from their source to their target. By default edges
+    /// are followed both ways, like `get_nodes` does.
+    pub fn directed(mut self, directed: bool) -> Self {
+        self.directed = directed;
+        self
+    }
+
+    /// Allowed edges leaving `node`, with the node they lead to. The scanned
+    /// neighborhood is registered as a predicate lock of the transaction.
+    fn neighbors(&self, node: &NodeId) -> Vec<(NodeId, EdgeId)> {
+        let direction = if self.directed { Direction::Out } else { Direction::Both };
+        if self.edge_types.is_empty() {
+            self.t.register_predicate(PredicateLock::new(node.clone(), None, direction));
+        }
+        for edge_type in &self.edge_types {
+            self.t.register_predicate(PredicateLock::new(node.clone(), Some(edge_type.clone()), direction));
+        }
+        self.graph
+            .visible_edges(self.t, &Node { id: node.clone() })
+            .into_iter()
+            .filter(|(_, edge)| self.edge_types.is_empty() || self.edge_types.iter().any(|edge_type| edge_type == edge.edgetype()))
+            .filter(|(_, edge)| !self.directed || edge.source() == node)
+            .map(|(other, edge)| (other.id().clone(), edge.id().clone()))
+            .collect()
+    }
+
+    fn weight(&self, edge: &EdgeId, key: &str) -> TxResult<Option<f64>> {
+        let weight = match self.graph.get_property(self.t, &ElementId::Edge(edge.clone()), key) {
+            Some(PropertyValue::Int(i)) => i as f64,
+            Some(PropertyValue::Float(x)) => x,
+            _ => return Ok(None),
+        };
+        if weight < 0.0 || weight.is_nan() {
+            return Err(TxError::InvalidWeight(format!("edge {:?} has weight {} for '{}'", edge, weight, key)));
+        }
+        Ok(Some(weight))
+    }
+
+    fn endpoints_visible(&self, from: &NodeId, to: &NodeId) -> bool {
+        self.graph.visible_node(self.t, from).is_some() && self.graph.visible_node(self.t, to).is_some()
+    }
+
+    /// Fewest hops from `from` to `to`, found by breadth-first search.
+    pub fn shortest_path(&self, from: &NodeId, to: &NodeId) -> Option<Route> {
+        if !self.endpoints_visible(from, to) {
+            return None;
+        }
+
+        let mut previous: HashMap<NodeId, Option<(NodeId, EdgeId)>> = HashMap::new();
+        previous.insert(from.clone(), None);
+        let mut queue = VecDeque::from(vec![from.clone()]);
+
+        while let Some(node) = queue.pop_front() {
+            if &node == to {
+                let route = unwind(&previous, to);
+                let hops = route.edges.len() as f64;
+                return Some(Route { cost: hops, ..route });
+            }
+            for (next, edge) in self.neighbors(&node) {
+                if !previous.contains_key(&next) {
+                    previous.insert(next.clone(), Some((node.clone(), edge)));
+                    queue.push_back(next);
+                }
+            }
+        }
+        None
+    }
+
+    /// Cheapest path by the numeric edge property `weight`. Edges without a
+    /// numeric value for it are not followed; negative weights are an error.
+    pub fn dijkstra(&self, from: &NodeId, to: &NodeId, weight: &str) -> TxResult<Option<Route>> {
+        self.a_star(from, to, weight, |_| 0.0)
+    }
+
+    /// Like `dijkstra`, guided by `heuristic`, an estimate of the remaining
+    /// cost from a node to `to`. The route is optimal as long as the estimate
+    /// never exceeds the real remaining cost.
+    pub fn a_star(&self, from: &NodeId, to: &NodeId, weight: &str, heuristic: impl Fn(&NodeId) -> f64) -> TxResult<Option<Route>> {
+        if !self.endpoints_visible(from, to) {
+            return Ok(None);
+        }
+
+        let mut best: HashMap<NodeId, f64> = HashMap::new();
+        let mut previous: HashMap<NodeId, Option<(NodeId, EdgeId)>> = HashMap::new();
+        let mut heap = BinaryHeap::new();
+        best.insert(from.clone(), 0.0);
+        previous.insert(from.clone(), None);
+        heap.push(Candidate { estimate: heuristic(from), cost: 0.0, node: from.clone() });
+
+        while let Some(Candidate { cost, node, .. }) = heap.pop() {
+            if &node == to {
+                let route = unwind(&previous, to);
+                return Ok(Some(Route { cost, ..route }));
+            }
+            if best.get(&node).is_some_and(|&known| cost > known) {
+                continue;
+            }
+            for (next, edge) in self.neighbors(&node) {
+                let step = match self.weight(&edge, weight)? {
+                    Some(step) => step,
+                    None => continue,
+                };
+                let candidate = cost + step;
+                if best.get(&next).is_none_or(|&known| candidate < known) {
+                    best.insert(next.clone(), candidate);
+                    previous.insert(next.clone(), Some((node.clone(), edge)));
+                    heap.push(Candidate { estimate: candidate + heuristic(&next), cost: candidate, node: next });
+                }
+            }
+        }
+        Ok(None)
+    }
+}
+
+fn unwind(previous: &HashMap<NodeId, Option<(NodeId, EdgeId)>>, to: &NodeId) -> Route {
+    let mut nodes = vec![to.clone()];
+    let mut edges = Vec::new();
+    let mut current = to;
+    while let Some(Some((node, edge))) = previous.get(current) {
+        nodes.push(node.clone());
+        edges.push(edge.clone());
+        current = node;
+    }
+    nodes.reverse();
+    edges.reverse();
+    Route { nodes, edges, cost: 0.0 }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Unit Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    /// a -Road(10)-> b -Road2(10)-> d, a -Rail(5)-> c -Rail2(30)-> d, a -Air(100)-> d
+    const NETWORK: [(usize, usize, &str); 5] = [(0, 1, "Road"), (1, 3, "Road2"), (0, 2, "Rail"), (2, 3, "Rail2"), (0, 3, "Air")];
+    const MINUTES: [(&str, i64); 5] = [("Road", 10), ("Road2", 10), ("Rail", 5), ("Rail2", 30), ("Air", 100)];
+
+    #[test]
+    fn test_shortest_path_by_hops() {
+        let (mut graph, nodes) = testing::graph(4, &NETWORK);
+        let tx = graph.start_transaction();
+        let route = graph.router(&tx).shortest_path(nodes[0].id(), nodes[3].id()).unwrap();
+        assert_eq!(route.nodes, vec![nodes[0].id().clone(), nodes[3].id().clone()]);
+        assert_eq!(route.cost, 1.0);
+
+        let route = graph.router(&tx).edge_types(&["Rail", "Rail2"]).shortest_path(nodes[0].id(), nodes[3].id()).unwrap();
+        assert_eq!(route.nodes, vec![nodes[0].id().clone(), nodes[2].id().clone(), nodes[3].id().clone()]);
+        assert_eq!(route.edges.len(), 2);
+    }
+
+    #[test]
+    fn test_dijkstra_and_a_star() {
+        let (mut graph, nodes) = testing::graph(4, &NETWORK);
+        testing::set_edge_property(&mut graph, "minutes", &MINUTES);
+        let tx = graph.start_transaction();
+        let route = graph.router(&tx).dijkstra(nodes[0].id(), nodes[3].id(), "minutes").unwrap().unwrap();
+        assert_eq!(route.nodes, vec![nodes[0].id().clone(), nodes[1].id().clone(), nodes[3].id().clone()]);
+        assert_eq!(route.cost, 20.0);
+
+        let route = graph.router(&tx).a_star(nodes[0].id(), nodes[3].id(), "minutes", |_| 1.0).unwrap().unwrap();
+        assert_eq!(route.cost, 20.0);
+
+        let route = graph.router(&tx).edge_types(&["Rail", "Rail2", "Air"]).dijkstra(nodes[0].id(), nodes[3].id(), "minutes").unwrap().unwrap();
+        assert_eq!(route.cost, 35.0);
+    }
+
+    #[test]
+    fn test_directed_routes() {
+        let (mut graph, nodes) = testing::graph(4, &NETWORK);
+        let tx = graph.start_transaction();
+        assert!(graph.router(&tx).directed(true).shortest_path(nodes[3].id(), nodes[0].id()).is_none());
+        assert!(graph.router(&tx).directed(false).shortest_path(nodes[3].id(), nodes[0].id()).is_some());
+    }
+
+    #[test]
+    fn test_negative_weight_is_rejected() {
+        let (mut graph, nodes) = testing::graph(4, &NETWORK);
+        testing::set_edge_property(&mut graph, "minutes", &MINUTES);
+        let mut tx = graph.start_transaction();
+        let edge = graph.add_edge_internal(&mut tx, &nodes[1], &nodes[2], "Ferry".to_string()).unwrap();
+        graph.set_property(&mut tx, ElementId::Edge(edge.id().clone()), "minutes".to_string(), (-1i64).into()).unwrap();
+        let result = graph.router(&tx).dijkstra(nodes[0].id(), nodes[3].id(), "minutes");
+        assert!(matches!(result, Err(TxError::InvalidWeight(_))));
+    }
+
+    #[test]
+    fn test_routes_follow_the_snapshot() {
+        let (mut graph, nodes) = testing::graph(4, &NETWORK);
+        testing::set_edge_property(&mut graph, "minutes", &MINUTES);
+
+        // A concurrent, uncommitted closure of the road is not seen by others.
+        let mut closing = graph.start_transaction();
+        let (_, road) = graph.visible_edges(&closing, &nodes[0]).into_iter()
+            .find(|(_, edge)| edge.edgetype() == "Road").unwrap();
+        graph.remove_edge(&mut closing, &road).unwrap();
+
+        let reader = graph.start_transaction();
+        let route = graph.router(&reader).dijkstra(nodes[0].id(), nodes[3].id(), "minutes").unwrap().unwrap();
+        assert_eq!(route.cost, 20.0);
+        let route = graph.router(&closing).dijkstra(nodes[0].id(), nodes[3].id(), "minutes").unwrap().unwrap();
+        assert_eq!(route.cost, 35.0);
+    }
+
+    #[test]
+    fn test_routes_lock_what_they_scan() {
+        let (mut graph, nodes) = testing::graph(4, &NETWORK);
+        let reader = graph.start_serializable_transaction();
+        assert!(graph.router(&reader).edge_types(&["Rail"]).directed(true).shortest_path(nodes[0].id(), nodes[1].id()).is_none());
+        assert!(graph.router(&reader).edge_types(&["Bus"]).shortest_path(nodes[0].id(), nodes[1].id()).is_none());
+
+        // Edges of other types, or at nodes the routes did not reach, are no
+        // phantoms
+        let mut writer = graph.start_transaction();
+        graph.add_edge(&mut writer, &nodes[3], &nodes[1], "Rail".to_string()).unwrap();
+        graph.add_edge(&mut writer, &nodes[1], &nodes[0], "Ferry".to_string()).unwrap();
+        graph.commit_transaction(&writer).unwrap();
+        assert!(graph.find_phantom(&reader).is_none());
+
+        // An undirected route scans the edges into its nodes as well
+        let mut writer = graph.start_transaction();
+        graph.add_edge(&mut writer, &nodes[2], &nodes[0], "Bus".to_string()).unwrap();
+        graph.commit_transaction(&writer).unwrap();
+        let aborted = graph.commit_transaction(&reader).unwrap_err();
+        assert_eq!(aborted.conflict().and_then(|conflict| conflict.with), Some(writer.txid));
+    }
+}
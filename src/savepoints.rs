@@ -0,0 +1,245 @@
+//! # Savepoints
+//!
+//! A savepoint marks a position in a transaction's undo log. Rolling back to
+//! it undoes, newest first, every node, edge, label, property and record write
+//! made since, while the transaction itself stays active and keeps its locks.
+//!
+//! ```
+//! use graph_mvcc::Graph;
+//!
+//! let mut graph = Graph::new();
+//! let mut tx = graph.start_transaction();
+//! let kept = graph.add_node(&mut tx);
+//!
+//! tx.savepoint("import");
+//! let dropped = graph.add_node(&mut tx);
+//! graph.rollback_to(&mut tx, "import").unwrap();
+//!
+//! assert!(graph.node_is_visible(&tx, &kept));
+//! assert!(!graph.node_is_visible(&tx, &dropped));
+//! graph.commit_transaction(&tx).unwrap();
+//! ```
+
+use crate::{Conflict, ElementId, Graph, Node, PropertyValue, TransactionId, TxError, TxResult, UndoAction, Version, MVCC};
+
+impl TransactionId {
+    /// Marks the current position of the undo log under `name`. A savepoint
+    /// with a name already in use hides the older one until it is released.
+    pub fn savepoint(&mut self, name: &str) {
+        self.savepoints.push((name.to_string(), self.rollback_actions.len()));
+    }
+
+    /// Forgets the savepoint `name` and every savepoint set after it. The
+    /// writes made since are kept.
+    pub fn release(&mut self, name: &str) -> TxResult<()> {
+        let index = self.savepoint_index(name)?;
+        self.savepoints.truncate(index);
+        Ok(())
+    }
+
+    fn savepoint_index(&self, name: &str) -> TxResult<usize> {
+        self.savepoints
+            .iter()
+            .rposition(|(savepoint, _)| savepoint == name)
+            .ok_or_else(|| TxError::SavepointNotFound(name.to_string()))
+    }
+}
+
+impl Graph {
+    /// Undoes every write `t` made since the savepoint `name`. The savepoint
+    /// itself is kept, so it can be rolled back to again; savepoints set after
+    /// it are forgotten. Fails with `TxError::Abort` once `t` is no longer
+    /// active.
+    pub fn rollback_to(&mut self, t: &mut TransactionId, name: &str) -> TxResult<()> {
+        if !self.conductor.active().contains(&t.txid) {
+            return Err(TxError::Abort(Conflict::new(t.txid)));
+        }
+        let index = t.savepoint_index(name)?;
+        let position = t.savepoints[index].1;
+        t.savepoints.truncate(index + 1);
+
+        while t.rollback_actions.len() > position {
+            if let Some(action) = t.rollback_actions.pop() {
+                self.undo(t.txid, &action);
+            }
+        }
+        Ok(())
+    }
+
+    /// Reverts a single write of `txid`. Writes are undone newest first, so
+    /// the latest version `txid` created or expired is the one to revert.
+    pub(crate) fn undo(&mut self, txid: u64, action: &UndoAction) {
+        match action {
+            UndoAction::CreateNode(node) => {
+                if self.nodes.get(node).is_some_and(|version| version.creation_id == txid) {
+                    self.nodes.remove(node);
+                    self.adjacencylist.remove(node);
+                }
+            }
+            UndoAction::ExpireNode(node) => {
+                if let Some(version) = self.nodes.get_mut(node) {
+                    if version.expiration_id == txid {
+                        version.expiration_id = 0;
+                    }
+                }
+            }
+            UndoAction::CreateEdge(id) => {
+                if self.edges.get(id).is_some_and(|(_, version)| version.creation_id == txid) {
+                    if let Some((edge, _)) = self.edges.remove(id) {
+                        // Only the endpoints list the edge
+                        for endpoint in [edge.source(), edge.target()] {
+                            if let Some(list) = self.adjacencylist.get_mut(&Node { id: endpoint.clone() }) {
+                                list.retain(|(_, listed)| listed.id() != id);
+                            }
+                        }
+                    }
+                }
+            }
+            UndoAction::ExpireEdge(id) => {
+                if let Some((_, version)) = self.edges.get_mut(id) {
+                    if version.expiration_id == txid {
+                        version.expiration_id = 0;
+                    }
+                }
+            }
+            UndoAction::AddLabel(node, label) => {
+                if let Some(versions) = self.labels.get_mut(node) {
+                    if let Some(i) = versions.iter().rposition(|(l, version)| l == label && version.creation_id == txid) {
+                        versions.remove(i);
+                    }
+                }
+            }
+            UndoAction::ExpireLabel(node, label) => {
+                if let Some(versions) = self.labels.get_mut(node) {
+                    if let Some((_, version)) = versions.iter_mut().rev().find(|(l, version)| l == label && version.expiration_id == txid) {
+                        version.expiration_id = 0;
+                    }
+                }
+            }
+            UndoAction::SetProperty(element, key) => {
+                if let Some(versions) = self.property_versions(element, key) {
+                    if let Some(i) = versions.iter().rposition(|(_, version)| version.creation_id == txid) {
+                        versions.remove(i);
+                    }
+                }
+            }
+            UndoAction::ExpireProperty(element, key) => {
+                if let Some(versions) = self.property_versions(element, key) {
+                    if let Some((_, version)) = versions.iter_mut().rev().find(|(_, version)| version.expiration_id == txid) {
+                        version.expiration_id = 0;
+                    }
+                }
+            }
+            UndoAction::PutRecord(key) => {
+                if let Some(versions) = self.store.get_mut(key) {
+                    if let Some(i) = versions.iter().rposition(|record| record.version.creation_id == txid) {
+                        versions.remove(i);
+                    }
+                    if versions.is_empty() {
+                        self.store.remove(key);
+                    }
+                }
+            }
+            UndoAction::RemoveRecord(key) => {
+                if let Some(versions) = self.store.get_mut(key) {
+                    if let Some(record) = versions.iter_mut().rev().find(|record| record.version.expiration_id == txid) {
+                        record.version.expiration_id = 0;
+                    }
+                }
+            }
+            UndoAction::CreateRecord(handle) => {
+                self.records.remove(*handle);
+            }
+            UndoAction::ExpireRecord(handle) => {
+                if let Some(record) = self.records.get_mut(*handle) {
+                    if record.get(&MVCC::TransactionExpirationId) == Some(&txid) {
+                        record.insert(MVCC::TransactionExpirationId, 0);
+                    }
+                }
+            }
+        }
+    }
+
+    fn property_versions(&mut self, element: &ElementId, key: &str) -> Option<&mut Vec<(PropertyValue, Version)>> {
+        self.properties.get_mut(element)?.get_mut(key)
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Unit Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_rollback_to_keeps_earlier_writes() {
+        let mut graph = Graph::new();
+        let mut tx = graph.start_transaction();
+        let a = graph.add_node(&mut tx);
+        let b = graph.add_node(&mut tx);
+        let name = ElementId::Node(a.id().clone());
+        graph.set_property(&mut tx, name.clone(), "name".to_string(), "a".into()).unwrap();
+
+        tx.savepoint("edges");
+        graph.add_edge(&mut tx, &a, &b, "red".to_string()).unwrap();
+        graph.set_property(&mut tx, name.clone(), "name".to_string(), "renamed".into()).unwrap();
+        graph.add_label(&mut tx, &a, "Station".to_string()).unwrap();
+        graph.remove_node(&mut tx, &b).unwrap();
+        graph.rollback_to(&mut tx, "edges").unwrap();
+
+        assert!(graph.node_is_visible(&tx, &b));
+        assert!(graph.visible_edges(&tx, &a).is_empty());
+        assert!(graph.get_labels(&tx, &a).is_empty());
+        assert_eq!(graph.get_property(&tx, &name, "name"), Some("a".into()));
+
+        // The transaction is still alive and the edge type is free again.
+        graph.add_edge(&mut tx, &a, &b, "red".to_string()).unwrap();
+        graph.commit_transaction(&tx).unwrap();
+        let reader = graph.start_transaction();
+        assert_eq!(graph.visible_nodes(&reader).len(), 2);
+        assert_eq!(graph.visible_edges(&reader, &a).len(), 1);
+    }
+
+    #[test]
+    fn test_nested_savepoints_and_release() {
+        let mut graph = Graph::new();
+        let mut tx = graph.start_transaction();
+        tx.savepoint("outer");
+        let a = graph.add_node(&mut tx);
+        tx.savepoint("inner");
+        let b = graph.add_node(&mut tx);
+
+        graph.rollback_to(&mut tx, "inner").unwrap();
+        assert!(graph.node_is_visible(&tx, &a));
+        assert!(!graph.node_is_visible(&tx, &b));
+
+        tx.release("inner").unwrap();
+        assert_eq!(graph.rollback_to(&mut tx, "inner"), Err(TxError::SavepointNotFound("inner".to_string())));
+        graph.rollback_to(&mut tx, "outer").unwrap();
+        assert!(graph.visible_nodes(&tx).is_empty());
+        // Rolling back keeps the savepoint itself.
+        graph.rollback_to(&mut tx, "outer").unwrap();
+    }
+
+    #[test]
+    fn test_rollback_to_restores_records() {
+        let mut graph = Graph::new();
+        let mut tx = graph.start_transaction();
+        let mut record = BTreeMap::new();
+        record.insert(MVCC::ElementId, 1);
+        let handle = graph.add_record(&mut tx, &mut record);
+
+        tx.savepoint("delete");
+        graph.delete_record(&mut tx, 1).unwrap();
+        let mut other = BTreeMap::new();
+        other.insert(MVCC::ElementId, 2);
+        graph.add_record(&mut tx, &mut other);
+        graph.rollback_to(&mut tx, "delete").unwrap();
+
+        assert_eq!(graph.records.len(), 1);
+        assert_eq!(graph.record(handle), Some(&record));
+        graph.commit_transaction(&tx).unwrap();
+        assert_eq!(graph.rollback_to(&mut tx, "delete"), Err(TxError::Abort(Conflict::new(tx.txid))));
+    }
+}
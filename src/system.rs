@@ -0,0 +1,265 @@
+//! # System operations
+//!
+//! The txids 0, 1 and 2 are never handed to transactions. They identify the
+//! administrative operations that act on the graph as a whole:
+//!
+//! - `reset` (txid 0) empties the graph.
+//! - `reboot` (txid 1) reloads the graph from its durable state. The graph
+//!   lives in memory, so its durable state is the set of committed versions:
+//!   a reboot rebuilds every index from them and drops expired versions.
+//! - `revert` (txid 2) restores the graph to the last `checkpoint`.
+//!
+//! None of them runs while a transaction, read-only ones included, is
+//! active, and each is recorded in the system log.
+//!
+//! ```
+//! use graph_mvcc::{Graph, SystemAction};
+//!
+//! let mut graph = Graph::new();
+//! graph.checkpoint().unwrap();
+//!
+//! let mut tx = graph.start_transaction();
+//! graph.add_node(&mut tx);
+//! graph.commit_transaction(&tx).unwrap();
+//!
+//! graph.revert().unwrap();
+//! let reader = graph.start_transaction();
+//! assert!(graph.visible_nodes(&reader).is_empty());
+//! graph.commit_transaction(&reader).unwrap();
+//! assert_eq!(graph.system_log()[0].action, SystemAction::Revert);
+//! ```
+
+use std::collections::{BTreeMap, HashMap};
+use std::time::SystemTime;
+
+use crate::records::RecordTable;
+use crate::{Edge, EdgeId, ElementId, Graph, Node, NodeId, PropertyValue, RecordVersion, TxError, TxResult, Version, MVCC};
+
+/// Reserved txid of `Graph::reset`.
+pub const RESET_TXID: u64 = 0;
+/// Reserved txid of `Graph::reboot`.
+pub const REBOOT_TXID: u64 = 1;
+/// Reserved txid of `Graph::revert`.
+pub const REVERT_TXID: u64 = 2;
+/// The first txid a transaction can be given.
+pub const FIRST_TXID: u64 = 3;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum SystemAction {
+    Reset,
+    Reboot,
+    Revert,
+}
+
+impl SystemAction {
+    /// The reserved txid that signals this action.
+    pub fn txid(&self) -> u64 {
+        match self {
+            SystemAction::Reset => RESET_TXID,
+            SystemAction::Reboot => REBOOT_TXID,
+            SystemAction::Revert => REVERT_TXID,
+        }
+    }
+}
+
+/// An entry of the system log.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SystemEvent {
+    pub action: SystemAction,
+    pub at: SystemTime,
+    /// The last txid issued when the action ran.
+    pub last_txid: u64,
+}
+
+/// The committed contents of the graph at the time of a `checkpoint`.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Checkpoint {
+    nodes: HashMap<Node, Version>,
+    edges: HashMap<EdgeId, (Edge, Version)>,
+    labels: HashMap<NodeId, Vec<(String, Version)>>,
+    properties: HashMap<ElementId, BTreeMap<String, Vec<(PropertyValue, Version)>>>,
+    records: RecordTable,
+    store: BTreeMap<String, Vec<RecordVersion>>,
+}
+
+impl Graph {
+    /// Remembers the committed contents of the graph for a later `revert`.
+    pub fn checkpoint(&mut self) -> TxResult<()> {
+        self.ensure_quiescent()?;
+        self.checkpoint = Some(Checkpoint {
+            nodes: self.nodes.clone(),
+            edges: self.edges.clone(),
+            labels: self.labels.clone(),
+            properties: self.properties.clone(),
+            records: self.records.clone(),
+            store: self.store.clone(),
+        });
+        Ok(())
+    }
+
+    /// Removes every node, edge, label, property and record. Txids keep
+    /// counting up and the last checkpoint is kept, so a reset can be reverted.
+    pub fn reset(&mut self) -> TxResult<()> {
+        self.ensure_quiescent()?;
+        self.load(Checkpoint::default());
+        self.log_system_action(SystemAction::Reset);
+        Ok(())
+    }
+
+    /// Reloads the graph from its committed versions, dropping the ones that
+    /// have been expired and rebuilding the adjacency lists.
+    pub fn reboot(&mut self) -> TxResult<()> {
+        self.ensure_quiescent()?;
+        let live = |version: &Version| version.expiration_id == 0;
+        let mut durable = Checkpoint {
+            nodes: self.nodes.drain().filter(|(_, version)| live(version)).collect(),
+            edges: self.edges.drain().filter(|(_, (_, version))| live(version)).collect(),
+            labels: HashMap::new(),
+            properties: HashMap::new(),
+            records: std::mem::take(&mut self.records),
+            store: BTreeMap::new(),
+        };
+        for (node, mut versions) in self.labels.drain() {
+            versions.retain(|(_, version)| live(version));
+            if !versions.is_empty() {
+                durable.labels.insert(node, versions);
+            }
+        }
+        for (element, keys) in self.properties.drain() {
+            let keys: BTreeMap<_, _> = keys
+                .into_iter()
+                .map(|(key, mut versions)| {
+                    versions.retain(|(_, version)| live(version));
+                    (key, versions)
+                })
+                .filter(|(_, versions)| !versions.is_empty())
+                .collect();
+            if !keys.is_empty() {
+                durable.properties.insert(element, keys);
+            }
+        }
+        for (key, mut versions) in std::mem::take(&mut self.store) {
+            versions.retain(|record| live(&record.version));
+            if !versions.is_empty() {
+                durable.store.insert(key, versions);
+            }
+        }
+        durable.records.retain(|record| record.get(&MVCC::TransactionExpirationId).is_none_or(|txid| *txid == 0));
+        self.load(durable);
+        self.log_system_action(SystemAction::Reboot);
+        Ok(())
+    }
+
+    /// Restores the contents of the last checkpoint.
+    pub fn revert(&mut self) -> TxResult<()> {
+        self.ensure_quiescent()?;
+        let checkpoint = self.checkpoint.clone().ok_or(TxError::NoCheckpoint)?;
+        self.load(checkpoint);
+        self.log_system_action(SystemAction::Revert);
+        Ok(())
+    }
+
+    /// Every system operation run on this graph, oldest first.
+    pub fn system_log(&self) -> &[SystemEvent] {
+        &self.system_log
+    }
+
+    fn ensure_quiescent(&mut self) -> TxResult<()> {
+        self.reap_expired_transactions();
+        let active = self.conductor.active().len() + self.readers.alive();
+        if active == 0 {
+            Ok(())
+        } else {
+            Err(TxError::TransactionsActive(active))
+        }
+    }
+
+    /// Replaces the contents of the graph and rebuilds the adjacency lists.
+    fn load(&mut self, contents: Checkpoint) {
+        self.nodes = contents.nodes;
+        self.edges = contents.edges;
+        self.labels = contents.labels;
+        self.properties = contents.properties;
+        self.records = contents.records;
+        self.store = contents.store;
+
+        self.adjacencylist = HashMap::new();
+        let mut edges: Vec<Edge> = self.edges.values().map(|(edge, _)| edge.clone()).collect();
+        edges.sort_by(|a, b| {
+            let (EdgeId::String(a), EdgeId::String(b)) = (a.id(), b.id());
+            a.cmp(b)
+        });
+        for edge in edges {
+            let source = Node { id: edge.source().clone() };
+            let target = Node { id: edge.target().clone() };
+            self.list_edge(&source, &target, edge);
+        }
+    }
+
+    fn log_system_action(&mut self, action: SystemAction) {
+        self.system_log.push(SystemEvent {
+            action,
+            at: SystemTime::now(),
+            last_txid: self.conductor.last_issued(),
+        });
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Unit Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reserved_txids_are_skipped() {
+        let mut graph = Graph::new();
+        let tx = graph.start_transaction();
+        assert_eq!(tx.txid, FIRST_TXID);
+        assert_eq!(SystemAction::Revert.txid(), REVERT_TXID);
+    }
+
+    #[test]
+    fn test_system_actions_require_quiescence() {
+        let mut graph = Graph::new();
+        let tx = graph.start_transaction();
+        assert_eq!(graph.reset(), Err(TxError::TransactionsActive(1)));
+        assert_eq!(graph.reboot(), Err(TxError::TransactionsActive(1)));
+        assert_eq!(graph.checkpoint(), Err(TxError::TransactionsActive(1)));
+        graph.commit_transaction(&tx).unwrap();
+        assert_eq!(graph.revert(), Err(TxError::NoCheckpoint));
+        assert!(graph.system_log().is_empty());
+    }
+
+    #[test]
+    fn test_reset_reboot_and_revert() {
+        let mut graph = Graph::new();
+        let mut tx = graph.start_transaction();
+        let a = graph.add_node(&mut tx);
+        let b = graph.add_node(&mut tx);
+        graph.add_edge(&mut tx, &a, &b, "red".to_string()).unwrap();
+        graph.commit_transaction(&tx).unwrap();
+        graph.checkpoint().unwrap();
+
+        let mut tx = graph.start_transaction();
+        graph.remove_node(&mut tx, &b).unwrap();
+        graph.commit_transaction(&tx).unwrap();
+
+        graph.reboot().unwrap();
+        assert_eq!(graph.nodes.len(), 1);
+        assert!(graph.edges.is_empty());
+        assert_eq!(graph.adjacencylist.len(), 0);
+
+        graph.reset().unwrap();
+        assert!(graph.nodes.is_empty());
+
+        graph.revert().unwrap();
+        let reader = graph.start_transaction();
+        assert_eq!(graph.visible_edges(&reader, &a).len(), 1);
+        assert!(reader.txid > tx.txid);
+        graph.commit_transaction(&reader).unwrap();
+
+        let actions: Vec<SystemAction> = graph.system_log().iter().map(|event| event.action).collect();
+        assert_eq!(actions, vec![SystemAction::Reboot, SystemAction::Reset, SystemAction::Revert]);
+    }
+}
@@ -0,0 +1,40 @@
+//! Graphs the unit tests start from.
+
+use crate::{ElementId, Graph, Node};
+
+/// `count` nodes joined by `edges`, given as `(from, to, type)` indices into
+/// the returned nodes, committed in one setup transaction.
+pub(crate) fn graph(count: usize, edges: &[(usize, usize, &str)]) -> (Graph, Vec<Node>) {
+    let mut graph = Graph::new();
+    let mut setup = graph.start_transaction();
+    let nodes: Vec<Node> = (0..count).map(|_| graph.add_node(&mut setup)).collect();
+    for (from, to, edge_type) in edges {
+        graph.add_edge(&mut setup, &nodes[*from], &nodes[*to], edge_type.to_string()).unwrap();
+    }
+    graph.commit_transaction(&setup).unwrap();
+    (graph, nodes)
+}
+
+/// Sets the `name` property of each node, in one committed transaction.
+pub(crate) fn name(graph: &mut Graph, nodes: &[Node], names: &[&str]) {
+    let mut tx = graph.start_transaction();
+    for (node, name) in nodes.iter().zip(names) {
+        graph.set_property(&mut tx, ElementId::Node(node.id().clone()), "name".to_string(), (*name).into()).unwrap();
+    }
+    graph.commit_transaction(&tx).unwrap();
+}
+
+/// Sets `key` on the edge of each type in `values`, in one committed
+/// transaction.
+pub(crate) fn set_edge_property(graph: &mut Graph, key: &str, values: &[(&str, i64)]) {
+    let mut tx = graph.start_transaction();
+    for node in graph.visible_nodes(&tx) {
+        for (_, edge) in graph.visible_edges(&tx, &node) {
+            let value = values.iter().find(|(edge_type, _)| *edge_type == edge.edgetype());
+            if let (true, Some((_, value))) = (edge.source() == node.id(), value) {
+                graph.set_property(&mut tx, ElementId::Edge(edge.id().clone()), key.to_string(), (*value).into()).unwrap();
+            }
+        }
+    }
+    graph.commit_transaction(&tx).unwrap();
+}
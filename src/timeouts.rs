@@ -0,0 +1,239 @@
+//! # Transaction timeouts
+//!
+//! Every transaction gets a deadline when it starts, `TRANSACTION_EXPIRATION_IN_SECONDS`
+//! from now unless the graph's default or the transaction's own timeout says
+//! otherwise. Once the deadline has passed the reaper rolls the transaction
+//! back, so its writes stop taking part in visibility and locking. Every
+//! write and the commit check that their transaction is still active, reaping
+//! it on the spot if its deadline has passed. Those of a reaped transaction
+//! fail with `TxError::TransactionExpired`, those of one that has committed
+//! or rolled back with `TxError::Abort`.
+//!
+//! ```
+//! use std::time::Duration;
+//! use graph_mvcc::{Graph, TxError};
+//!
+//! let mut graph = Graph::new();
+//! let mut tx = graph.start_transaction();
+//! graph.add_node(&mut tx);
+//! graph.set_transaction_timeout(&tx, Some(Duration::ZERO));
+//!
+//! assert_eq!(graph.reap_expired_transactions(), vec![tx.txid]);
+//! assert_eq!(graph.commit_transaction(&tx), Err(TxError::TransactionExpired(tx.txid)));
+//! ```
+
+use std::collections::{BTreeSet, HashMap};
+use std::time::{Duration, Instant};
+
+use crate::{Conflict, Graph, TransactionId, TxError, TxResult, MVCC};
+
+/// The hard time cap on a transaction when no other timeout is configured.
+pub const TRANSACTION_EXPIRATION_IN_SECONDS: u64 = 900;
+
+/// Deadlines of the active transactions and the txids the reaper aborted.
+#[derive(Debug, Clone)]
+pub(crate) struct Timeouts {
+    default: Option<Duration>,
+    deadlines: HashMap<u64, Instant>,
+    expired: BTreeSet<u64>,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Timeouts {
+            default: Some(Duration::from_secs(TRANSACTION_EXPIRATION_IN_SECONDS)),
+            deadlines: HashMap::new(),
+            expired: BTreeSet::new(),
+        }
+    }
+}
+
+impl Timeouts {
+    fn set(&mut self, txid: u64, timeout: Option<Duration>) {
+        match timeout {
+            Some(timeout) => self.deadlines.insert(txid, Instant::now() + timeout),
+            None => self.deadlines.remove(&txid),
+        };
+    }
+
+    /// Starts the clock for a new transaction.
+    pub(crate) fn start(&mut self, txid: u64) {
+        self.set(txid, self.default);
+    }
+
+    /// Forgets the deadline of a transaction that committed or rolled back.
+    pub(crate) fn finish(&mut self, txid: u64) {
+        self.deadlines.remove(&txid);
+    }
+}
+
+impl Graph {
+    /// The timeout given to transactions started from now on. `None` lets
+    /// them run without a deadline.
+    pub fn set_default_transaction_timeout(&mut self, timeout: Option<Duration>) {
+        self.timeouts.default = timeout;
+    }
+
+    /// Replaces the deadline of `t` with one `timeout` from now, or removes it.
+    pub fn set_transaction_timeout(&mut self, t: &TransactionId, timeout: Option<Duration>) {
+        if self.conductor.active().contains(&t.txid) {
+            self.timeouts.set(t.txid, timeout);
+        }
+    }
+
+    /// The point in time after which `t` is reaped, if it has a deadline.
+    pub fn transaction_deadline(&self, t: &TransactionId) -> Option<Instant> {
+        self.timeouts.deadlines.get(&t.txid).copied()
+    }
+
+    /// Rolls back every active transaction whose deadline has passed and
+    /// returns their txids. Runs on every `start_transaction` and commit as
+    /// well, so it only needs calling to free resources of idle graphs.
+    pub fn reap_expired_transactions(&mut self) -> Vec<u64> {
+        let now = Instant::now();
+        let mut expired: Vec<u64> = self
+            .timeouts
+            .deadlines
+            .iter()
+            .filter(|(_, deadline)| **deadline <= now)
+            .map(|(txid, _)| *txid)
+            .collect();
+        expired.sort_unstable();
+
+        for &txid in &expired {
+            // The undo log lives with the caller's `TransactionId`, so sweep
+            // for the txid's writes instead.
+            self.rollback_graph_elements(txid);
+            self.rollback_records(txid);
+            self.conductor.abort(txid);
+            self.writes.finish(txid);
+            self.timeouts.finish(txid);
+            self.locks.manager.release_all(txid);
+            self.timeouts.expired.insert(txid);
+        }
+        expired
+    }
+
+    /// Fails unless `t` is still active: with `TxError::TransactionExpired`
+    /// if the reaper aborted it, on the spot if it is past its deadline, and
+    /// with `TxError::Abort` if it has committed or rolled back. Only the
+    /// deadline of `t` is looked at, so every write can afford the check.
+    pub(crate) fn check_active(&mut self, t: &TransactionId) -> TxResult<()> {
+        if self.timeouts.deadlines.get(&t.txid).is_some_and(|deadline| *deadline <= Instant::now()) {
+            self.reap_expired_transactions();
+        }
+        if self.timeouts.expired.contains(&t.txid) {
+            return Err(TxError::TransactionExpired(t.txid));
+        }
+        if !self.conductor.active().contains(&t.txid) {
+            return Err(TxError::Abort(Conflict::new(t.txid)));
+        }
+        Ok(())
+    }
+
+    fn rollback_records(&mut self, txid: u64) {
+        self.records.retain(|record| record.get(&MVCC::TransactionCreationId) != Some(&txid));
+        let expired: Vec<_> = self
+            .records
+            .iter()
+            .filter(|(_, record)| record.get(&MVCC::TransactionExpirationId) == Some(&txid))
+            .map(|(handle, _)| handle)
+            .collect();
+        for handle in expired {
+            if let Some(record) = self.records.get_mut(handle) {
+                record.insert(MVCC::TransactionExpirationId, 0);
+            }
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Unit Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_expired_transaction_is_reaped_and_rejected() {
+        let mut graph = Graph::new();
+        let mut writer = graph.start_transaction();
+        let node = graph.add_node(&mut writer);
+        let mut record = BTreeMap::new();
+        record.insert(MVCC::ElementId, 7);
+        graph.add_record(&mut writer, &mut record);
+        graph.set_transaction_timeout(&writer, Some(Duration::ZERO));
+
+        // Starting another transaction runs the reaper.
+        let reader = graph.start_transaction();
+        assert!(!graph.conductor.active().contains(&writer.txid));
+        assert!(!graph.nodes.contains_key(&node));
+        assert_eq!(graph.records.len(), 0);
+        assert_eq!(graph.commit_transaction(&writer), Err(TxError::TransactionExpired(writer.txid)));
+        assert!(graph.commit_transaction(&reader).is_ok());
+    }
+
+    #[test]
+    fn test_writes_check_the_deadline() {
+        let mut graph = Graph::new();
+        let mut writer = graph.start_transaction();
+        let node = graph.add_node(&mut writer);
+        graph.set_transaction_timeout(&writer, Some(Duration::ZERO));
+
+        // The write itself reaps the transaction, nothing else has to run.
+        let expired = Err(TxError::TransactionExpired(writer.txid));
+        assert_eq!(graph.add_label(&mut writer, &node, "late".to_string()), expired);
+        assert!(graph.nodes.is_empty());
+        let late = graph.add_node(&mut writer);
+        assert!(!graph.nodes.contains_key(&late));
+        assert_eq!(graph.record_store(&mut writer).put("key", &1), expired.clone().map(|_| 0));
+        assert_eq!(graph.commit_transaction(&writer), expired);
+        assert!(graph.timeouts.deadlines.is_empty());
+    }
+
+    #[test]
+    fn test_ended_transactions_are_not_expired() {
+        let mut graph = Graph::new();
+        let mut committed = graph.start_transaction();
+        graph.add_node(&mut committed);
+        graph.commit_transaction(&committed).unwrap();
+        let ended = Err(TxError::Abort(Conflict::new(committed.txid)));
+        assert_eq!(graph.commit_transaction(&committed), ended);
+
+        let aborted = graph.start_transaction();
+        graph.abort_transaction(&aborted).unwrap();
+        assert_eq!(graph.commit_transaction(&aborted), Err(TxError::Abort(Conflict::new(aborted.txid))));
+    }
+
+    #[test]
+    fn test_reaping_releases_locks() {
+        let mut graph = Graph::new();
+        let mut setup = graph.start_transaction();
+        let node = graph.add_node(&mut setup);
+        graph.commit_transaction(&setup).unwrap();
+
+        let mut slow = graph.start_transaction();
+        graph.remove_node(&mut slow, &node).unwrap();
+        graph.set_transaction_timeout(&slow, Some(Duration::ZERO));
+
+        let mut fast = graph.start_transaction();
+        assert!(graph.node_is_visible(&fast, &node));
+        graph.remove_node(&mut fast, &node).unwrap();
+        graph.commit_transaction(&fast).unwrap();
+    }
+
+    #[test]
+    fn test_default_timeout() {
+        let mut graph = Graph::new();
+        let tx = graph.start_transaction();
+        let deadline = graph.transaction_deadline(&tx).unwrap();
+        assert!(deadline > Instant::now() + Duration::from_secs(TRANSACTION_EXPIRATION_IN_SECONDS - 60));
+
+        graph.set_default_transaction_timeout(None);
+        let unbounded = graph.start_transaction();
+        assert_eq!(graph.transaction_deadline(&unbounded), None);
+        graph.commit_transaction(&tx).unwrap();
+        assert_eq!(graph.transaction_deadline(&tx), None);
+        assert!(graph.reap_expired_transactions().is_empty());
+    }
+}
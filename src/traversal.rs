@@ -0,0 +1,598 @@
+//! # Traversals
+//!
+//! A fluent, Gremlin-style traversal builder. A traversal is a list of steps
+//! that is only evaluated when it is iterated or when a terminal step such as
+//! `to_list`, `paths` or `count` is called, and it always reads the graph as
+//! the given transaction sees it.
+//!
+//! ```
+//! use graph_mvcc::Graph;
+//!
+//! let mut graph = Graph::new();
+//! let mut tx = graph.start_transaction();
+//! let a = graph.add_node(&mut tx);
+//! let b = graph.add_node(&mut tx);
+//! let c = graph.add_node(&mut tx);
+//! graph.add_edge(&mut tx, &a, &b, "red".to_string()).unwrap();
+//! graph.add_edge(&mut tx, &b, &c, "blue".to_string()).unwrap();
+//!
+//! let g = graph.traversal(&tx);
+//! let reached = g.v(a.id()).out("red").both("blue").dedup().limit(10).to_list();
+//! assert_eq!(reached, vec![c.id().clone()]);
+//! ```
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::iter;
+
+use crate::{ElementId, Graph, Node, NodeId, PredicateLock, PropertyValue, TransactionId};
+
+/// Entry point for traversals over the view of transaction `t`, the
+/// equivalent of Gremlin's `g`.
+#[derive(Debug, Clone, Copy)]
+pub struct TraversalSource<'graph> {
+    graph: &'graph Graph,
+    t: &'graph TransactionId,
+}
+
+impl Graph {
+    pub fn traversal<'graph>(&'graph self, t: &'graph TransactionId) -> TraversalSource<'graph> {
+        TraversalSource { graph: self, t }
+    }
+}
+
+impl<'graph> TraversalSource<'graph> {
+    /// Starts a traversal at a single node.
+    pub fn v(&self, id: &NodeId) -> TypePath<'graph> {
+        self.start(vec![id.clone()])
+    }
+
+    /// Starts a traversal at several nodes.
+    pub fn vs(&self, ids: &[NodeId]) -> TypePath<'graph> {
+        self.start(ids.to_vec())
+    }
+
+    /// Starts a traversal at every node visible to the transaction.
+    pub fn v_all(&self) -> TypePath<'graph> {
+        let ids = self.graph.visible_nodes(self.t).into_iter().map(|node| node.id().clone()).collect();
+        self.start(ids)
+    }
+
+    fn start(&self, start: Vec<NodeId>) -> TypePath<'graph> {
+        TypePath {
+            graph: self.graph,
+            t: self.t,
+            start,
+            steps: Vec::new(),
+            pending: None,
+            running: None,
+        }
+    }
+}
+
+/// Which way an edge is followed from the node it is attached to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Direction {
+    Out,
+    In,
+    Both,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Step {
+    Walk(Direction, Option<String>),
+    Has(String, PropertyValue),
+    HasNot(String),
+    HasLabel(String),
+    Dedup,
+    SimplePath,
+    Skip(usize),
+    Limit(usize),
+    Repeat(Repeat),
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+struct Repeat {
+    body: Vec<Step>,
+    until: Option<Vec<Step>>,
+    // `until` given before `repeat` is checked before every iteration
+    // (while-do), after it only once the body has run (do-while).
+    check_first: bool,
+    times: Option<usize>,
+    emit: bool,
+}
+
+/// One traverser: the node it stands on and every node it passed through.
+#[derive(Debug, Clone)]
+struct Traverser {
+    path: Vec<NodeId>,
+}
+
+impl Traverser {
+    fn node(&self) -> &NodeId {
+        self.path.last().expect("a traverser always has a position")
+    }
+}
+
+type Traversers<'graph> = Box<dyn Iterator<Item = Traverser> + 'graph>;
+
+/// TypePath represents a traversal of the graph based on a sequence of steps
+/// leading from one or more starting nodes, through adjacent nodes connected
+/// via edges matching the requested types.
+///
+/// Steps are only recorded by the builder methods; the graph is read when the
+/// traversal is iterated or a terminal step is called. A clone starts over
+/// from the starting nodes.
+pub struct TypePath<'graph> {
+    graph: &'graph Graph,
+    t: &'graph TransactionId,
+    start: Vec<NodeId>,
+    steps: Vec<Step>,
+    // Loop modifiers given before the `repeat` they belong to.
+    pending: Option<Repeat>,
+    // The traversers left, once iteration has begun.
+    running: Option<Traversers<'graph>>,
+}
+
+impl Clone for TypePath<'_> {
+    fn clone(&self) -> Self {
+        TypePath {
+            graph: self.graph,
+            t: self.t,
+            start: self.start.clone(),
+            steps: self.steps.clone(),
+            pending: self.pending.clone(),
+            running: None,
+        }
+    }
+}
+
+impl<'graph> TypePath<'graph> {
+    fn push(mut self, step: Step) -> Self {
+        self.steps.push(step);
+        self.running = None;
+        self
+    }
+
+    /// An empty traversal sharing this one's graph and transaction, used to
+    /// build the bodies of `repeat` and `until`.
+    fn anonymous(&self) -> Self {
+        TypePath {
+            graph: self.graph,
+            t: self.t,
+            start: Vec::new(),
+            steps: Vec::new(),
+            pending: None,
+            running: None,
+        }
+    }
+
+    /// Follows outgoing edges of type `edge_type`.
+    pub fn out(self, edge_type: &str) -> Self {
+        self.push(Step::Walk(Direction::Out, Some(edge_type.to_string())))
+    }
+
+    /// Follows incoming edges of type `edge_type`.
+    pub fn in_(self, edge_type: &str) -> Self {
+        self.push(Step::Walk(Direction::In, Some(edge_type.to_string())))
+    }
+
+    /// Follows edges of type `edge_type` in either direction.
+    pub fn both(self, edge_type: &str) -> Self {
+        self.push(Step::Walk(Direction::Both, Some(edge_type.to_string())))
+    }
+
+    pub fn out_any(self) -> Self {
+        self.push(Step::Walk(Direction::Out, None))
+    }
+
+    pub fn in_any(self) -> Self {
+        self.push(Step::Walk(Direction::In, None))
+    }
+
+    pub fn both_any(self) -> Self {
+        self.push(Step::Walk(Direction::Both, None))
+    }
+
+    /// Keeps nodes whose property `key` equals `value`.
+    pub fn has(self, key: &str, value: impl Into<PropertyValue>) -> Self {
+        self.push(Step::Has(key.to_string(), value.into()))
+    }
+
+    /// Keeps nodes that have no property `key`.
+    pub fn has_not(self, key: &str) -> Self {
+        self.push(Step::HasNot(key.to_string()))
+    }
+
+    pub fn has_label(self, label: &str) -> Self {
+        self.push(Step::HasLabel(label.to_string()))
+    }
+
+    /// Drops traversers standing on a node that was already emitted.
+    pub fn dedup(self) -> Self {
+        self.push(Step::Dedup)
+    }
+
+    /// Drops traversers whose path visits a node twice.
+    pub fn simple_path(self) -> Self {
+        self.push(Step::SimplePath)
+    }
+
+    pub fn skip(self, n: usize) -> Self {
+        self.push(Step::Skip(n))
+    }
+
+    pub fn limit(self, n: usize) -> Self {
+        self.push(Step::Limit(n))
+    }
+
+    /// Applies the steps built by `body` over and over. Combine with `until`,
+    /// `times` and `emit` to decide which traversers leave the loop. Without
+    /// `times` the loop enters every node at most once, so it also ends on a
+    /// graph with cycles.
+    pub fn repeat(mut self, body: impl FnOnce(TypePath<'graph>) -> TypePath<'graph>) -> Self {
+        let mut repeat = self.pending.take().unwrap_or_default();
+        repeat.body = body(self.anonymous()).steps;
+        self.push(Step::Repeat(repeat))
+    }
+
+    /// Ends the loop for a traverser as soon as `condition` yields anything
+    /// for it. Called before `repeat`, the condition is checked before each
+    /// iteration, otherwise after.
+    pub fn until(self, condition: impl FnOnce(TypePath<'graph>) -> TypePath<'graph>) -> Self {
+        let steps = condition(self.anonymous()).steps;
+        self.modify_repeat(|repeat, before| {
+            repeat.until = Some(steps);
+            repeat.check_first = before;
+        })
+    }
+
+    /// Runs the loop body exactly `n` times.
+    pub fn times(self, n: usize) -> Self {
+        self.modify_repeat(|repeat, _| repeat.times = Some(n))
+    }
+
+    /// Also emits the traversers produced by every iteration of the loop.
+    pub fn emit(self) -> Self {
+        self.modify_repeat(|repeat, _| repeat.emit = true)
+    }
+
+    fn modify_repeat(mut self, modify: impl FnOnce(&mut Repeat, bool)) -> Self {
+        match self.steps.last_mut() {
+            Some(Step::Repeat(repeat)) if self.pending.is_none() => modify(repeat, false),
+            _ => modify(self.pending.get_or_insert_with(Repeat::default), true),
+        }
+        self
+    }
+
+    ////////////////////////////////////////////////////////////////////////////
+    // Terminal steps
+
+    /// The nodes the traversal ends on.
+    pub fn to_list(self) -> Vec<NodeId> {
+        self.traversers().map(|traverser| traverser.node().clone()).collect()
+    }
+
+    /// The full path of every traverser, starting node included.
+    pub fn paths(self) -> Vec<Vec<NodeId>> {
+        self.traversers().map(|traverser| traverser.path).collect()
+    }
+
+    pub fn count(self) -> usize {
+        self.traversers().count()
+    }
+
+    /// The value of property `key` on every node the traversal ends on,
+    /// skipping nodes without it.
+    pub fn values(self, key: &str) -> Vec<PropertyValue> {
+        let (graph, t) = (self.graph, self.t);
+        self.traversers()
+            .filter_map(|traverser| graph.get_property(t, &ElementId::Node(traverser.node().clone()), key))
+            .collect()
+    }
+
+    /// How many traversers ended on each node.
+    pub fn group_count(self) -> HashMap<NodeId, usize> {
+        let mut counts = HashMap::new();
+        for traverser in self.traversers() {
+            *counts.entry(traverser.node().clone()).or_insert(0) += 1;
+        }
+        counts
+    }
+
+    /// How many traversers ended on a node with each value of property `key`,
+    /// in order of first appearance.
+    pub fn group_count_by(self, key: &str) -> Vec<(PropertyValue, usize)> {
+        let mut counts: Vec<(PropertyValue, usize)> = Vec::new();
+        for value in self.values(key) {
+            match counts.iter_mut().find(|(seen, _)| *seen == value) {
+                Some((_, count)) => *count += 1,
+                None => counts.push((value, 1)),
+            }
+        }
+        counts
+    }
+
+    /// Sum of the numeric values of property `key`.
+    pub fn sum(self, key: &str) -> f64 {
+        self.numbers(key).sum()
+    }
+
+    pub fn mean(self, key: &str) -> Option<f64> {
+        let numbers: Vec<f64> = self.numbers(key).collect();
+        if numbers.is_empty() {
+            None
+        } else {
+            Some(numbers.iter().sum::<f64>() / numbers.len() as f64)
+        }
+    }
+
+    pub fn min(self, key: &str) -> Option<f64> {
+        self.numbers(key).reduce(f64::min)
+    }
+
+    pub fn max(self, key: &str) -> Option<f64> {
+        self.numbers(key).reduce(f64::max)
+    }
+
+    fn numbers(self, key: &str) -> impl Iterator<Item = f64> {
+        self.values(key).into_iter().filter_map(|value| match value {
+            PropertyValue::Int(i) => Some(i as f64),
+            PropertyValue::Float(x) => Some(x),
+            _ => None,
+        })
+    }
+
+    /// The traversers not yet handed out by `next`.
+    fn traversers(self) -> Traversers<'graph> {
+        match self.running {
+            Some(running) => running,
+            None => self.evaluate(),
+        }
+    }
+
+    fn evaluate(&self) -> Traversers<'graph> {
+        let (graph, t) = (self.graph, self.t);
+        let start = self.start.clone().into_iter().filter_map(move |id| {
+            graph.visible_node(t, &id).map(|node| Traverser { path: vec![node.id().clone()] })
+        });
+        run(graph, t, &self.steps, Box::new(start))
+    }
+}
+
+impl Iterator for TypePath<'_> {
+    type Item = Node;
+
+    fn next(&mut self) -> Option<Node> {
+        if self.running.is_none() {
+            self.running = Some(self.evaluate());
+        }
+        let traverser = self.running.as_mut()?.next()?;
+        Some(Node { id: traverser.node().clone() })
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Evaluation
+fn run<'graph>(graph: &'graph Graph, t: &'graph TransactionId, steps: &[Step], input: Traversers<'graph>) -> Traversers<'graph> {
+    steps.iter().fold(input, |input, step| apply(graph, t, step.clone(), input))
+}
+
+fn apply<'graph>(graph: &'graph Graph, t: &'graph TransactionId, step: Step, input: Traversers<'graph>) -> Traversers<'graph> {
+    match step {
+        Step::Walk(direction, edge_type) => Box::new(input.flat_map(move |traverser| {
+            walk(graph, t, &traverser, direction, edge_type.as_deref())
+        })),
+        Step::Has(key, value) => Box::new(input.filter(move |traverser| {
+            graph.get_property(t, &ElementId::Node(traverser.node().clone()), &key).as_ref() == Some(&value)
+        })),
+        Step::HasNot(key) => Box::new(input.filter(move |traverser| {
+            graph.get_property(t, &ElementId::Node(traverser.node().clone()), &key).is_none()
+        })),
+        Step::HasLabel(label) => Box::new(input.filter(move |traverser| {
+            graph.get_labels(t, &Node { id: traverser.node().clone() }).contains(&label)
+        })),
+        Step::Dedup => {
+            let mut seen = HashSet::new();
+            Box::new(input.filter(move |traverser| seen.insert(traverser.node().clone())))
+        }
+        Step::SimplePath => Box::new(input.filter(|traverser| {
+            let mut seen = HashSet::new();
+            traverser.path.iter().all(|id| seen.insert(id))
+        })),
+        Step::Skip(n) => Box::new(input.skip(n)),
+        Step::Limit(n) => Box::new(input.take(n)),
+        Step::Repeat(repeat) => Box::new(input.flat_map(move |traverser| RepeatIter {
+            graph,
+            t,
+            repeat: repeat.clone(),
+            entered: HashSet::from([traverser.node().clone()]),
+            queue: VecDeque::from(vec![(traverser, 0)]),
+            ready: VecDeque::new(),
+        })),
+    }
+}
+
+fn walk(graph: &Graph, t: &TransactionId, traverser: &Traverser, direction: Direction, edge_type: Option<&str>) -> Vec<Traverser> {
+    let node = Node { id: traverser.node().clone() };
+    t.register_predicate(PredicateLock::new(node.id().clone(), edge_type.map(str::to_string), direction));
+    graph
+        .visible_edges(t, &node)
+        .into_iter()
+        .filter(|(_, edge)| edge_type.is_none_or(|edge_type| edge.edgetype() == edge_type))
+        .filter(|(_, edge)| match direction {
+            Direction::Out => edge.source() == node.id(),
+            Direction::In => edge.target() == node.id(),
+            Direction::Both => true,
+        })
+        .map(|(other, _)| {
+            let mut path = traverser.path.clone();
+            path.push(other.id().clone());
+            Traverser { path }
+        })
+        .collect()
+}
+
+/// Breadth-first evaluation of a `repeat` step for a single traverser.
+struct RepeatIter<'graph> {
+    graph: &'graph Graph,
+    t: &'graph TransactionId,
+    repeat: Repeat,
+    // Nodes the loop has stood on; an unbounded loop enters each only once
+    entered: HashSet<NodeId>,
+    queue: VecDeque<(Traverser, usize)>,
+    ready: VecDeque<Traverser>,
+}
+
+impl RepeatIter<'_> {
+    fn satisfies_until(&self, traverser: &Traverser) -> bool {
+        match &self.repeat.until {
+            Some(condition) => {
+                let input: Traversers = Box::new(iter::once(traverser.clone()));
+                run(self.graph, self.t, condition, input).next().is_some()
+            }
+            None => false,
+        }
+    }
+}
+
+impl Iterator for RepeatIter<'_> {
+    type Item = Traverser;
+
+    fn next(&mut self) -> Option<Traverser> {
+        loop {
+            if let Some(traverser) = self.ready.pop_front() {
+                return Some(traverser);
+            }
+            let (traverser, loops) = self.queue.pop_front()?;
+
+            if self.repeat.check_first && self.satisfies_until(&traverser) {
+                return Some(traverser);
+            }
+
+            let input: Traversers = Box::new(iter::once(traverser));
+            let children: Vec<Traverser> = run(self.graph, self.t, &self.repeat.body, input).collect();
+            for child in children {
+                let done = self.repeat.times == Some(loops + 1)
+                    || (!self.repeat.check_first && self.satisfies_until(&child));
+                if done {
+                    self.ready.push_back(child);
+                } else if self.repeat.times.is_some() || self.entered.insert(child.node().clone()) {
+                    if self.repeat.emit {
+                        self.ready.push_back(child.clone());
+                    }
+                    self.queue.push_back((child, loops + 1));
+                }
+            }
+        }
+    }
+}
+
+////////////////////////////////////////////////////////////////////////////////
+// Unit Tests
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    /// a -red-> b -red2-> c -red3-> d, plus b -blue-> e. The collision rule
+    /// allows one edge of a type per node, so each hop has its own type.
+    const LINE: [(usize, usize, &str); 4] = [(0, 1, "red"), (1, 2, "red2"), (2, 3, "red3"), (1, 4, "blue")];
+    const NAMES: [&str; 5] = ["a", "b", "c", "d", "e"];
+
+    #[test]
+    fn test_directional_steps() {
+        let (mut graph, nodes) = testing::graph(5, &LINE);
+        testing::name(&mut graph, &nodes, &NAMES);
+        let tx = graph.start_transaction();
+        let g = graph.traversal(&tx);
+        assert_eq!(g.v(nodes[0].id()).out("red").to_list(), vec![nodes[1].id().clone()]);
+        assert!(g.v(nodes[0].id()).in_("red").to_list().is_empty());
+        assert_eq!(g.v(nodes[1].id()).in_("red").to_list(), vec![nodes[0].id().clone()]);
+        assert_eq!(g.v(nodes[4].id()).both("blue").out_any().has("name", "c").count(), 1);
+    }
+
+    #[test]
+    fn test_repeat_until_and_paths() {
+        let (mut graph, nodes) = testing::graph(5, &LINE);
+        testing::name(&mut graph, &nodes, &NAMES);
+        let tx = graph.start_transaction();
+        let g = graph.traversal(&tx);
+
+        let paths = g.v(nodes[0].id()).repeat(|p| p.out_any()).until(|p| p.has("name", "d")).paths();
+        let expected: Vec<NodeId> = [0, 1, 2, 3].iter().map(|&i| nodes[i].id().clone()).collect();
+        assert_eq!(paths, vec![expected]);
+
+        let two_hops = g.v(nodes[0].id()).repeat(|p| p.out_any()).times(2).values("name");
+        assert_eq!(two_hops.len(), 2);
+        assert!(two_hops.contains(&"c".into()) && two_hops.contains(&"e".into()));
+
+        let everything = g.v(nodes[0].id()).repeat(|p| p.out_any()).emit().dedup().count();
+        assert_eq!(everything, 4);
+
+        // Checked before the first iteration, so the start node qualifies.
+        let immediate = g.v(nodes[0].id()).until(|p| p.has("name", "a")).repeat(|p| p.out_any()).to_list();
+        assert_eq!(immediate, vec![nodes[0].id().clone()]);
+    }
+
+    #[test]
+    fn test_unbounded_repeat_ends_on_cycles() {
+        let (mut graph, nodes) = testing::graph(3, &[(0, 1, "ab"), (1, 2, "bc"), (2, 0, "ca")]);
+        let tx = graph.start_transaction();
+        let g = graph.traversal(&tx);
+        assert_eq!(g.v(nodes[0].id()).repeat(|p| p.out_any()).emit().count(), 2);
+        assert_eq!(g.v(nodes[0].id()).repeat(|p| p.out_any()).until(|p| p.has("name", "z")).count(), 0);
+        // A bounded loop may come round again
+        assert_eq!(g.v(nodes[0].id()).repeat(|p| p.out_any()).times(3).to_list(), vec![nodes[0].id().clone()]);
+    }
+
+    #[test]
+    fn test_type_path_iterates_nodes() {
+        let (mut graph, nodes) = testing::graph(3, &[(0, 1, "red"), (1, 2, "blue")]);
+        let tx = graph.start_transaction();
+        let mut path = graph.traversal(&tx).v(nodes[0].id()).out("red").out("blue");
+        assert_eq!(path.next(), Some(nodes[2].clone()));
+        assert_eq!(path.next(), None);
+    }
+
+    #[test]
+    fn test_self_loops_are_walked_once() {
+        let (mut graph, nodes) = testing::graph(1, &[(0, 0, "loop")]);
+        let tx = graph.start_transaction();
+        let g = graph.traversal(&tx);
+        let a = vec![nodes[0].id().clone()];
+        assert_eq!(g.v(nodes[0].id()).out("loop").to_list(), a);
+        assert_eq!(g.v(nodes[0].id()).in_("loop").to_list(), a);
+        assert_eq!(g.v(nodes[0].id()).both("loop").to_list(), a);
+    }
+
+    #[test]
+    fn test_dedup_limit_and_aggregates() {
+        let (mut graph, nodes) = testing::graph(5, &LINE);
+        testing::name(&mut graph, &nodes, &NAMES);
+        let tx = graph.start_transaction();
+        let g = graph.traversal(&tx);
+        assert_eq!(g.v_all().both_any().count(), 8);
+        assert_eq!(g.v_all().both_any().dedup().count(), 5);
+        assert_eq!(g.v_all().both_any().dedup().limit(2).count(), 2);
+
+        let visits = g.v_all().both_any().group_count();
+        assert_eq!(visits[nodes[1].id()], 3);
+        assert_eq!(g.v_all().has_not("missing").group_count_by("name").len(), 5);
+    }
+
+    #[test]
+    fn test_traversal_reads_transaction_snapshot() {
+        let mut graph = Graph::new();
+        let mut t1 = graph.start_transaction();
+        let a = graph.add_node(&mut t1);
+        let b = graph.add_node(&mut t1);
+        graph.commit_transaction(&t1).unwrap();
+
+        let mut t2 = graph.start_transaction();
+        graph.add_edge(&mut t2, &a, &b, "red".to_string()).unwrap();
+        let t3 = graph.start_transaction();
+
+        assert_eq!(graph.traversal(&t2).v(a.id()).out("red").count(), 1);
+        assert_eq!(graph.traversal(&t3).v(a.id()).out("red").count(), 0);
+    }
+}